@@ -0,0 +1,184 @@
+//! Structs for encoding animated cursors (`.ani` files).
+
+extern crate ico;
+
+use crate::{ico::Key, AsSize, IconError, Image};
+use image::DynamicImage;
+use std::io::{self, Write};
+#[cfg(feature = "std-fs")]
+use std::{fs::File, path::Path};
+
+const ANIH_LENGTH: u32 = 36;
+const AF_ICON: u32 = 0x1;
+const DEFAULT_RATE: u32 = 10;
+
+/// An encoder for the `.ani` file format.
+///
+/// The output of `Ani::write` is a _RIFF_ `ACON` file embedding
+/// one `.cur` file per frame. Frames are displayed in the order
+/// in which they were added.
+#[derive(Clone, Debug)]
+pub struct Ani {
+    frames: Vec<Vec<u8>>,
+    rate: u32,
+}
+
+impl Ani {
+    /// Creates a new animated cursor with no frames.
+    ///
+    /// Frames are displayed for _10 jiffies_ (_1/6th_ of a second)
+    /// each, unless otherwise specified by `Ani::set_rate`.
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates a new animated cursor with space for
+    /// at least `capacity` frames.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            frames: Vec::with_capacity(capacity),
+            rate: DEFAULT_RATE,
+        }
+    }
+
+    /// Rasterizes `source` to the size of `key` and appends it as
+    /// a frame of the animation, with its _hotspot_ at `hotspot`.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::HotspotOutOfBounds(hotspot))` if
+    ///   `hotspot` lies outside of the bounds of the frame.
+    /// * Returns `Err(IconError::Resample(_))` if the resampling
+    ///   of `source` fails.
+    /// * Returns `Ok(())` otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// use iconwriter::{ani::Ani, ico::Key, resample, Image};
+    ///
+    /// fn main() -> io::Result<()> {
+    ///     let mut ani = Ani::new();
+    ///
+    ///     for path in &["frame0.svg", "frame1.svg"] {
+    ///         let frame = Image::open(path)?;
+    ///
+    ///         ani.add_frame(resample::linear, &frame, Key(32), (0, 0))
+    ///             .map_err(Into::<io::Error>::into)?;
+    ///     }
+    ///
+    ///     ani.set_rate(6);
+    ///     ani.save("./busy.ani")
+    /// }
+    /// ```
+    pub fn add_frame<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        filter: F,
+        source: &Image,
+        key: Key,
+        hotspot: (u16, u16),
+    ) -> Result<(), IconError<Key>> {
+        let (w, h) = key.as_dimensions();
+
+        if u32::from(hotspot.0) >= w || u32::from(hotspot.1) >= h {
+            return Err(IconError::HotspotOutOfBounds(hotspot));
+        }
+
+        let frame = source.rasterize_to(filter, (w, h))?;
+        let mut image = ico::IconImage::from_rgba_data(w, h, frame.to_rgba().into_vec());
+        image.set_cursor_hotspot(Some(hotspot));
+
+        let mut cursor = ico::IconDir::new(ico::ResourceType::Cursor);
+        cursor.add_entry(ico::IconDirEntry::encode_as_bmp(&image)?);
+
+        let mut data = Vec::new();
+        cursor.write(&mut data)?;
+        self.frames.push(data);
+
+        Ok(())
+    }
+
+    /// Returns the number of frames in the animation.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns `true` if the animation has no frames.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Returns the display rate of each frame, in _jiffies_
+    /// (_1/60th_ of a second).
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// Sets the display rate of each frame, in _jiffies_
+    /// (_1/60th_ of a second).
+    pub fn set_rate(&mut self, jiffies: u32) -> &mut Self {
+        self.rate = jiffies;
+        self
+    }
+
+    /// Writes the contents of the animated cursor to `w`.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let n_frames = self.frames.len() as u32;
+        let fram_len = 4 + self
+            .frames
+            .iter()
+            .map(|frame| 8 + padded(frame.len() as u32))
+            .sum::<u32>();
+        let riff_len = 4 + (8 + ANIH_LENGTH) + (8 + fram_len);
+
+        w.write_all(b"RIFF")?;
+        w.write_all(&riff_len.to_le_bytes())?;
+        w.write_all(b"ACON")?;
+
+        // The cursors dimensions are only relevant for raw bitmap frames,
+        // hence `cx`, `cy`, `cBitCount` and `cPlanes` are left zeroed.
+        w.write_all(b"anih")?;
+        w.write_all(&ANIH_LENGTH.to_le_bytes())?;
+        for field in &[ANIH_LENGTH, n_frames, n_frames, 0, 0, 0, 0, self.rate, AF_ICON] {
+            w.write_all(&field.to_le_bytes())?;
+        }
+
+        w.write_all(b"LIST")?;
+        w.write_all(&fram_len.to_le_bytes())?;
+        w.write_all(b"fram")?;
+
+        for frame in &self.frames {
+            w.write_all(b"icon")?;
+            w.write_all(&(frame.len() as u32).to_le_bytes())?;
+            w.write_all(frame)?;
+
+            // RIFF chunks are padded to an even length
+            if frame.len() % 2 == 1 {
+                w.write_all(&[0])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the contents of the animated cursor to disk.
+    ///
+    /// This method is only available with the `std-fs` feature.
+    #[cfg(feature = "std-fs")]
+    pub fn save<P: AsRef<Path>>(&self, path: &P) -> io::Result<()> {
+        let mut file = File::create(path.as_ref())?;
+        self.write(&mut file)?;
+        file.flush()
+    }
+}
+
+impl Default for Ani {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[inline]
+fn padded(len: u32) -> u32 {
+    len + (len & 1)
+}
@@ -0,0 +1,171 @@
+//! A thin command line interface for `iconwriter`.
+//!
+//! ```text
+//! iconwriter <OUTPUT> [--size 16,32,48] [--filter lanczos3] <INPUT>
+//! ```
+//!
+//! The encoder is chosen based on the extension of `OUTPUT`: `.ico` and
+//! `.icns` files are encoded as such, `.tar` files are encoded as _favicon_
+//! archives and any other path is treated as a _favicon_ directory.
+
+extern crate iconwriter;
+extern crate image;
+
+use iconwriter::{favicon::Favicon, icns::{self, Icns}, ico::{self, Ico}, resample, Icon, Image};
+use image::DynamicImage;
+use std::{
+    convert::TryFrom,
+    env,
+    ffi::OsStr,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    process,
+};
+
+type Filter = fn(&DynamicImage, u32) -> io::Result<DynamicImage>;
+
+const USAGE: &str = "usage: iconwriter <OUTPUT> [--size 16,32,48] [--filter lanczos3] <INPUT>";
+const USAGE_ERR: i32 = 2;
+const RUNTIME_ERR: i32 = 1;
+
+const ICO_SIZES: [u32; 7] = [16, 24, 32, 48, 64, 128, 256];
+const ICNS_SIZES: [u32; 7] = [16, 32, 64, 128, 256, 512, 1024];
+const FAVICON_SIZES: [u32; 5] = [16, 32, 180, 192, 512];
+
+/// The command line arguments.
+struct Args {
+    output: PathBuf,
+    input: PathBuf,
+    sizes: Option<Vec<u32>>,
+    filter: Filter,
+}
+
+/// The supported output formats.
+enum Format {
+    Ico,
+    Icns,
+    FaviconTar,
+    FaviconDir,
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("error: {}\n{}", msg, USAGE);
+            process::exit(USAGE_ERR);
+        }
+    };
+
+    if let Err(msg) = run(&args) {
+        eprintln!("error: {}", msg);
+        process::exit(RUNTIME_ERR);
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+    let mut paths = Vec::with_capacity(2);
+    let mut sizes = None;
+    let mut filter: Filter = resample::linear;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-s" | "--size" => {
+                let list = args.next().ok_or("missing value for `--size`")?;
+                sizes = Some(parse_sizes(&list)?);
+            }
+            "-f" | "--filter" => {
+                let name = args.next().ok_or("missing value for `--filter`")?;
+                filter = parse_filter(&name)?;
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.len() != 2 {
+        return Err(String::from("expected an output and an input path"));
+    }
+
+    let input = paths.pop().unwrap();
+    let output = paths.pop().unwrap();
+
+    Ok(Args { output, input, sizes, filter })
+}
+
+fn parse_sizes(list: &str) -> Result<Vec<u32>, String> {
+    list.split(',')
+        .map(|size| {
+            size.trim()
+                .parse()
+                .map_err(|_| format!("invalid size `{}`", size))
+        })
+        .collect()
+}
+
+fn parse_filter(name: &str) -> Result<Filter, String> {
+    resample::by_name(name).ok_or_else(|| format!("unknown filter `{}`", name))
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let source = Image::open(&args.input)
+        .map_err(|err| format!("could not open `{}`: {}", args.input.display(), err))?;
+
+    match format(&args.output) {
+        Format::Ico => {
+            let keys = keys(&args.sizes, &ICO_SIZES, ico::Key::try_from)?;
+            encode::<Ico>(args, &source, keys)
+        }
+        Format::Icns => {
+            let keys = keys(&args.sizes, &ICNS_SIZES, icns::Key::try_from)?;
+            encode::<Icns>(args, &source, keys)
+        }
+        Format::FaviconTar | Format::FaviconDir => {
+            let keys = keys(&args.sizes, &FAVICON_SIZES, iconwriter::favicon::Key::try_from)?;
+            encode::<Favicon>(args, &source, keys)
+        }
+    }
+}
+
+fn format(output: &Path) -> Format {
+    match output.extension().and_then(OsStr::to_str) {
+        Some(ext) if ext.eq_ignore_ascii_case("ico") => Format::Ico,
+        Some(ext) if ext.eq_ignore_ascii_case("icns") => Format::Icns,
+        Some(ext) if ext.eq_ignore_ascii_case("tar") => Format::FaviconTar,
+        _ => Format::FaviconDir,
+    }
+}
+
+/// Converts the requested sizes, or `default` if none were
+/// requested, to keys of the output format.
+fn keys<K, F: Fn(u32) -> io::Result<K>>(
+    sizes: &Option<Vec<u32>>,
+    default: &[u32],
+    to_key: F,
+) -> Result<Vec<K>, String> {
+    let sizes = sizes.as_ref().map(Vec::as_slice).unwrap_or(default);
+
+    sizes
+        .iter()
+        .map(|&size| to_key(size).map_err(|_| format!("unsupported size `{}`", size)))
+        .collect()
+}
+
+fn encode<I: Icon>(args: &Args, source: &Image, keys: Vec<I::Key>) -> Result<(), String> {
+    let mut icon = I::with_capacity(keys.len());
+
+    icon.add_entries(args.filter, source, keys)
+        .map_err(|err| err.to_string())?;
+
+    let result = match format(&args.output) {
+        Format::FaviconTar => File::create(&args.output).and_then(|mut file| icon.write(&mut file)),
+        _ => icon.save(&args.output),
+    };
+
+    result.map_err(|err| format!("could not save `{}`: {}", args.output.display(), err))
+}
@@ -0,0 +1,101 @@
+//! Structs for generating the icons of a cross-platform application at once.
+//!
+//! This module is only available with the `std-fs` feature.
+
+use crate::{cache::CachedSource, favicon::{self, Favicon}, icns::{self, Icns}, ico::{self, Ico}, Icon, Image};
+use image::DynamicImage;
+use std::{
+    convert::TryFrom,
+    fs::DirBuilder,
+    io,
+    path::Path,
+};
+
+/// A set of icons sharing the same source image, sizes and
+/// resampling filter.
+///
+/// `Bundle::write_all` outputs an `icon.ico` file, an `icon.icns`
+/// file and a `favicon` directory, rasterizing the source image only
+/// once per size.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// use iconwriter::{bundle::Bundle, resample, Image};
+///
+/// fn main() -> io::Result<()> {
+///     let mut bundle = Bundle {
+///         source: Image::open("logo.svg")?,
+///         sizes: vec![16, 32, 48, 64, 128, 256, 512],
+///         filter: resample::linear,
+///     };
+///
+///     bundle.write_all(&"./icons/")
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Bundle<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>> {
+    /// The source image of the icons.
+    pub source: Image,
+    /// The sizes of the entries of the icons. Sizes that are
+    /// not supported by a given format are skipped for that format.
+    pub sizes: Vec<u32>,
+    /// The resampling filter used to rasterize `source`.
+    pub filter: F,
+}
+
+impl<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>> Bundle<F> {
+    /// Writes `icon.ico`, `icon.icns` and the `favicon` directory to
+    /// `out_dir`, creating it if needed.
+    ///
+    /// Each size is rasterized once and shared between the formats
+    /// supporting it. A format supporting none of the sizes is still
+    /// written, with no entries.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if
+    ///   `self.sizes` contains duplicates.
+    /// * Returns `Err(_)` if the rasterization of `self.source` or the
+    ///   writing of any of the files fails.
+    /// * Otherwise returns `Ok(())`.
+    pub fn write_all<P: AsRef<Path>>(&mut self, out_dir: &P) -> io::Result<()> {
+        let out_dir = out_dir.as_ref();
+        let cache = CachedSource::with_capacity(self.source.clone(), self.sizes.len());
+
+        DirBuilder::new().recursive(true).create(out_dir)?;
+
+        let mut ico = Ico::new();
+        add_entries(&mut ico, &cache, &mut self.filter, keys::<ico::Key>(&self.sizes))?;
+        ico.save(&out_dir.join("icon.ico"))?;
+
+        let mut icns = Icns::new();
+        add_entries(&mut icns, &cache, &mut self.filter, keys::<icns::Key>(&self.sizes))?;
+        icns.save(&out_dir.join("icon.icns"))?;
+
+        let mut favicon = Favicon::new();
+        add_entries(&mut favicon, &cache, &mut self.filter, keys::<favicon::Key>(&self.sizes))?;
+        favicon.save(&out_dir.join("favicon"))
+    }
+}
+
+/// Returns the keys of type `K` associated with `sizes`,
+/// skipping the unsupported ones.
+fn keys<K: TryFrom<u32>>(sizes: &[u32]) -> Vec<K> {
+    sizes.iter().filter_map(|&size| K::try_from(size).ok()).collect()
+}
+
+fn add_entries<I: Icon, F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+    icon: &mut I,
+    cache: &CachedSource,
+    filter: &mut F,
+    keys: Vec<I::Key>,
+) -> io::Result<()> {
+    for key in keys {
+        cache
+            .add_entry(icon, &mut *filter, key)
+            .map_err(Into::<io::Error>::into)?;
+    }
+
+    Ok(())
+}
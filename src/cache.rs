@@ -0,0 +1,264 @@
+//! A cache for rasterized images.
+
+use crate::{add_rasterized, AsSize, Icon, IconError, Image, ResReResampleError};
+use image::DynamicImage;
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    sync::{Mutex, MutexGuard},
+};
+
+const DEFAULT_CAPACITY: usize = 16;
+
+/// A memoization table for rasterized images.
+///
+/// Since resampling filters can't be compared nor hashed, each
+/// filter is identified by a user-provided _filter id_. Entries
+/// are labeled by their _filter id_ and their dimensions.
+///
+/// Note that a `RasterCache` does not keep track of the source image
+/// its entries were generated from. Therefore, a single `RasterCache`
+/// should only ever be used along with a single source image.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// use iconwriter::{cache::RasterCache, Ico, Icns, Image, Icon, IconError};
+///
+/// fn example() -> Result<(), IconError> {
+///     let mut cache = RasterCache::new();
+///     let src = Image::open("image.svg")?;
+///
+///     let mut ico = Ico::new();
+///     ico.add_entry_cached(resample::linear, "linear", &src, 32, &mut cache)?;
+///
+///     // The 32x32 image is not rasterized a second time
+///     let mut icns = Icns::new();
+///     icns.add_entry_cached(resample::linear, "linear", &src, Rgba32, &mut cache)
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct RasterCache {
+    entries: HashMap<String, HashMap<(u32, u32), DynamicImage>>,
+}
+
+impl RasterCache {
+    /// Creates a new, empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of images contained in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.values().map(HashMap::len).sum()
+    }
+
+    /// Returns `true` if the cache contains no images.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the cache contains an image of
+    /// dimensions `(width, height)` produced by the filter
+    /// identified by `filter_id`.
+    pub fn contains(&self, filter_id: &str, dimensions: (u32, u32)) -> bool {
+        match self.entries.get(filter_id) {
+            Some(images) => images.contains_key(&dimensions),
+            None => false,
+        }
+    }
+
+    /// Removes all images from the cache.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Rasterizes `source` to an image of dimensions `(width, height)`,
+    /// or retrieves it from the cache if it was previously rasterized
+    /// by the filter identified by `filter_id`.
+    pub fn rasterize<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        filter: F,
+        filter_id: &str,
+        source: &Image,
+        dimensions: (u32, u32),
+    ) -> Result<DynamicImage, ResReResampleError> {
+        if let Some(icon) = self.entries.get(filter_id).and_then(|images| images.get(&dimensions)) {
+            return Ok(icon.clone());
+        }
+
+        let icon = source.rasterize_to(filter, dimensions)?;
+
+        self.entries
+            .entry(filter_id.to_owned())
+            .or_default()
+            .insert(dimensions, icon.clone());
+
+        Ok(icon)
+    }
+}
+
+/// A source image which memoizes its rasterizations, so that rendering
+/// it at the same size for several icons only happens once.
+///
+/// At most `capacity` images are kept, the least recently used
+/// ones being evicted first. Images are identified by their
+/// dimensions only, hence, as with a `RasterCache`, a single
+/// `CachedSource` should only ever be used with a single resampling
+/// filter. Since _SVG_ sources are rasterized without resampling, this
+/// restriction does not apply to them.
+///
+/// # Thread Safety
+///
+/// `CachedSource` is `Sync`, so it can be shared between threads.
+/// The cache is locked only while it is being looked up or updated,
+/// so concurrent rasterizations are not serialized, but two threads
+/// rasterizing the same size at the same time may both miss the cache.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// use iconwriter::{cache::CachedSource, Ico, Icns, Image, Icon, IconError};
+///
+/// fn example() -> Result<(), IconError> {
+///     let src = CachedSource::new(Image::open("image.svg")?);
+///
+///     let mut ico = Ico::new();
+///     src.add_entry(&mut ico, resample::linear, ico::Key(32))?;
+///
+///     // The 32x32 image is not rendered a second time
+///     let mut icns = Icns::new();
+///     src.add_entry(&mut icns, resample::linear, icns::Key::Rgba32)
+/// }
+/// ```
+pub struct CachedSource {
+    source: Image,
+    capacity: usize,
+    entries: Mutex<VecDeque<((u32, u32), DynamicImage)>>,
+}
+
+impl CachedSource {
+    /// Wraps `source` in a cache holding up to 16 images.
+    pub fn new(source: Image) -> Self {
+        Self::with_capacity(source, DEFAULT_CAPACITY)
+    }
+
+    /// Wraps `source` in a cache holding up to `capacity` images.
+    pub fn with_capacity(source: Image, capacity: usize) -> Self {
+        CachedSource {
+            source,
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Returns a reference to the underlying source image.
+    pub fn source(&self) -> &Image {
+        &self.source
+    }
+
+    /// Returns the number of images contained in the cache.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if the cache contains no images.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all images from the cache.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Rasterizes the source image to a `DynamicImage`, or retrieves
+    /// it from the cache if it was previously rasterized at `size`.
+    ///
+    /// See `Image::rasterize` for details.
+    pub fn rasterize<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &self,
+        filter: F,
+        size: u32,
+    ) -> Result<DynamicImage, ResReResampleError> {
+        self.rasterize_to(filter, (size, size))
+    }
+
+    /// Rasterizes the source image to a `DynamicImage` of dimensions
+    /// `width`x`height`, or retrieves it from the cache if it was
+    /// previously rasterized at those dimensions.
+    ///
+    /// See `Image::rasterize_to` for details.
+    pub fn rasterize_to<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &self,
+        filter: F,
+        dimensions: (u32, u32),
+    ) -> Result<DynamicImage, ResReResampleError> {
+        if let Some(icon) = self.get(dimensions) {
+            return Ok(icon);
+        }
+
+        // The lock is not held while rasterizing
+        let icon = self.source.rasterize_to(filter, dimensions)?;
+        self.insert(dimensions, icon.clone());
+
+        Ok(icon)
+    }
+
+    /// Adds an entry to `icon`, rasterizing the source image
+    /// only if it is not yet cached at the dimensions of `key`.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::AlreadyIncluded(_))` if `icon` already contains
+    ///   an entry associated with `key`.
+    /// * Returns `Err(IconError::Resample(_))` if the resampling filter provided in
+    ///   the `filter` argument fails produces results of dimensions other than the
+    ///   ones specified by `key`.
+    /// * Otherwise returns `Ok(())`.
+    pub fn add_entry<I: Icon, F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &self,
+        icon: &mut I,
+        filter: F,
+        key: I::Key,
+    ) -> Result<(), IconError<I::Key>> {
+        let image = self.rasterize_to(filter, key.as_dimensions())?;
+        add_rasterized(icon, image, key)
+    }
+
+    /// Looks up the image of the specified dimensions, marking
+    /// it as the most recently used one.
+    fn get(&self, dimensions: (u32, u32)) -> Option<DynamicImage> {
+        let mut entries = self.lock();
+        let index = entries.iter().position(|(dims, _)| *dims == dimensions)?;
+        let entry = entries.remove(index)?;
+        let icon = entry.1.clone();
+
+        entries.push_back(entry);
+        Some(icon)
+    }
+
+    /// Inserts an image, evicting the least recently used
+    /// images if the cache is full.
+    fn insert(&self, dimensions: (u32, u32), icon: DynamicImage) {
+        let mut entries = self.lock();
+        entries.retain(|(dims, _)| *dims != dimensions);
+        entries.push_back((dimensions, icon));
+
+        while entries.len() > self.capacity {
+            entries.pop_front();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<((u32, u32), DynamicImage)>> {
+        // The cache is never left in an inconsistent state, so a
+        // poisoned lock can be safely recovered
+        self.entries.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl From<Image> for CachedSource {
+    fn from(source: Image) -> Self {
+        CachedSource::new(source)
+    }
+}
@@ -0,0 +1,52 @@
+//! Functions for converting icons between _file formats_.
+
+use crate::{add_rasterized, icns::{self, Icns}, ico::Ico, AsSize, Icon};
+use std::{
+    convert::TryFrom,
+    io::{self, Read, Write},
+};
+
+/// Converts the `.ico` file read from `r` to a `.icns` file written to `w`.
+///
+/// Entries are copied without being resampled, hence entries of
+/// dimensions not supported by the `.icns` file format, such as
+/// _48x48_ entries, are skipped. If the input contains several
+/// entries of the same size, the one with the most bits per pixel
+/// is used, as described by `Ico::from_reader`.
+///
+/// # Return Value
+///
+/// * Returns `Err(io::Error::from(io::ErrorKind::InvalidData))` if the
+///   input is not a valid `.ico` file.
+/// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if the
+///   input contains entries of dimensions not supported by `Ico`.
+/// * Otherwise returns the result of writing the `.icns` file.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let input = File::open("icon.ico")?;
+/// let output = BufWriter::new(File::create("icon.icns")?);
+///
+/// convert::ico_to_icns(input, output)?;
+/// ```
+pub fn ico_to_icns<R: Read, W: Write>(r: R, mut w: W) -> io::Result<()> {
+    let ico = Ico::from_reader(r)?;
+    let mut icns = Icns::with_capacity(ico.len());
+
+    for key in &ico {
+        if let Ok(icns_key) = icns::Key::try_from(key.as_size()) {
+            if icns.contains(icns_key) {
+                continue;
+            }
+
+            let image = ico
+                .get_entry(key)
+                .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?;
+
+            add_rasterized(&mut icns, image, icns_key).map_err(Into::<io::Error>::into)?;
+        }
+    }
+
+    icns.write(&mut w)
+}
@@ -0,0 +1,103 @@
+//! Utilities for comparing the entries of two icons.
+
+use crate::{AsSize, Icon};
+use image::{DynamicImage, GenericImageView};
+use std::collections::BTreeMap;
+
+/// The result of the comparison of the entries of
+/// two icons of the same dimensions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SizeDiff {
+    /// The dimensions of the compared entries.
+    pub dimensions: (u32, u32),
+    /// The outcome of the comparison.
+    pub status: Status,
+}
+
+/// The outcome of the comparison of two entries.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Status {
+    /// Only the first icon contains an entry of these dimensions.
+    OnlyInA,
+    /// Only the second icon contains an entry of these dimensions.
+    OnlyInB,
+    /// Both icons contain an entry of these dimensions, whose
+    /// contents differ by the specified number of pixels.
+    Changed(usize),
+    /// Both icons contain an entry of these dimensions,
+    /// with identical contents.
+    Same,
+}
+
+/// Compares the entries of `a` and `b`, which may be icons of different
+/// formats, by decoding them and comparing their pixels.
+///
+/// Entries are matched by their dimensions, and the results are sorted by
+/// dimensions. Icons containing several entries of the same dimensions, such
+/// as `Icns`, have them matched in the order of `Icon::keys`. Entries which
+/// cannot be decoded by `Icon::get_entry` are treated as missing.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let committed = Ico::from_reader(File::open("icon.ico")?)?;
+/// let changes: Vec<_> = diff::compare(&committed, &regenerated)
+///     .into_iter()
+///     .filter(|diff| diff.status != Status::Same)
+///     .collect();
+///
+/// assert!(changes.is_empty(), "the icon is out of date: {:?}", changes);
+/// ```
+pub fn compare<A: Icon, B: Icon>(a: &A, b: &B) -> Vec<SizeDiff> {
+    let mut entries_a = entries(a);
+    let mut entries_b = entries(b);
+    let mut dimensions: Vec<(u32, u32)> = entries_a.keys().chain(entries_b.keys()).cloned().collect();
+
+    dimensions.sort();
+    dimensions.dedup();
+
+    let mut diffs = Vec::new();
+
+    for dims in dimensions {
+        let images_a = entries_a.remove(&dims).unwrap_or_default();
+        let images_b = entries_b.remove(&dims).unwrap_or_default();
+
+        for i in 0..images_a.len().max(images_b.len()) {
+            let status = match (images_a.get(i), images_b.get(i)) {
+                (Some(image_a), Some(image_b)) => match changed_pixels(image_a, image_b) {
+                    0 => Status::Same,
+                    n => Status::Changed(n),
+                },
+                (Some(_), None) => Status::OnlyInA,
+                _ => Status::OnlyInB,
+            };
+
+            diffs.push(SizeDiff { dimensions: dims, status });
+        }
+    }
+
+    diffs
+}
+
+/// Decodes the entries of `icon`, grouped by dimensions.
+fn entries<I: Icon>(icon: &I) -> BTreeMap<(u32, u32), Vec<DynamicImage>> {
+    let mut entries: BTreeMap<(u32, u32), Vec<DynamicImage>> = BTreeMap::new();
+
+    for key in icon.keys() {
+        let dims = key.as_dimensions();
+
+        if let Some(image) = icon.get_entry(key) {
+            entries.entry(dims).or_default().push(image);
+        }
+    }
+
+    entries
+}
+
+/// Counts the pixels of `a` and `b` whose _RGBA_ values differ.
+fn changed_pixels(a: &DynamicImage, b: &DynamicImage) -> usize {
+    a.pixels()
+        .zip(b.pixels())
+        .filter(|((_, _, pa), (_, _, pb))| pa != pb)
+        .count()
+}
@@ -0,0 +1,730 @@
+//! Embedding of icons into _Windows_ executables.
+//!
+//! This module is only available with the `pe` feature.
+
+use crate::ico::Ico;
+use std::{cmp::Ordering, collections::HashSet, convert::TryFrom, io};
+#[cfg(feature = "std-fs")]
+use std::{fs, path::Path};
+
+const RT_ICON: u16 = 3;
+const RT_GROUP_ICON: u16 = 14;
+const LANG_NEUTRAL: u16 = 0;
+const DIRECTORY_RESOURCE: usize = 2;
+const DIRECTORY_SECURITY: usize = 4;
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+const COFF_HEADER_LENGTH: usize = 20;
+const SECTION_HEADER_LENGTH: usize = 40;
+const RSRC_NAME: &[u8; 8] = b".rsrc\0\0\0";
+const RSRC_CHARACTERISTICS: u32 = 0x4000_0040;
+const MAX_DEPTH: usize = 8;
+const MAX_ENTRIES: usize = 1 << 16;
+const HIGH_BIT: u32 = 0x8000_0000;
+
+/// Sets the icon of the executable at `exe_path` to `icon`.
+///
+/// See `embed::set_icon` for details. This function is only
+/// available with the `std-fs` feature.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// use iconwriter::{embed, ico::{Ico, Key}, resample, Icon, Image};
+///
+/// fn main() -> io::Result<()> {
+///     let mut icon = Ico::new();
+///     let src = Image::open("image.svg")?;
+///
+///     icon.add_entries(resample::linear, &src, vec![Key(16), Key(32), Key(48), Key(0)])
+///         .map_err(Into::<io::Error>::into)?;
+///
+///     embed::set_exe_icon(&"./target/release/app.exe", &icon)
+/// }
+/// ```
+#[cfg(feature = "std-fs")]
+pub fn set_exe_icon<P: AsRef<Path>>(exe_path: &P, icon: &Ico) -> io::Result<()> {
+    let exe = fs::read(exe_path.as_ref())?;
+    fs::write(exe_path.as_ref(), set_icon(&exe, icon)?)
+}
+
+/// Returns a copy of the _PE_ image `exe` (either _PE32_ or _PE32+_)
+/// whose icon is set to `icon`.
+///
+/// The first icon group of the executable, which _Windows_ uses as its
+/// icon, is replaced by the entries of `icon`, along with the images it
+/// referenced. Every other resource is preserved. An icon group is
+/// created if the executable has none.
+///
+/// The resource section is rewritten in place if it is the last section
+/// of the image and appended as a new section otherwise. Any _Authenticode_
+/// signature is invalidated by the change and is therefore unregistered.
+///
+/// # Return Value
+///
+/// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if
+///   `icon` has no entries.
+/// * Returns `Err(io::Error::from(io::ErrorKind::InvalidData))` if
+///   `exe` is not a valid _PE_ image.
+/// * Returns `Err(io::Error::from(io::ErrorKind::Other))` if there is
+///   no room for an additional section header in `exe`.
+/// * Otherwise returns `Ok(exe)`.
+pub fn set_icon(exe: &[u8], icon: &Ico) -> io::Result<Vec<u8>> {
+    let pe = Pe::parse(exe)?;
+    let mut resources = pe.resources()?;
+
+    replace_icon(&mut resources, icon)?;
+    pe.with_resources(&resources)
+}
+
+/// A node of the resource tree of a _PE_ image.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Directory {
+    characteristics: u32,
+    time_date_stamp: u32,
+    major_version: u16,
+    minor_version: u16,
+    entries: Vec<(Name, Node)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Name {
+    Str(Vec<u16>),
+    Id(u16),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Node {
+    Dir(Directory),
+    Data(Vec<u8>, u32),
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Section {
+    header: usize,
+    virtual_size: u32,
+    virtual_address: u32,
+    raw_size: u32,
+    raw_pointer: u32,
+}
+
+/// A parsed view of the headers of a _PE_ image.
+struct Pe<'a> {
+    data: &'a [u8],
+    optional_header: usize,
+    data_directories: usize,
+    n_data_directories: usize,
+    section_table: usize,
+    sections: Vec<Section>,
+}
+
+impl Directory {
+    /// Returns the data of the first resource of type `type_id` and
+    /// name `name_id`, regardless of its language.
+    #[cfg(test)]
+    pub(crate) fn data(&self, type_id: u16, name_id: u16) -> Option<&[u8]> {
+        let names = self.dir(&Name::Id(type_id))?;
+
+        match names.dir(&Name::Id(name_id))?.entries.first() {
+            Some((_, Node::Data(data, _))) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Returns the ids of the resources of type `type_id`.
+    #[cfg(test)]
+    pub(crate) fn ids(&self, type_id: u16) -> Vec<u16> {
+        self.dir(&Name::Id(type_id))
+            .map(|names| {
+                names
+                    .entries
+                    .iter()
+                    .filter_map(|(name, _)| match name {
+                        Name::Id(id) => Some(*id),
+                        Name::Str(_) => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn dir(&self, name: &Name) -> Option<&Directory> {
+        self.entries.iter().find_map(|(n, node)| match node {
+            Node::Dir(dir) if n == name => Some(dir),
+            _ => None,
+        })
+    }
+
+    fn dir_mut(&mut self, name: Name) -> &mut Directory {
+        let index = match self.entries.iter().position(|(n, _)| *n == name) {
+            Some(index) => index,
+            None => {
+                self.entries.push((name, Node::Dir(Directory::default())));
+                self.entries.len() - 1
+            }
+        };
+
+        if let Node::Data(..) = self.entries[index].1 {
+            self.entries[index].1 = Node::Dir(Directory::default());
+        }
+
+        match &mut self.entries[index].1 {
+            Node::Dir(dir) => dir,
+            Node::Data(..) => unreachable!(),
+        }
+    }
+
+    /// Sorts the entries of the tree in the order mandated by the
+    /// _PE_ format: named entries first, followed by ids.
+    fn sort(&mut self) {
+        self.entries.sort_by(|(a, _), (b, _)| compare_names(a, b));
+
+        for (_, node) in &mut self.entries {
+            if let Node::Dir(dir) = node {
+                dir.sort();
+            }
+        }
+    }
+
+    /// Reads the directory at `offset` from the root of the tree, which
+    /// is mapped at `root`. At most `budget` entries are read, so that
+    /// malformed trees referencing the same directories over and over
+    /// are rejected.
+    fn read(pe: &Pe, root: u32, offset: u32, depth: usize, budget: &mut usize) -> io::Result<Self> {
+        let header = pe.slice(root.wrapping_add(offset), 16)?;
+        let n_entries = usize::from(u16_at(header, 12)) + usize::from(u16_at(header, 14));
+
+        if depth > MAX_DEPTH || n_entries > *budget {
+            return Err(invalid_data());
+        }
+
+        *budget -= n_entries;
+        let table = pe.slice(root.wrapping_add(offset).wrapping_add(16), 8 * n_entries)?;
+        let mut entries = Vec::with_capacity(n_entries);
+
+        for entry in table.chunks(8) {
+            let name = u32_at(entry, 0);
+            let name = if name & HIGH_BIT != 0 {
+                let name_offset = root.wrapping_add(name & !HIGH_BIT);
+                let len = usize::from(u16_at(pe.slice(name_offset, 2)?, 0));
+                let chars = pe.slice(name_offset.wrapping_add(2), 2 * len)?;
+
+                Name::Str((0..len).map(|i| u16_at(chars, 2 * i)).collect())
+            } else {
+                Name::Id(name as u16)
+            };
+
+            let target = u32_at(entry, 4);
+            let node = if target & HIGH_BIT != 0 {
+                Node::Dir(Directory::read(pe, root, target & !HIGH_BIT, depth + 1, budget)?)
+            } else {
+                let data_entry = pe.slice(root.wrapping_add(target), 16)?;
+                let data = pe.slice(u32_at(data_entry, 0), u32_at(data_entry, 4) as usize)?;
+
+                Node::Data(data.to_vec(), u32_at(data_entry, 8))
+            };
+
+            entries.push((name, node));
+        }
+
+        Ok(Self {
+            characteristics: u32_at(header, 0),
+            time_date_stamp: u32_at(header, 4),
+            major_version: u16_at(header, 8),
+            minor_version: u16_at(header, 10),
+            entries,
+        })
+    }
+
+    /// Encodes the tree as the contents of a resource section
+    /// mapped at `rva`.
+    ///
+    /// The directory tables are laid out first, in breadth-first
+    /// order, followed by the names, the data entries and finally
+    /// the data itself.
+    fn write(&self, rva: u32) -> io::Result<Vec<u8>> {
+        let mut dirs = vec![self];
+        let mut i = 0;
+
+        while i < dirs.len() {
+            for (_, node) in &dirs[i].entries {
+                if let Node::Dir(dir) = node {
+                    dirs.push(dir);
+                }
+            }
+            i += 1;
+        }
+
+        let entries = || dirs.iter().flat_map(|dir| dir.entries.iter());
+        let dirs_len: usize = dirs.iter().map(|dir| 16 + 8 * dir.entries.len()).sum();
+        let names_len: usize = entries()
+            .map(|(name, _)| match name {
+                Name::Str(chars) => 2 + 2 * chars.len(),
+                Name::Id(_) => 0,
+            })
+            .sum();
+        let n_data = entries().filter(|(_, node)| matches_data(node)).count();
+        let data_entries_start = align(dirs_len + names_len, 4);
+        let data_start = align(data_entries_start + 16 * n_data, 8);
+
+        let mut buf = vec![0; data_start];
+        let (mut dir_offset, mut next_dir) = (0, 16 + 8 * self.entries.len());
+        let (mut name_offset, mut data_entry_offset) = (dirs_len, data_entries_start);
+
+        for dir in &dirs {
+            let n_named = dir.entries.iter().filter(|(name, _)| name.is_str()).count();
+
+            put_u32(&mut buf, dir_offset, dir.characteristics);
+            put_u32(&mut buf, dir_offset + 4, dir.time_date_stamp);
+            put_u16(&mut buf, dir_offset + 8, dir.major_version);
+            put_u16(&mut buf, dir_offset + 10, dir.minor_version);
+            put_u16(&mut buf, dir_offset + 12, n_named as u16);
+            put_u16(&mut buf, dir_offset + 14, (dir.entries.len() - n_named) as u16);
+
+            for (n, (name, node)) in dir.entries.iter().enumerate() {
+                let entry = dir_offset + 16 + 8 * n;
+
+                match name {
+                    Name::Str(chars) => {
+                        put_u32(&mut buf, entry, HIGH_BIT | name_offset as u32);
+                        put_u16(&mut buf, name_offset, chars.len() as u16);
+                        for (i, &c) in chars.iter().enumerate() {
+                            put_u16(&mut buf, name_offset + 2 + 2 * i, c);
+                        }
+                        name_offset += 2 + 2 * chars.len();
+                    }
+                    Name::Id(id) => put_u32(&mut buf, entry, u32::from(*id)),
+                }
+
+                match node {
+                    Node::Dir(child) => {
+                        put_u32(&mut buf, entry + 4, HIGH_BIT | next_dir as u32);
+                        next_dir += 16 + 8 * child.entries.len();
+                    }
+                    Node::Data(data, codepage) => {
+                        let data_rva = add_offset(rva, buf.len())?;
+
+                        put_u32(&mut buf, entry + 4, data_entry_offset as u32);
+                        put_u32(&mut buf, data_entry_offset, data_rva);
+                        put_u32(&mut buf, data_entry_offset + 4, data.len() as u32);
+                        put_u32(&mut buf, data_entry_offset + 8, *codepage);
+                        data_entry_offset += 16;
+
+                        buf.extend_from_slice(data);
+                        buf.resize(align(buf.len(), 8), 0);
+                    }
+                }
+            }
+
+            dir_offset += 16 + 8 * dir.entries.len();
+        }
+
+        Ok(buf)
+    }
+}
+
+impl Name {
+    fn is_str(&self) -> bool {
+        match self {
+            Name::Str(_) => true,
+            Name::Id(_) => false,
+        }
+    }
+}
+
+impl Section {
+    fn raw_end(&self) -> usize {
+        self.raw_pointer as usize + self.raw_size as usize
+    }
+}
+
+impl<'a> Pe<'a> {
+    fn parse(data: &'a [u8]) -> io::Result<Self> {
+        if data.get(0..2) != Some(b"MZ") {
+            return Err(invalid_data());
+        }
+
+        let pe_header = u32_at(slice(data, 0x3c, 4)?, 0) as usize;
+        if slice(data, pe_header, 4)? != b"PE\0\0" {
+            return Err(invalid_data());
+        }
+
+        let coff = slice(data, pe_header + 4, COFF_HEADER_LENGTH)?;
+        let n_sections = usize::from(u16_at(coff, 2));
+        let optional_header_len = usize::from(u16_at(coff, 16));
+        let optional_header = pe_header + 4 + COFF_HEADER_LENGTH;
+
+        let (data_directories, n_data_directories_offset) =
+            match u16_at(slice(data, optional_header, 2)?, 0) {
+                PE32_MAGIC => (96, 92),
+                PE32_PLUS_MAGIC => (112, 108),
+                _ => return Err(invalid_data()),
+            };
+
+        let n_data_directories = u32_at(
+            slice(data, optional_header + n_data_directories_offset, 4)?,
+            0,
+        ) as usize;
+
+        if n_data_directories <= DIRECTORY_RESOURCE
+            || data_directories + 8 * n_data_directories > optional_header_len
+        {
+            return Err(invalid_data());
+        }
+
+        let section_table = optional_header + optional_header_len;
+        let table = slice(data, section_table, SECTION_HEADER_LENGTH * n_sections)?;
+        let sections = table
+            .chunks(SECTION_HEADER_LENGTH)
+            .enumerate()
+            .map(|(i, header)| Section {
+                header: section_table + SECTION_HEADER_LENGTH * i,
+                virtual_size: u32_at(header, 8),
+                virtual_address: u32_at(header, 12),
+                raw_size: u32_at(header, 16),
+                raw_pointer: u32_at(header, 20),
+            })
+            .collect();
+
+        Ok(Self {
+            data,
+            optional_header,
+            data_directories: optional_header + data_directories,
+            n_data_directories,
+            section_table,
+            sections,
+        })
+    }
+
+    fn header_u32(&self, offset: usize) -> u32 {
+        u32_at(self.data, self.optional_header + offset)
+    }
+
+    fn data_directory(&self, index: usize) -> (u32, u32) {
+        let offset = self.data_directories + 8 * index;
+        (u32_at(self.data, offset), u32_at(self.data, offset + 4))
+    }
+
+    /// Returns the `len` bytes of the image mapped at `rva`.
+    fn slice(&self, rva: u32, len: usize) -> io::Result<&'a [u8]> {
+        let section = self
+            .sections
+            .iter()
+            .find(|s| {
+                rva >= s.virtual_address
+                    && u64::from(rva) + len as u64
+                        <= u64::from(s.virtual_address) + u64::from(s.raw_size)
+            })
+            .ok_or_else(invalid_data)?;
+
+        let offset = add_offset(section.raw_pointer, (rva - section.virtual_address) as usize)?;
+        slice(self.data, offset as usize, len)
+    }
+
+    fn resources(&self) -> io::Result<Directory> {
+        match self.data_directory(DIRECTORY_RESOURCE) {
+            (0, _) => Ok(Directory::default()),
+            (rva, _) => Directory::read(self, rva, 0, 0, &mut { MAX_ENTRIES }),
+        }
+    }
+
+    /// Returns a copy of the image with its resource section
+    /// replaced by `resources`.
+    fn with_resources(&self, resources: &Directory) -> io::Result<Vec<u8>> {
+        let section_alignment = self.header_u32(32);
+        let file_alignment = self.header_u32(36);
+
+        if !section_alignment.is_power_of_two() || !file_alignment.is_power_of_two() {
+            return Err(invalid_data());
+        }
+
+        let last = self.sections.iter().max_by_key(|s| s.virtual_address);
+        let raw_end = self.sections.iter().map(Section::raw_end).max().unwrap_or(0);
+
+        if raw_end > self.data.len() {
+            return Err(invalid_data());
+        }
+
+        let (rsrc_rva, _) = self.data_directory(DIRECTORY_RESOURCE);
+        let rsrc = last.filter(|s| {
+            s.virtual_address == rsrc_rva
+                && s.raw_end() == raw_end
+                && self.data.get(s.header..s.header + 8) == Some(&RSRC_NAME[..])
+        });
+
+        // Rewrites the resource section in place if it is the last
+        // one, otherwise a new section is appended to the image
+        let (mut exe, header, rva, overlay) = match rsrc {
+            Some(s) => (
+                self.data[..s.raw_pointer as usize].to_vec(),
+                s.header,
+                s.virtual_address,
+                raw_end,
+            ),
+            None => {
+                let header = self.section_table + SECTION_HEADER_LENGTH * self.sections.len();
+                let first_raw = self
+                    .sections
+                    .iter()
+                    .filter(|s| s.raw_size > 0)
+                    .map(|s| s.raw_pointer as usize)
+                    .min()
+                    .unwrap_or(self.data.len());
+
+                let size_of_headers = self.header_u32(60) as usize;
+
+                if header + SECTION_HEADER_LENGTH > size_of_headers
+                    || header + SECTION_HEADER_LENGTH > first_raw
+                {
+                    return Err(io::Error::from(io::ErrorKind::Other));
+                }
+
+                // The headers are copied even if no section has raw data
+                let copy_end = raw_end.max(size_of_headers);
+                if copy_end > self.data.len() {
+                    return Err(invalid_data());
+                }
+
+                let rva = match last {
+                    Some(s) => add_offset(s.virtual_address, s.virtual_size.max(s.raw_size) as usize)?,
+                    None => section_alignment,
+                };
+                let mut exe = self.data[..copy_end].to_vec();
+                exe.resize(align(exe.len(), file_alignment as usize), 0);
+
+                let n_sections = (self.sections.len() + 1) as u16;
+                put_u16(&mut exe, self.optional_header - COFF_HEADER_LENGTH + 2, n_sections);
+
+                (exe, header, align_rva(rva, section_alignment)?, copy_end)
+            }
+        };
+
+        let section = resources.write(rva)?;
+        let size_of_image = align_rva(add_offset(rva, section.len())?, section_alignment)?;
+        let raw_pointer = exe.len() as u32;
+        let raw_size = align(section.len(), file_alignment as usize) as u32;
+
+        exe.extend_from_slice(&section);
+        exe.resize(raw_pointer as usize + raw_size as usize, 0);
+        if overlay < self.data.len() {
+            exe.extend_from_slice(&self.data[overlay..]);
+        }
+
+        exe[header..header + 8].copy_from_slice(RSRC_NAME);
+        put_u32(&mut exe, header + 8, section.len() as u32);
+        put_u32(&mut exe, header + 12, rva);
+        put_u32(&mut exe, header + 16, raw_size);
+        put_u32(&mut exe, header + 20, raw_pointer);
+        for offset in (24..36).step_by(4) {
+            put_u32(&mut exe, header + offset, 0);
+        }
+        put_u32(&mut exe, header + 36, RSRC_CHARACTERISTICS);
+
+        put_u32(&mut exe, self.optional_header + 56, size_of_image);
+
+        let resource_directory = self.data_directories + 8 * DIRECTORY_RESOURCE;
+        put_u32(&mut exe, resource_directory, rva);
+        put_u32(&mut exe, resource_directory + 4, section.len() as u32);
+
+        // The file offset of the signature is no longer valid
+        if self.n_data_directories > DIRECTORY_SECURITY {
+            let security_directory = self.data_directories + 8 * DIRECTORY_SECURITY;
+            put_u32(&mut exe, security_directory, 0);
+            put_u32(&mut exe, security_directory + 4, 0);
+        }
+
+        let checksum = checksum(&exe, self.optional_header + 64);
+        put_u32(&mut exe, self.optional_header + 64, checksum);
+
+        Ok(exe)
+    }
+}
+
+/// Replaces the first icon group of `resources` by the entries of `icon`.
+fn replace_icon(resources: &mut Directory, icon: &Ico) -> io::Result<()> {
+    let entries = icon.dir_entries();
+
+    if entries.is_empty() {
+        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+    }
+
+    resources.sort();
+
+    // Removes the first group, along with the images it references
+    let mut group_name = Name::Id(1);
+    let mut referenced = HashSet::new();
+
+    if let Some(groups) = resources.dir(&Name::Id(RT_GROUP_ICON)) {
+        if let Some((name, Node::Dir(langs))) = groups.entries.first() {
+            group_name = name.clone();
+
+            for (_, node) in &langs.entries {
+                if let Node::Data(data, _) = node {
+                    referenced.extend(group_ids(data));
+                }
+            }
+        }
+    }
+
+    let groups = resources.dir_mut(Name::Id(RT_GROUP_ICON));
+    groups.entries.retain(|(name, _)| *name != group_name);
+
+    let icons = resources.dir_mut(Name::Id(RT_ICON));
+    icons.entries.retain(|(name, _)| match name {
+        Name::Id(id) => !referenced.contains(id),
+        Name::Str(_) => true,
+    });
+
+    let last_id = icons
+        .entries
+        .iter()
+        .filter_map(|(name, _)| match name {
+            Name::Id(id) => Some(*id),
+            Name::Str(_) => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    // The new images are numbered after the remaining ones
+    let ids = (1..=entries.len())
+        .map(|i| u16::try_from(i).ok().and_then(|i| last_id.checked_add(i)))
+        .collect::<Option<Vec<u16>>>()
+        .ok_or_else(invalid_data)?;
+
+    // Adds the new images, followed by the group referencing them
+    let mut group = Vec::with_capacity(6 + 14 * entries.len());
+    group.extend_from_slice(&[0, 0, 1, 0]);
+    group.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+
+    for (entry, &id) in entries.iter().zip(&ids) {
+        let bits_per_pixel = entry.bits_per_pixel();
+        let n_colors = if bits_per_pixel < 8 { 1u8 << bits_per_pixel } else { 0 };
+
+        icons
+            .dir_mut(Name::Id(id))
+            .entries
+            .push((Name::Id(LANG_NEUTRAL), Node::Data(entry.data().to_vec(), 0)));
+
+        group.extend_from_slice(&[entry.width() as u8, entry.height() as u8, n_colors, 0]);
+        group.extend_from_slice(&1u16.to_le_bytes());
+        group.extend_from_slice(&bits_per_pixel.to_le_bytes());
+        group.extend_from_slice(&(entry.data().len() as u32).to_le_bytes());
+        group.extend_from_slice(&id.to_le_bytes());
+    }
+
+    resources
+        .dir_mut(Name::Id(RT_GROUP_ICON))
+        .dir_mut(group_name)
+        .entries
+        .push((Name::Id(LANG_NEUTRAL), Node::Data(group, 0)));
+
+    resources.sort();
+    Ok(())
+}
+
+/// Returns the ids of the images referenced by an icon group.
+fn group_ids(group: &[u8]) -> Vec<u16> {
+    if group.len() < 6 {
+        return Vec::new();
+    }
+
+    let n_entries = usize::from(u16_at(group, 4));
+    group[6..]
+        .chunks(14)
+        .take(n_entries)
+        .filter(|entry| entry.len() == 14)
+        .map(|entry| u16_at(entry, 12))
+        .collect()
+}
+
+/// Computes the checksum of a _PE_ image, skipping
+/// the checksum field itself.
+pub(crate) fn checksum(exe: &[u8], checksum_offset: usize) -> u32 {
+    let mut sum: u64 = 0;
+
+    for (i, word) in exe.chunks(2).enumerate() {
+        if 2 * i == checksum_offset || 2 * i == checksum_offset + 2 {
+            continue;
+        }
+
+        sum += u64::from(word[0]) | u64::from(word.get(1).cloned().unwrap_or(0)) << 8;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    sum = (sum & 0xffff) + (sum >> 16);
+    (sum as u32).wrapping_add(exe.len() as u32)
+}
+
+/// Reads the resource tree of the _PE_ image `exe`.
+#[cfg(test)]
+pub(crate) fn read_resources(exe: &[u8]) -> io::Result<Directory> {
+    Pe::parse(exe)?.resources()
+}
+
+fn compare_names(a: &Name, b: &Name) -> Ordering {
+    match (a, b) {
+        (Name::Str(a), Name::Str(b)) => a.cmp(b),
+        (Name::Str(_), Name::Id(_)) => Ordering::Less,
+        (Name::Id(_), Name::Str(_)) => Ordering::Greater,
+        (Name::Id(a), Name::Id(b)) => a.cmp(b),
+    }
+}
+
+fn matches_data(node: &Node) -> bool {
+    match node {
+        Node::Data(..) => true,
+        Node::Dir(_) => false,
+    }
+}
+
+#[inline]
+fn align(n: usize, alignment: usize) -> usize {
+    (n + alignment - 1) & !(alignment - 1)
+}
+
+/// Returns `base + offset`, failing if the result does not fit the
+/// 32 bits address space of the image.
+fn add_offset(base: u32, offset: usize) -> io::Result<u32> {
+    u32::try_from(offset)
+        .ok()
+        .and_then(|offset| base.checked_add(offset))
+        .ok_or_else(invalid_data)
+}
+
+/// Rounds `rva` up to `alignment`, failing if the result does not fit
+/// the 32 bits address space of the image.
+fn align_rva(rva: u32, alignment: u32) -> io::Result<u32> {
+    rva.checked_add(alignment - 1)
+        .map(|rva| rva & !(alignment - 1))
+        .ok_or_else(invalid_data)
+}
+
+#[inline]
+fn invalid_data() -> io::Error {
+    io::Error::from(io::ErrorKind::InvalidData)
+}
+
+fn slice(data: &[u8], offset: usize, len: usize) -> io::Result<&[u8]> {
+    data.get(offset..offset.checked_add(len).ok_or_else(invalid_data)?)
+        .ok_or_else(invalid_data)
+}
+
+#[inline]
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+#[inline]
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+#[inline]
+fn put_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+#[inline]
+fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
@@ -1,16 +1,52 @@
 //! A collection of functions to assist in encoding images
 //! in commonly used _file formats_.
 
-use image::{png::PNGEncoder, ColorType, DynamicImage, GenericImageView};
+use image::{png::PNGEncoder, ColorType, DynamicImage, GenericImageView, ImageError};
+pub use image::ImageOutputFormat;
 use std::io;
 use resvg::usvg::{Tree, XmlIndent, XmlOptions};
 
+const PNG_SIGNATURE_LENGTH: usize = 8;
+const PNG_IHDR_LENGTH: usize = 25;
+// 72 DPI, in pixels per meter
+const PNG_BASE_DENSITY: f64 = 72.0 / 0.0254;
+// PNG limits the number of pixels per unit to 2^31 - 1
+const PNG_MAX_DENSITY: f64 = 2_147_483_647.0;
+// The perceptual rendering intent of the `sRGB` chunk
+const PNG_SRGB_PERCEPTUAL: u8 = 0;
+
 const XML_OPTS: XmlOptions = XmlOptions {
     indent: XmlIndent::None,
     attributes_indent: XmlIndent::None,
     use_single_quote: false,
 };
 
+/// Encodes _raster graphics_ in buffers of the specified `format`.
+///
+/// # Return Value
+///
+/// * Returns `Ok(buf)` if `image` was successfully encoded.
+/// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if
+///   `format` is not supported by the `image` crate.
+/// * Returns `Err(io::Error::from(io::ErrorKind::InvalidData))` otherwise.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let buf = encode::to_format(&img, ImageOutputFormat::JPEG(90))?;
+/// ```
+pub fn to_format(image: &DynamicImage, format: ImageOutputFormat) -> io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+
+    match image.write_to(&mut output, format) {
+        Ok(()) => Ok(output),
+        Err(ImageError::IoError(err)) => Err(err),
+        Err(ImageError::UnsupportedError(_)) => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+        Err(ImageError::InsufficientMemory) => Err(io::Error::from(io::ErrorKind::Other)),
+        Err(_) => Err(io::Error::from(io::ErrorKind::InvalidData)),
+    }
+}
+
 /// Encodes _raster graphics_ in _PNG_ buffers.
 pub fn png(image: &DynamicImage) -> io::Result<Vec<u8>> {
     let data = image.to_rgba().into_raw();
@@ -22,6 +58,182 @@ pub fn png(image: &DynamicImage) -> io::Result<Vec<u8>> {
     Ok(output)
 }
 
+/// Encodes _raster graphics_ in _PNG_ buffers, with a `pHYs` chunk
+/// marking the image as having `density` pixels per logical point.
+///
+/// A `density` of `1` stands for _72 DPI_, so that a _64x64_ image with
+/// a `density` of `2` is intended to be displayed as _32x32_ points.
+///
+/// # Return Value
+///
+/// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if
+///   `density` is `0` or too large to be represented.
+/// * Otherwise returns the encoded buffer.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let buf = encode::png_with_density(&img, 2)?;
+/// ```
+pub fn png_with_density(image: &DynamicImage, density: u32) -> io::Result<Vec<u8>> {
+    let mut output = png(image)?;
+    set_png_density(&mut output, density)?;
+
+    Ok(output)
+}
+
+/// Sets the `pHYs` chunk of the _PNG_ buffer `buf` to `density`
+/// pixels per logical point, replacing any existing one.
+pub(crate) fn set_png_density(buf: &mut Vec<u8>, density: u32) -> io::Result<()> {
+    let ppm = (PNG_BASE_DENSITY * f64::from(density)).round();
+    if density == 0 || ppm > PNG_MAX_DENSITY {
+        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+    }
+
+    let ppm = ppm as u32;
+    let mut data = Vec::with_capacity(9);
+
+    data.extend_from_slice(&ppm.to_be_bytes());
+    data.extend_from_slice(&ppm.to_be_bytes());
+    data.push(1); // The unit is the meter
+
+    set_png_chunk(buf, b"pHYs", &data)
+}
+
+/// Marks the _PNG_ buffer `buf` as being in the _sRGB_ color space by
+/// setting its `sRGB` chunk, replacing any existing one, so that color
+/// managed readers do not reinterpret its colors in their own color space.
+pub(crate) fn set_png_srgb(buf: &mut Vec<u8>) -> io::Result<()> {
+    set_png_chunk(buf, b"sRGB", &[PNG_SRGB_PERCEPTUAL])
+}
+
+/// Inserts a chunk of type `name` containing `data` right after the
+/// `IHDR` chunk of the _PNG_ buffer `buf`, removing the existing chunks
+/// of that type which precede the image data.
+fn set_png_chunk(buf: &mut Vec<u8>, name: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let ihdr_end = PNG_SIGNATURE_LENGTH + PNG_IHDR_LENGTH;
+    if buf.len() < ihdr_end || &buf[PNG_SIGNATURE_LENGTH + 4..PNG_SIGNATURE_LENGTH + 8] != b"IHDR" {
+        return Err(io::Error::from(io::ErrorKind::InvalidData));
+    }
+
+    let mut offset = ihdr_end;
+    while offset + 12 <= buf.len() && &buf[offset + 4..offset + 8] != b"IDAT" {
+        let len = u32::from_be_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]]);
+        let end = offset + 12 + len as usize;
+
+        if &buf[offset + 4..offset + 8] == name {
+            buf.drain(offset..end.min(buf.len()));
+        } else {
+            offset = end;
+        }
+    }
+
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(name);
+    chunk.extend_from_slice(data);
+    let crc = crc32fast::hash(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+
+    buf.splice(ihdr_end..ihdr_end, chunk);
+    Ok(())
+}
+
+#[inline]
+/// Encodes _raster graphics_ in _BMP_ buffers.
+pub fn bmp(image: &DynamicImage) -> io::Result<Vec<u8>> {
+    to_format(image, ImageOutputFormat::BMP)
+}
+
+#[inline]
+/// Encodes _raster graphics_ in _JPEG_ buffers.
+///
+/// `quality` ranges from `1` to `100`. Since _JPEG_ has no
+/// alpha channel, transparency is discarded.
+pub fn jpeg(image: &DynamicImage, quality: u8) -> io::Result<Vec<u8>> {
+    to_format(image, ImageOutputFormat::JPEG(quality))
+}
+
+#[cfg(feature = "avif")]
+/// Encodes _raster graphics_ in _AVIF_ buffers.
+///
+/// `quality` ranges from `1` (smallest output) to `100` (best quality).
+///
+/// # Return Value
+///
+/// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if
+///   `quality` is not in the `1..=100` range.
+/// * Otherwise returns the encoded buffer.
+pub fn avif(image: &DynamicImage, quality: u8) -> io::Result<Vec<u8>> {
+    if quality == 0 || quality > 100 {
+        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+    }
+
+    let data: Vec<ravif::RGBA8> = image
+        .to_rgba()
+        .into_raw()
+        .chunks_exact(4)
+        .map(|p| ravif::RGBA8::new(p[0], p[1], p[2], p[3]))
+        .collect();
+
+    let img = ravif::Img::new(&data[..], image.width() as usize, image.height() as usize);
+    let encoded = ravif::Encoder::new()
+        .with_quality(f32::from(quality))
+        .encode_rgba(img)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+    Ok(encoded.avif_file)
+}
+
+#[cfg(feature = "webp")]
+/// Encodes _raster graphics_ in lossy _WebP_ buffers.
+///
+/// `quality` ranges from `0` (smallest output) to `100` (best quality).
+///
+/// # Return Value
+///
+/// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if
+///   `quality` is greater than `100`.
+/// * Returns `Err(io::Error::from(io::ErrorKind::InvalidData))` if
+///   the image could not be encoded.
+/// * Otherwise returns the encoded buffer.
+pub fn webp(image: &DynamicImage, quality: u8) -> io::Result<Vec<u8>> {
+    if quality > 100 {
+        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+    }
+
+    let data = image.to_rgba().into_raw();
+    let (w, h) = image.dimensions();
+    let mut output: *mut u8 = std::ptr::null_mut();
+
+    // WebPEncodeRGBA allocates the output buffer, which has
+    // to be released with WebPFree
+    unsafe {
+        let len = libwebp_sys::WebPEncodeRGBA(
+            data.as_ptr(),
+            w as i32,
+            h as i32,
+            (w * 4) as i32,
+            f32::from(quality),
+            &mut output,
+        );
+
+        if output.is_null() {
+            return Err(io::Error::from(io::ErrorKind::InvalidData));
+        }
+
+        let buf = std::slice::from_raw_parts(output, len).to_vec();
+        libwebp_sys::WebPFree(output as *mut _);
+
+        if buf.is_empty() {
+            Err(io::Error::from(io::ErrorKind::InvalidData))
+        } else {
+            Ok(buf)
+        }
+    }
+}
+
 #[inline]
 /// Converts _vector graphics_ in UTF-8 encoded _SVG_ buffers.
 pub fn svg(image: &Tree) -> Vec<u8> {
@@ -3,18 +3,24 @@
 extern crate image;
 extern crate tar;
 
-use crate::{encode, resample, AsSize, IconError, Icon, Image};
+use crate::{add_rasterized, encode, ico::{self, Ico}, resample, AsSize, IconError, Icon, Image, Keys, DEFAULT_MAX_SIZE};
 use image::DynamicImage;
 use resvg::usvg;
 use std::{
     convert::TryFrom,
     collections::{hash_map::{HashMap, Entry}, btree_set::BTreeSet},
-    fs::{DirBuilder, File},
     io::{self, Write},
-    path::{Path, PathBuf},
+    path::PathBuf,
+};
+#[cfg(feature = "std-fs")]
+use std::{
+    fs::{DirBuilder, File},
+    path::Path,
 };
 
 const APPLE_TOUCH_SIZES: [u32;4] = [76, 120, 152, 180];
+const ICO_SIZES: [u32;3] = [16, 32, 48];
+const SVG_LINK: &str = "<link rel=\"icon\" type=\"image/svg+xml\" href=\"favicon.svg\">";
 
 macro_rules! path {
     ($path: expr) => {
@@ -33,11 +39,29 @@ pub struct Favicon {
     svgs: HashMap<Vec<u8>, Vec<u32>>,
     svg_entries: BTreeSet<u32>,
     include_apple_touch_helper: bool,
-    include_pwa_helper: bool
+    include_pwa_helper: bool,
+    manifest_config: ManifestConfig,
+    ico_sizes: Vec<u32>,
+    svg_passthrough: Option<Vec<u8>>,
+    max_size: u32
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+/// Additional fields of the _web app manifest_ generated
+/// by a `Favicon`.
+pub struct ManifestConfig {
+    /// The `name` of the web application.
+    pub name: Option<String>,
+    /// The `theme_color` of the web application, as a
+    /// _CSS_ color (e.g. `"#ffffff"`).
+    pub theme_color: Option<String>,
 }
 
 /// The _key type_ for `FavIcon`. Note that `Key(0)` represents
 /// a _65536x65536_ entry.
+///
+/// `Key` only describes square entries, hence it relies on the
+/// default implementation of `AsSize::as_dimensions`.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Key(pub u16);
 
@@ -122,6 +146,166 @@ impl Favicon {
         self
     }
 
+    #[inline]
+    /// Sets the additional fields of the _web app manifest_.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let fav = Favicon::new().manifest_config(ManifestConfig {
+    ///     name: Some(String::from("My App")),
+    ///     theme_color: Some(String::from("#ffffff")),
+    /// });
+    /// ```
+    pub fn manifest_config(&mut self, config: ManifestConfig) -> &mut Self {
+        self.manifest_config = config;
+        self
+    }
+
+    #[inline]
+    /// Sets the sizes of the entries included in the `favicon.ico`
+    /// file outputted by `self.write` and `self.save`, which browsers
+    /// request by default.
+    ///
+    /// Sizes that are not included in the favicon or that are not supported
+    /// by the `.ico` file format are skipped. No `favicon.ico` file is
+    /// outputted if none of the sizes are available.
+    ///
+    /// This option defaults to `[16, 32, 48]`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let fav = Favicon::new().ico_sizes(&[16, 32, 48, 64]);
+    /// ```
+    pub fn ico_sizes(&mut self, sizes: &[u32]) -> &mut Self {
+        self.ico_sizes = sizes.to_vec();
+        self
+    }
+
+    #[inline]
+    /// Sets the size limit, in pixels, of the entries of the favicon.
+    ///
+    /// Adding a larger entry fails with `IconError::InvalidSize`.
+    /// This option defaults to `DEFAULT_MAX_SIZE` (`4096`).
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let fav = Favicon::new().max_size(8192);
+    /// ```
+    pub fn max_size(&mut self, size: u32) -> &mut Self {
+        self.max_size = size;
+        self
+    }
+
+    /// Includes the _SVG_ document in `data` as a scalable `favicon.svg`
+    /// file in the output of `self.write` and `self.save`, along with the
+    /// corresponding _HTML_ link tag, for browsers which support _SVG_
+    /// favicons.
+    ///
+    /// The bytes are written out unchanged, so any styling, scripting or
+    /// `prefers-color-scheme` media queries in the original document are
+    /// preserved.
+    ///
+    /// Calling this method again replaces the previously added _SVG_.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidData))` if
+    ///   `data` is not a valid _SVG_ document.
+    /// * Otherwise returns `Ok(())`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut fav = Favicon::new();
+    /// fav.add_svg_passthrough(&fs::read("logo.svg")?)?;
+    /// ```
+    ///
+    /// ```xml
+    /// <link rel="icon" type="image/svg+xml" href="favicon.svg">
+    /// ```
+    pub fn add_svg_passthrough(&mut self, data: &[u8]) -> io::Result<()> {
+        match usvg::Tree::from_data(data, &usvg::Options::default()) {
+            Ok(_) => {
+                self.svg_passthrough = Some(data.to_vec());
+                Ok(())
+            }
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the favicon.svg file requires a valid SVG document",
+            )),
+        }
+    }
+
+    /// Adds entries for each of the `logical` sizes, at both _1x_ and
+    /// _2x_ _"retina"_ density.
+    ///
+    /// See `iconwriter::retina_sizes` for details.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::UnsupportedRetinaSize(size))` if the
+    ///   physical sizes of the logical `size` are not supported by `Key`.
+    /// * Otherwise see `Icon::add_entries`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// // Adds 16x16, 32x32, 64x64 and 96x96 entries
+    /// fav.add_retina_set(resample::linear, &src, &[16, 32, 48])?;
+    /// ```
+    pub fn add_retina_set<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        filter: F,
+        source: &Image,
+        logical: &[u32],
+    ) -> Result<(), IconError<Key>> {
+        for &size in logical {
+            let supported = Key::try_from(size).is_ok()
+                && size.checked_mul(2).map_or(false, |size_2x| Key::try_from(size_2x).is_ok());
+
+            if !supported {
+                return Err(IconError::UnsupportedRetinaSize(size));
+            }
+        }
+
+        let keys = crate::retina_sizes(logical)
+            .into_iter()
+            .map(Key::try_from)
+            .collect::<io::Result<Vec<_>>>()?;
+
+        self.add_entries(filter, source, keys)
+    }
+
+    /// Marks the _PNG_ entry associated with `key` as having `density`
+    /// pixels per logical point, by writing a `pHYs` chunk into it.
+    ///
+    /// See `encode::png_with_density` for details.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(io::Error::from(io::ErrorKind::NotFound))` if the
+    ///   favicon contains no _PNG_ entry associated with `key`.
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if
+    ///   `density` is `0`.
+    /// * Otherwise returns `Ok(())`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// // A 64x64 image, intended to be displayed as 32x32 points
+    /// fav.add_entry(resample::linear, &src, Key(64))?;
+    /// fav.set_density(Key(64), 2)?;
+    /// ```
+    pub fn set_density(&mut self, key: Key, density: u32) -> io::Result<()> {
+        match self.pngs.get_mut(&key.as_size()) {
+            Some(buf) => encode::set_png_density(buf, density),
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+    }
+
     /// Returns a buffer containing _HTML_ link tags to assist on
     /// the creating of the icon.
     pub fn html_helper(&self) -> io::Result<Vec<u8>> {
@@ -145,43 +329,141 @@ impl Favicon {
             i += 1;
         }
 
+        if self.svg_passthrough.is_some() {
+            writeln!(helper, "{}", SVG_LINK)?;
+        }
+
         Ok(helper)
     }
 
+    /// Returns the _HTML_ link tags for each of the entries of the
+    /// icon, referencing the files written by `self.save`.
+    ///
+    /// An `apple-touch-icon` link tag is included if the icon
+    /// contains a _180x180_ _PNG_ entry.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut fav = Favicon::new();
+    ///
+    /// fav.add_entries(resample::linear, &src, vec![Key(16), Key(180)])?;
+    /// println!("{}", fav.html_links());
+    /// ```
+    ///
+    /// ```xml
+    /// <link rel="icon" type="image/png" sizes="16x16" href="icons/favicon-0.png">
+    /// <link rel="icon" type="image/png" sizes="180x180" href="icons/favicon-1.png">
+    /// <link rel="apple-touch-icon" sizes="180x180" href="icons/favicon-1.png">
+    /// ```
+    pub fn html_links(&self) -> String {
+        let mut links = String::with_capacity(self.len() * 80);
+
+        for (i, (info, _)) in self.entries().enumerate() {
+            let sizes: Vec<String> = info.sizes().map(|size| format!("{0}x{0}", size)).collect();
+            let href = format!("icons/favicon-{}.{}", i, info.extension());
+
+            links.push_str(&format!(
+                "<link rel=\"icon\" type=\"{}\" sizes=\"{}\" href=\"{}\">\n",
+                info.res_type(),
+                sizes.join(" "),
+                href
+            ));
+
+            if let BufInfo::Png(180) = info {
+                links.push_str(&format!(
+                    "<link rel=\"apple-touch-icon\" sizes=\"180x180\" href=\"{}\">\n",
+                    href
+                ));
+            }
+        }
+
+        if self.svg_passthrough.is_some() {
+            links.push_str(SVG_LINK);
+            links.push('\n');
+        }
+
+        links
+    }
+
     /// Returns a buffer containing a _JSON_ helper for web manisfests.
     pub fn manifest(&self) -> io::Result<Vec<u8>> {
-        // TODO Preallocate this
-        let mut manifest = Vec::new();
-        let mut i = 0;
+        let mut manifest = Vec::with_capacity(128 + self.len() * 120);
+        self.write_manifest(&mut manifest)?;
 
-        write!(manifest, "{{\n    \"icons\": [\n")?;
+        Ok(manifest)
+    }
+
+    /// Writes a _[web app manifest](https://developer.mozilla.org/en-US/docs/Web/Manifest)_
+    /// listing the entries of the icon to `w`.
+    ///
+    /// The `src` of each icon matches the path of the
+    /// file written by `self.save`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut file = File::create("site.webmanifest")?;
+    /// fav.write_manifest(&mut file)?;
+    /// ```
+    pub fn write_manifest<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "{{")?;
+
+        if let Some(ref name) = self.manifest_config.name {
+            writeln!(w, "    \"name\": {},", json_string(name))?;
+        }
+
+        if let Some(ref theme_color) = self.manifest_config.theme_color {
+            writeln!(w, "    \"theme_color\": {},", json_string(theme_color))?;
+        }
+
+        write!(w, "    \"icons\": [")?;
+
+        for (i, (info, _)) in self.entries().enumerate() {
+            if i > 0 {
+                write!(w, ",")?;
+            }
 
-        for (info, _) in self.entries() {
             write!(
-                manifest,
-                "        {{\n            \"src\": \"icons/favicon-{}.{}\",\n            \"sizes\": \"",
+                w,
+                "\n        {{\n            \"src\": \"icons/favicon-{}.{}\",\n            \"sizes\": \"",
                 i,
                 info.extension()
             )?;
 
-            info.write_sizes(&mut manifest, |_| true)?;
+            info.write_sizes(w, |_| true)?;
 
             write!(
-                manifest,
-                "\",\n            \"type\": \"{}\"\n        }},\n",
+                w,
+                "\",\n            \"type\": \"{}\"\n        }}",
                 info.res_type()
             )?;
-
-            i += 1;
         }
 
-        // Remove the last comma
-        manifest.pop();
-        manifest.pop();
+        write!(w, "\n    ]\n}}")
+    }
 
-        write!(manifest, "\n    ]\n}}")?;
+    /// Returns the encoded `favicon.ico` file, if any of
+    /// the sizes specified by `self.ico_sizes` is available.
+    fn favicon_ico(&self) -> io::Result<Option<Vec<u8>>> {
+        let mut icon = Ico::with_capacity(self.ico_sizes.len());
 
-        Ok(manifest)
+        for &size in &self.ico_sizes {
+            let key = match ico::Key::try_from(size) {
+                Ok(key) if !icon.contains(key) => key,
+                _ => continue,
+            };
+
+            if let Some(image) = self.get_entry(Key(size as u16)) {
+                add_rasterized(&mut icon, image, key).map_err(Into::<io::Error>::into)?;
+            }
+        }
+
+        if icon.is_empty() {
+            Ok(None)
+        } else {
+            icon.write_to_vec().map(Some)
+        }
     }
 
     /// Returns the `Favicon`'s entries sorted by size.
@@ -239,6 +521,7 @@ impl Favicon {
     }
 
     /// Saves the _favicon_ to a directory.
+    #[cfg(feature = "std-fs")]
     fn save_to_dir<P: AsRef<Path>>(&self, base_path: &P) -> io::Result<()> {
         let container = base_path.as_ref().join("icons/");
 
@@ -247,13 +530,20 @@ impl Favicon {
             builder.recursive(true).create(container)?;
         }
 
-        let mut i = 0;
+        let files: Vec<(PathBuf, &[u8])> = self
+            .entries()
+            .enumerate()
+            .map(|(i, (info, buf))| (path!("icons/favicon-{}.{}", i, info.extension()), buf.as_ref()))
+            .collect();
 
-        for (info, buf) in self.entries() {
-            let path = path!("icons/favicon-{}.{}", i, info.extension());
-            save_file(buf.as_ref(), base_path, &path)?;
+        save_files(&files, base_path)?;
 
-            i += 1;
+        if let Some(buf) = self.favicon_ico()? {
+            save_file(buf.as_ref(), base_path, &"favicon.ico")?;
+        }
+
+        if let Some(ref buf) = self.svg_passthrough {
+            save_file(buf.as_ref(), base_path, &"favicon.svg")?;
         }
 
         let mut helper = self.html_helper()?;
@@ -278,7 +568,11 @@ impl Icon for Favicon {
             svgs: HashMap::new(),
             svg_entries: BTreeSet::new(),
             include_apple_touch_helper: false,
-            include_pwa_helper: false
+            include_pwa_helper: false,
+            manifest_config: ManifestConfig::default(),
+            ico_sizes: ICO_SIZES.to_vec(),
+            svg_passthrough: None,
+            max_size: DEFAULT_MAX_SIZE
         }
     }
 
@@ -286,16 +580,70 @@ impl Icon for Favicon {
         self.pngs.len() + self.svg_entries.len()
     }
 
+    fn capacity(&self) -> usize {
+        self.pngs.capacity() + self.svg_entries.len()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.pngs.reserve(additional);
+    }
+
+    fn keys(&self) -> Keys<Self::Key> {
+        let mut sizes: Vec<u32> = self.pngs.keys().chain(self.svg_entries.iter()).cloned().collect();
+        sizes.sort();
+
+        let keys: Vec<Key> = sizes
+            .into_iter()
+            .map(|size| Key::try_from(size).expect("The size should be supported by Favicon"))
+            .collect();
+
+        Keys::from(keys)
+    }
+
+    fn contains(&self, key: Self::Key) -> bool {
+        let size = key.as_size();
+        self.pngs.contains_key(&size) || self.svg_entries.contains(&size)
+    }
+
     fn add_entry<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
         &mut self,
         filter: F,
         source: &Image,
         key: Self::Key,
     ) -> Result<(), IconError<Self::Key>> {
+        if key.as_size() > self.max_size {
+            return Err(IconError::InvalidSize { requested: key.as_size(), max: self.max_size });
+        }
+
         match source {
             Image::Raster(ras) => self.add_raster(&resample::apply(filter, ras, key.as_size())?, key),
-            Image::Svg(svg) => self.add_svg(&svg, key)
+            Image::Svg(svg) => self.add_svg(&svg, key),
+            Image::Generated(_) => self.add_raster(&source.rasterize(filter, key.as_size())?, key),
+        }
+    }
+
+    fn get_entry(&self, key: Self::Key) -> Option<DynamicImage> {
+        let size = key.as_size();
+
+        if let Some(buf) = self.pngs.get(&size) {
+            return image::load_from_memory(buf).ok();
         }
+
+        // SVG entries are stored as vector graphics, so
+        // they have to be rasterized at the requested size
+        let (buf, _) = self.svgs.iter().find(|(_, sizes)| sizes.contains(&size))?;
+        let svg = Image::from_bytes(buf).ok()?;
+
+        svg.rasterize(resample::linear, size).ok()
+    }
+
+    fn clear(&mut self) {
+        // The passthrough file is derived from a source
+        // image, so it is not part of the configuration
+        self.pngs.clear();
+        self.svgs.clear();
+        self.svg_entries.clear();
+        self.svg_passthrough = None;
     }
 
     fn write<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
@@ -309,6 +657,14 @@ impl Icon for Favicon {
             i += 1;
         }
 
+        if let Some(buf) = self.favicon_ico()? {
+            write_data(&mut tar_builder, buf.as_ref(), path!("favicon.ico"))?;
+        }
+
+        if let Some(ref buf) = self.svg_passthrough {
+            write_data(&mut tar_builder, buf.as_ref(), path!("favicon.svg"))?;
+        }
+
         let mut helper = self.html_helper()?;
 
         if self.include_pwa_helper {
@@ -321,6 +677,7 @@ impl Icon for Favicon {
         write_data(&mut tar_builder, helper.as_ref(), path!("helper.html"))
     }
 
+    #[cfg(feature = "std-fs")]
     fn save<P: AsRef<Path>>(&mut self, base_path: &P) -> io::Result<()> {
         if base_path.as_ref().is_file() {
             let mut file = File::create(base_path.as_ref())?;
@@ -331,6 +688,15 @@ impl Icon for Favicon {
     }
 }
 
+impl IntoIterator for &Favicon {
+    type Item = Key;
+    type IntoIter = Keys<Key>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys()
+    }
+}
+
 impl AsSize for Key {
     fn as_size(&self) -> u32 {
         if self.0 == 0 {
@@ -452,6 +818,27 @@ impl<'a> BufInfo<'a> {
     }
 }
 
+/// Helper function to encode a string as a _JSON_ string literal.
+fn json_string(s: &str) -> String {
+    let mut output = String::with_capacity(s.len() + 2);
+    output.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if c.is_control() => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+
+    output.push('"');
+    output
+}
+
 #[inline]
 /// Helper function to append a buffer to a `.tar` file
 fn write_data<W: Write>(
@@ -466,6 +853,34 @@ fn write_data<W: Write>(
     builder.append_data::<PathBuf, &[u8]>(&mut header, path, data)
 }
 
+#[cfg(all(feature = "std-fs", feature = "rayon"))]
+/// Writes each of the `files` relative to `base_path` concurrently.
+///
+/// Every file is written even if some of the writes fail, in
+/// which case the error of the first failed file is returned.
+fn save_files<P: AsRef<Path>>(files: &[(PathBuf, &[u8])], base_path: &P) -> io::Result<()> {
+    use rayon::prelude::*;
+
+    let base_path = base_path.as_ref();
+    let results: Vec<io::Result<()>> = files
+        .par_iter()
+        .map(|(path, data)| save_file(data, &base_path, path))
+        .collect();
+
+    results.into_iter().collect()
+}
+
+#[cfg(all(feature = "std-fs", not(feature = "rayon")))]
+/// Writes each of the `files` relative to `base_path`.
+fn save_files<P: AsRef<Path>>(files: &[(PathBuf, &[u8])], base_path: &P) -> io::Result<()> {
+    for (path, data) in files {
+        save_file(data, base_path, path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "std-fs")]
 #[inline]
 /// Helper function to write a buffer to a location on disk.
 fn save_file<P1: AsRef<Path>, P2: AsRef<Path>>(
@@ -2,30 +2,524 @@
 
 extern crate icns;
 
-use crate::{Icon, AsSize, Image, IconError, ResReResampleError};
-use image::{DynamicImage, GenericImageView};
+use crate::{encode, Icon, AsSize, Image, IconError, Keys, ResReResampleError, Warning};
+use image::{DynamicImage, GenericImageView, RgbaImage};
 use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
     convert::TryFrom,
     fmt::{self, Debug, Formatter},
-    io::{self, Write},
+    hash::{Hash, Hasher},
+    io::{self, Seek, SeekFrom, Write},
+    str::FromStr,
+};
+#[cfg(feature = "std-fs")]
+use std::{
+    fs::{DirBuilder, File},
+    io::BufWriter,
+    path::Path,
 };
 
+const ICNS_MAGIC: &[u8] = b"icns";
+const ICNS_HEADER_LENGTH: u32 = 8;
+const TOC_OSTYPE: &[u8; 4] = b"TOC ";
+const TOC_ENTRY_LENGTH: u32 = 8;
+const INFO_OSTYPE: &[u8; 4] = b"info";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const DEBUG_MAX_ENTRIES: usize = 32;
+const SUPPORTED_SIZES: [u32; 7] = [16, 32, 64, 128, 256, 512, 1024];
+const RECOMMENDED_KEYS: [Key; 10] = [
+    Key::Rgba16,
+    Key::Rgba16At2x,
+    Key::Rgba32,
+    Key::Rgba32At2x,
+    Key::Rgba128,
+    Key::Rgba128At2x,
+    Key::Rgba256,
+    Key::Rgba256At2x,
+    Key::Rgba512,
+    Key::Rgba1024,
+];
+
 /// An ecoder for the `.icns` file format.
 pub struct Icns {
     icon_family: icns::IconFamily,
-    keys: Vec<u32>,
+    keys: Vec<Key>,
+    srgb_profile: bool,
+    jpeg2000: bool,
+    info: BTreeMap<String, String>,
+}
+
+/// An encoder for the `.icns` file format that writes each
+/// entry as soon as it is added, instead of keeping the whole
+/// icon in memory until it is written.
+///
+/// Since the header of a `.icns` file contains the length of the
+/// file, it can only be filled in once all entries are added.
+/// Therefore, the underlying writer is required to be seekable.
+/// Use `Icns` instead to write to a non-seekable writer.
+///
+/// Since the entries are not known in advance, no `TOC ` element is
+/// written, which _icns_ readers treat as optional.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let file = BufWriter::new(File::create("icon.icns")?);
+/// let mut icon = Icns::streaming(file)?;
+///
+/// icon.add_entry(resample::linear, &src, Key::Rgba1024)?;
+/// icon.add_entry(resample::linear, &src, Key::Rgba512)?;
+///
+/// icon.finish()?;
+/// ```
+pub struct IcnsStream<W: Write + Seek> {
+    writer: W,
+    length: u32,
+    keys: Vec<Key>,
 }
 
 /// The _key-type_ for `Icns`.
+///
+/// The `*At2x` variants represent entries at 2x _"retina"_ density.
+/// Note that `Key::as_size` reports the physical dimensions of an
+/// entry, so `Key::Rgba128At2x` and `Key::Rgba256` both describe
+/// _256x256_ images, but are encoded under different _OSTypes_.
+///
+/// The masks of legacy entries are taken from the alpha channel of
+/// the output of the resampling filter, which also produces their
+/// color element, so both elements stay aligned for any filter.
+///
+/// The `.icns` file format only supports square entries, hence `Key`
+/// relies on the default implementation of `AsSize::as_dimensions`.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Key {
+    /// A _16x16_ entry. Encoded as a legacy pair of a 24-bit _RGB_
+    /// element (`is32`) and an 8-bit alpha mask (`s8mk`).
     Rgba16,
+    /// A _32x32_ entry. Encoded as a legacy pair of a 24-bit _RGB_
+    /// element (`il32`) and an 8-bit alpha mask (`l8mk`).
     Rgba32,
     Rgba64,
+    /// A _128x128_ entry. Encoded as a legacy pair of a 24-bit _RGB_
+    /// element (`it32`) and an 8-bit alpha mask (`t8mk`).
     Rgba128,
     Rgba256,
     Rgba512,
-    Rgba1024
+    /// A _1024x1024_ entry. Encoded as _512x512_ at 2x density (`ic10`).
+    Rgba1024,
+    /// A _16x16_ entry at 2x density (`ic11`).
+    Rgba16At2x,
+    /// A _32x32_ entry at 2x density (`ic12`).
+    Rgba32At2x,
+    /// A _128x128_ entry at 2x density (`ic13`).
+    Rgba128At2x,
+    /// A _256x256_ entry at 2x density (`ic14`).
+    Rgba256At2x,
+}
+
+impl Icns {
+    /// Creates a new icon whose entries are tagged as being in the
+    /// _sRGB_ color space, so that color managed versions of _macOS_
+    /// display them consistently on wide-gamut displays, such as
+    /// _Display P3_ ones.
+    ///
+    /// Entries encoded as _PNG_ images carry an `sRGB` chunk. The legacy
+    /// _RGB_ elements of `Key::Rgba16`, `Key::Rgba32` and `Key::Rgba128`
+    /// cannot carry color space information, and are left untagged.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut icon = Icns::with_srgb_profile();
+    /// icon.add_entry(resample::linear, &src, Key::Rgba512)?;
+    /// ```
+    pub fn with_srgb_profile() -> Self {
+        let mut icon = Icns::new();
+        icon.srgb_profile = true;
+
+        icon
+    }
+
+    /// Creates a new icon whose entries of _256x256_ and above are
+    /// stored as _JPEG 2000_ images, which are considerably smaller
+    /// than _PNG_ ones for photographic icons.
+    ///
+    /// No _JPEG 2000_ encoder is currently available to this crate,
+    /// so these entries fall back to being stored as _RGBA_ (_PNG_)
+    /// images, exactly as they would be by `Icns::new`. The output is
+    /// therefore always a valid `.icns` file.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut icon = Icns::with_jpeg2000();
+    /// icon.add_entry(resample::linear, &src, Key::Rgba512)?;
+    /// ```
+    pub fn with_jpeg2000() -> Self {
+        let mut icon = Icns::new();
+        icon.jpeg2000 = true;
+
+        icon
+    }
+
+    /// Creates a new icon containing the standard set of _macOS_
+    /// icon sizes: _16x16_, _32x32_, _128x128_, _256x256_ and
+    /// _512x512_, each at both standard and 2x _"retina"_ density.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::WhileAdding(size, _))` if the resampling filter
+    ///   provided in the `filter` argument fails or produces results of dimensions
+    ///   other than the ones specified by the standard sizes.
+    /// * Returns `Err(IconError::UnsupportedSize(_))` if an entry could not be
+    ///   stored in the icon family.
+    /// * Otherwise returns `Ok(icns)`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let src = Image::open("image.svg")?;
+    /// let icon = Icns::standard_macos(resample::linear, &src)?;
+    /// ```
+    pub fn standard_macos<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        filter: F,
+        source: &Image,
+    ) -> Result<Self, IconError<Key>> {
+        let keys = [
+            Key::Rgba16,
+            Key::Rgba16At2x,
+            Key::Rgba32,
+            Key::Rgba32At2x,
+            Key::Rgba128,
+            Key::Rgba128At2x,
+            Key::Rgba256,
+            Key::Rgba256At2x,
+            Key::Rgba512,
+            Key::Rgba1024,
+        ];
+
+        let mut icon = Icns::with_capacity(keys.len());
+
+        icon.add_entries(filter, source, keys.iter().cloned())?;
+        Ok(icon)
+    }
+
+    /// Adds entries for each of the `logical` sizes, at both _1x_ and
+    /// _2x_ _"retina"_ density.
+    ///
+    /// The _2x_ entry of `512` is `Key::Rgba1024`. Repeated logical
+    /// sizes are only added once.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::UnsupportedRetinaSize(size))` if `.icns`
+    ///   files have no entry for the logical `size` at either density.
+    /// * Otherwise see `Icon::add_entries`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// icon.add_retina_set(resample::linear, &src, &[16, 32, 128, 256, 512])?;
+    /// ```
+    pub fn add_retina_set<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        filter: F,
+        source: &Image,
+        logical: &[u32],
+    ) -> Result<(), IconError<Key>> {
+        let mut keys = Vec::with_capacity(2 * logical.len());
+
+        for &size in logical {
+            let (key, key_2x) = match (Key::try_from(size), Key::at_2x(size)) {
+                (Ok(key), Some(key_2x)) => (key, key_2x),
+                _ => return Err(IconError::UnsupportedRetinaSize(size)),
+            };
+
+            for key in [key, key_2x].iter() {
+                if !keys.contains(key) {
+                    keys.push(*key);
+                }
+            }
+        }
+
+        self.add_entries(filter, source, keys)
+    }
+
+    /// Writes the contents of the icon to a directory following
+    /// the layout of _Apple's_ `.iconset` folders, which can be
+    /// converted to `.icns` files with the `iconutil` tool.
+    ///
+    /// Each entry is saved as a _PNG_ file named after the
+    /// dimensions of the entry in points, such as `icon_32x32.png`
+    /// or `icon_32x32@2x.png`. Entries that fit multiple names,
+    /// such as _32x32_ entries, are saved under all of them.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut icon = Icns::new();
+    ///
+    /// /* Process the icon */
+    ///
+    /// icon.save_iconset("Icon.iconset")?;
+    /// ```
+    #[cfg(feature = "std-fs")]
+    pub fn save_iconset<P: AsRef<Path>>(&self, dir: P) -> io::Result<()> {
+        let dir = dir.as_ref();
+
+        if !dir.exists() {
+            let mut builder = DirBuilder::new();
+            builder.recursive(true).create(dir)?;
+        }
+
+        // Retina entries are saved last, so that they take precedence
+        // over standard entries saved under the same name
+        let standard = self.keys.iter().filter(|key| !key.is_retina());
+        let retina = self.keys.iter().filter(|key| key.is_retina());
+
+        for key in standard.chain(retina) {
+            let image = self.icon_family.get_icon_with_type(key.icon_type())?;
+
+            for name in key.iconset_names() {
+                let file = BufWriter::new(File::create(dir.join(name))?);
+                image.write_png(file)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies all the entries of `other` into `self`, without
+    /// re-encoding them.
+    ///
+    /// The metadata of `other`, set by `Icns::set_info`, is not copied.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::AlreadyIncluded(key))` if `self` already
+    ///   contains an entry associated with a key of `other`, in which
+    ///   case `self` is left unchanged.
+    /// * Otherwise returns `Ok(())`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut icon = Icns::new();
+    /// icon.add_entry(resample::linear, &src, Key::Rgba32)?;
+    ///
+    /// let mut large = Icns::new();
+    /// large.add_entry(resample::linear, &src, Key::Rgba512)?;
+    ///
+    /// icon.merge(&large)?;
+    /// ```
+    pub fn merge(&mut self, other: &Self) -> Result<(), IconError<Key>> {
+        if let Some(&key) = other.keys.iter().find(|key| self.keys.contains(key)) {
+            return Err(IconError::AlreadyIncluded(key));
+        }
+
+        for element in &other.icon_family.elements {
+            if &element.ostype.0 == INFO_OSTYPE {
+                continue;
+            }
+
+            let clone = icns::IconElement::new(element.ostype, element.data.clone());
+
+            self.icon_family.elements.push(clone);
+        }
+
+        self.keys.extend_from_slice(&other.keys);
+        Ok(())
+    }
+
+    /// Creates a new `IcnsStream`, which writes the header
+    /// of the icon to `w` and then writes each entry to `w`
+    /// as it is added.
+    ///
+    /// See `IcnsStream` for details.
+    pub fn streaming<W: Write + Seek>(mut w: W) -> io::Result<IcnsStream<W>> {
+        // The length of the file is filled in by IcnsStream::finish
+        w.write_all(ICNS_MAGIC)?;
+        w.write_all(&0u32.to_be_bytes())?;
+
+        Ok(IcnsStream {
+            writer: w,
+            length: ICNS_HEADER_LENGTH,
+            keys: Vec::new(),
+        })
+    }
+
+    /// Sets the `value` of the metadata field `key`, replacing its
+    /// previous value.
+    ///
+    /// The metadata of the icon is written as an `info` element, whose
+    /// data is an _XML property list_ mapping each field to its value as
+    /// a string. Readers which do not support `info` elements skip them,
+    /// like any element of an unknown type.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut icon = Icns::new();
+    /// icon.set_info("CFBundleVersion", env!("CARGO_PKG_VERSION"));
+    /// ```
+    pub fn set_info(&mut self, key: &str, value: &str) {
+        self.info.insert(key.to_owned(), value.to_owned());
+
+        let data = info_plist(&self.info).into_bytes();
+        let element = icns::IconElement::new(icns::OSType(*INFO_OSTYPE), data);
+
+        self.icon_family.elements.retain(|element| &element.ostype.0 != INFO_OSTYPE);
+        self.icon_family.elements.push(element);
+    }
+
+    /// Returns the length in bytes of the output of `self.write`,
+    /// without encoding the icon.
+    ///
+    /// This is the length of the _8_ byte header of the icon family plus
+    /// the length of each of its elements, including their _8_ byte headers
+    /// and the leading `TOC ` element.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// if icon.encoded_len() > BUDGET {
+    ///     icon.remove_entry(Key::Rgba1024);
+    /// }
+    /// ```
+    pub fn encoded_len(&self) -> usize {
+        let elements_len: u32 = self
+            .icon_family
+            .elements
+            .iter()
+            .map(|element| element.total_length())
+            .sum();
+
+        let toc_len = ICNS_HEADER_LENGTH + TOC_ENTRY_LENGTH * self.icon_family.elements.len() as u32;
+
+        (ICNS_HEADER_LENGTH + toc_len + elements_len) as usize
+    }
+
+    /// Returns a hash of the entries of the icon, which does not
+    /// depend on the order the entries were added in.
+    ///
+    /// The hash is only guaranteed to be stable for a single
+    /// build of the program.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+
+        hasher.finish()
+    }
+
+    /// Returns the _OSTypes_ of the elements of the icon family
+    /// along with their data, sorted by _OSType_.
+    fn sorted_elements(&self) -> Vec<([u8; 4], &[u8])> {
+        let mut elements: Vec<([u8; 4], &[u8])> = self
+            .icon_family
+            .elements
+            .iter()
+            .map(|element| (element.ostype.0, element.data.as_ref()))
+            .collect();
+
+        elements.sort();
+        elements
+    }
+
+    /// Adds `image` to the icon family as the entry associated with `key`.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::UnsupportedSize(key))` if the dimensions
+    ///   of `image` do not match the icon type of `key`.
+    /// * Otherwise returns `Ok(())`.
+    pub(crate) fn add_image(&mut self, image: &icns::Image, key: Key) -> Result<(), IconError<Key>> {
+        // The IconFamily::add_icon_with_type method only fails when the
+        // specified image dimensions do not match the icon type
+        if self.icon_family.add_icon_with_type(image, key.icon_type()).is_err() {
+            return Err(IconError::UnsupportedSize(key));
+        }
+
+        if self.jpeg2000 && key.as_size() >= 256 {
+            if let Some(data) = encode_jpeg2000(image) {
+                for element in &mut self.icon_family.elements {
+                    if element.icon_type() == Some(key.icon_type()) {
+                        element.data = data.clone();
+                    }
+                }
+            }
+        }
+
+        if self.srgb_profile {
+            // Legacy elements and their masks are not encoded as PNG
+            for element in &mut self.icon_family.elements {
+                if element.icon_type() == Some(key.icon_type()) && element.data.starts_with(PNG_SIGNATURE) {
+                    encode::set_png_srgb(&mut element.data)?;
+                }
+            }
+        }
+
+        self.keys.push(key);
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> IcnsStream<W> {
+    /// Rasterizes `source` and writes it as the entry associated with `key`.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::AlreadyIncluded(_))` if an entry associated
+    ///   with `key` was already written.
+    /// * Returns `Err(IconError::Resample(_))` if the resampling filter provided in
+    ///   the `filter` argument fails or produces results of dimensions other than the
+    ///   ones specified by `key`, or if the entry could not be written.
+    /// * Returns `Err(IconError::UnsupportedSize(_))` if the entry could not be
+    ///   encoded as the icon type of `key`.
+    /// * Otherwise returns `Ok(())`.
+    pub fn add_entry<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        filter: F,
+        source: &Image,
+        key: Key,
+    ) -> Result<(), IconError<Key>> {
+        if self.keys.contains(&key) {
+            return Err(IconError::AlreadyIncluded(key));
+        }
+
+        let image = rasterize(filter, source, key)?;
+        let mut icon_family = icns::IconFamily::new();
+
+        if icon_family.add_icon_with_type(&image, key.icon_type()).is_err() {
+            return Err(IconError::UnsupportedSize(key));
+        }
+
+        for element in &icon_family.elements {
+            element.write(&mut self.writer)?;
+            self.length += element.total_length();
+        }
+
+        self.keys.push(key);
+        Ok(())
+    }
+
+    /// Returns the keys of the entries written so far.
+    pub fn keys(&self) -> Keys<Key> {
+        Keys::from(self.keys.clone())
+    }
+
+    /// Fills in the header of the icon and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        // The writer is positioned at the end of the icon
+        let length = i64::from(self.length);
+        let magic_length = ICNS_MAGIC.len() as i64;
+
+        self.writer.seek(SeekFrom::Current(magic_length - length))?;
+        self.writer.write_all(&self.length.to_be_bytes())?;
+        self.writer.seek(SeekFrom::Current(length - magic_length - 4))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
 }
 
 impl Icon for Icns {
@@ -35,11 +529,31 @@ impl Icon for Icns {
         Icns {
             icon_family: icns::IconFamily { elements: Vec::with_capacity(capacity) },
             keys: Vec::with_capacity(capacity),
+            srgb_profile: false,
+            jpeg2000: false,
+            info: BTreeMap::new(),
         }
     }
 
     fn len(&self) -> usize {
-        self.icon_family.elements.len()
+        self.keys.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.keys.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.keys.reserve(additional);
+        self.icon_family.elements.reserve(additional);
+    }
+
+    fn keys(&self) -> Keys<Self::Key> {
+        Keys::from(self.keys.clone())
+    }
+
+    fn contains(&self, key: Self::Key) -> bool {
+        self.keys.contains(&key)
     }
 
     fn add_entry<'a, F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
@@ -48,30 +562,96 @@ impl Icon for Icns {
         source: &Image,
         key: Self::Key
     ) -> Result<(), IconError<Self::Key>> {
-        let size = key.as_size();
-        let icon = source.rasterize(filter, size)?;
-        let data = icon.to_rgba().into_vec();
-
-        if self.keys.contains(&size) {
+        if self.keys.contains(&key) {
             return Err(IconError::AlreadyIncluded(key));
         }
 
-        // The Image::from_data method only fails when the specified
-        // image dimensions do not fit the buffer length
-        let image = icns::Image::from_data(icns::PixelFormat::RGBA, size, size, data)
-            .map_err(|_| ResReResampleError::MismatchedDimensions(size, icon.dimensions()))?;
+        let image = rasterize(filter, source, key)?;
+        self.add_image(&image, key)
+    }
 
-        // The IconFamily::add_icon method only fails when the
-        // specified image dimensions are not supported by ICNS
-        self.icon_family
-            .add_icon(&image)
-            .expect("The image dimensions should be supported by ICNS");
+    fn get_entry(&self, key: Self::Key) -> Option<DynamicImage> {
+        if !self.keys.contains(&key) {
+            return None;
+        }
 
-        Ok(())
+        let image = self
+            .icon_family
+            .get_icon_with_type(key.icon_type())
+            .ok()?
+            .convert_to(icns::PixelFormat::RGBA);
+
+        let (w, h) = (image.width(), image.height());
+        let buf = RgbaImage::from_raw(w, h, image.into_data().into_vec())?;
+
+        Some(DynamicImage::ImageRgba8(buf))
+    }
+
+    /// Reports the entries recommended by _Apple_ which are missing from the
+    /// icon, namely _16x16_, _32x32_, _128x128_, _256x256_ and _512x512_ at
+    /// both standard and 2x density, as well as the entries reported by
+    /// `Icon::duplicate_content`.
+    fn validate(&self) -> Vec<Warning<Self::Key>> {
+        RECOMMENDED_KEYS
+            .iter()
+            .filter(|key| !self.keys.contains(key))
+            .map(|&key| Warning::MissingRecommendedSize(key))
+            .chain(self.duplicate_content().into_iter().map(|(a, b)| Warning::DuplicateContent(a, b)))
+            .collect()
+    }
+
+    fn remove_entry(&mut self, key: Self::Key) -> bool {
+        if let Some(index) = self.keys.iter().position(|&k| k == key) {
+            let icon_type = key.icon_type();
+
+            self.keys.remove(index);
+            self.icon_family.elements.retain(|element| match element.icon_type() {
+                Some(t) => t != icon_type && Some(t) != icon_type.mask_type(),
+                None => true,
+            });
+
+            true
+        } else {
+            false
+        }
+    }
+
+    fn clear(&mut self) {
+        // The metadata of the icon is part of its configuration
+        self.icon_family.elements.retain(|element| &element.ostype.0 == INFO_OSTYPE);
+        self.keys.clear();
     }
 
     fn write<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
-        self.icon_family.write(w)
+        // Elements are written in ascending order of size, regardless of
+        // the order in which they were added, so that the output is
+        // reproducible. They are preceded by a `TOC ` element, which lets
+        // readers locate them without scanning the whole file
+        self.icon_family.elements.sort_by_key(element_order);
+
+        let toc = toc_element(&self.icon_family.elements);
+        let length = ICNS_HEADER_LENGTH
+            + toc.total_length()
+            + self.icon_family.elements.iter().map(|element| element.total_length()).sum::<u32>();
+
+        w.write_all(ICNS_MAGIC)?;
+        w.write_all(&length.to_be_bytes())?;
+        toc.write(&mut *w)?;
+
+        for element in &self.icon_family.elements {
+            element.write(&mut *w)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl IntoIterator for &Icns {
+    type Item = Key;
+    type IntoIter = Keys<Key>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys()
     }
 }
 
@@ -90,34 +670,135 @@ impl Clone for Icns {
         Icns {
             icon_family,
             keys: self.keys.clone(),
+            srgb_profile: self.srgb_profile,
+            jpeg2000: self.jpeg2000,
+            info: self.info.clone(),
         }
     }
 }
 
-macro_rules! element {
-    ($elm:expr) => {
-        format!(
-            "IconElement {{ ostype: {:?}, data: {:?} }}",
-            $elm.ostype, $elm.data
-        )
-    };
+/// Builds the `TOC ` element of an icon family, which lists the _OSType_
+/// and the length of each of the following `elements`.
+fn toc_element(elements: &[icns::IconElement]) -> icns::IconElement {
+    let mut data = Vec::with_capacity(elements.len() * TOC_ENTRY_LENGTH as usize);
+
+    for element in elements {
+        data.extend_from_slice(&element.ostype.0);
+        data.extend_from_slice(&element.total_length().to_be_bytes());
+    }
+
+    icns::IconElement::new(icns::OSType(*TOC_OSTYPE), data)
+}
+
+/// Renders `info` as an _XML property list_ containing a dictionary
+/// of strings.
+fn info_plist(info: &BTreeMap<String, String>) -> String {
+    fn escape(s: &str) -> String {
+        s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+    }
+
+    let mut plist = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+        "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+        "<plist version=\"1.0\">\n<dict>\n",
+    ));
+
+    for (key, value) in info {
+        plist.push_str(&format!("\t<key>{}</key>\n\t<string>{}</string>\n", escape(key), escape(value)));
+    }
+
+    plist.push_str("</dict>\n</plist>\n");
+    plist
+}
+
+/// Returns the position of `element` in the order in which elements are
+/// written: by physical size, with _@2x_ elements following the standard
+/// ones of the same size and masks following the elements they belong to.
+/// Elements of unknown types are written last.
+fn element_order(element: &icns::IconElement) -> (bool, u32, u32, bool, [u8; 4]) {
+    match element.icon_type() {
+        Some(t) => (false, t.pixel_width(), t.pixel_density(), t.is_mask(), element.ostype.0),
+        None => (true, 0, 0, false, element.ostype.0),
+    }
+}
+
+/// Encodes `image` as a _JPEG 2000_ code stream.
+///
+/// Returns `None` when _JPEG 2000_ compression is unavailable, in which
+/// case the entry keeps its _RGBA_ encoding. This is currently always
+/// the case, since neither the `icns` crate nor any of the dependencies
+/// of this crate provide a _JPEG 2000_ encoder.
+fn encode_jpeg2000(_image: &icns::Image) -> Option<Vec<u8>> {
+    None
+}
+
+/// Rasterizes `source` to an `icns::Image` of the dimensions of `key`.
+fn rasterize<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+    filter: F,
+    source: &Image,
+    key: Key,
+) -> Result<icns::Image, IconError<Key>> {
+    let size = key.as_size();
+    let icon = source.rasterize(filter, size)?;
+
+    // Legacy masks are derived from this same buffer by `icns`,
+    // rather than resampled separately
+    let data = icon.to_rgba().into_vec();
+
+    // The Image::from_data method only fails when the specified
+    // image dimensions do not fit the buffer length
+    let image = icns::Image::from_data(icns::PixelFormat::RGBA, size, size, data)
+        .map_err(|_| ResReResampleError::MismatchedDimensions(size, icon.dimensions()))?;
+
+    Ok(image)
+}
+
+impl PartialEq for Icns {
+    /// Two icons are equal if they contain the same entries, with the
+    /// same encoded data, regardless of the order they were added in.
+    fn eq(&self, other: &Self) -> bool {
+        self.sorted_elements() == other.sorted_elements()
+    }
+}
+
+impl Eq for Icns {}
+
+impl Hash for Icns {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sorted_elements().hash(state);
+    }
 }
 
 impl Debug for Icns {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let entries_strs: Vec<String> = self
-            .icon_family
-            .elements
+        let elements = &self.icon_family.elements;
+        let mut elements_strs: Vec<String> = elements
             .iter()
-            .map(|element| element!(element))
+            .take(DEBUG_MAX_ENTRIES)
+            .map(|element| match element.icon_type() {
+                Some(icon_type) if icon_type.pixel_density() > 1 => format!(
+                    "{} ({}x{}@{}x)",
+                    element.ostype,
+                    icon_type.screen_width(),
+                    icon_type.screen_height(),
+                    icon_type.pixel_density()
+                ),
+                Some(icon_type) => format!(
+                    "{} ({}x{})",
+                    element.ostype,
+                    icon_type.pixel_width(),
+                    icon_type.pixel_height()
+                ),
+                None => format!("{}", element.ostype),
+            })
             .collect();
 
-        let icon_dir = format!(
-            "icns::IconFamily {{ elements: [{}] }}",
-            entries_strs.join(", ")
-        );
+        if elements.len() > DEBUG_MAX_ENTRIES {
+            elements_strs.push(format!("... {} more", elements.len() - DEBUG_MAX_ENTRIES));
+        }
 
-        write!(f, "iconwriter::Icns {{ icon_family: {} }} ", icon_dir)
+        write!(f, "iconwriter::Icns {{ elements: [{}] }}", elements_strs.join(", "))
     }
 }
 
@@ -130,11 +811,116 @@ impl AsSize for Key {
             Self::Rgba128 => 128,
             Self::Rgba64 => 64,
             Self::Rgba32 => 32,
-            Self::Rgba16 => 16
+            Self::Rgba16 => 16,
+            Self::Rgba256At2x => 512,
+            Self::Rgba128At2x => 256,
+            Self::Rgba32At2x => 64,
+            Self::Rgba16At2x => 32
+        }
+    }
+}
+
+impl Key {
+    /// Returns the sizes supported by the `.icns` file format:
+    /// _16x16_, _32x32_, _64x64_, _128x128_, _256x256_, _512x512_
+    /// and _1024x1024_.
+    ///
+    /// Each of these sizes can be converted to a `Key` with `Key::try_from`.
+    pub fn supported_sizes() -> &'static [u32] {
+        &SUPPORTED_SIZES
+    }
+
+    /// Returns the key of the supported size closest to `size`.
+    /// Ties are resolved in favor of the largest size.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// assert_eq!(Key::nearest_supported(100), Key::Rgba128);
+    /// ```
+    pub fn nearest_supported(size: u32) -> Self {
+        let nearest = SUPPORTED_SIZES
+            .iter()
+            .rev()
+            .min_by_key(|&&s| (i64::from(s) - i64::from(size)).abs())
+            .expect("SUPPORTED_SIZES should not be empty");
+
+        Key::try_from(*nearest).expect("The size should be supported by ICNS")
+    }
+
+    /// Returns the key of the entry of `size`x`size` points
+    /// at 2x _"retina"_ density, if any.
+    fn at_2x(size: u32) -> Option<Self> {
+        match size {
+            16 => Some(Self::Rgba16At2x),
+            32 => Some(Self::Rgba32At2x),
+            128 => Some(Self::Rgba128At2x),
+            256 => Some(Self::Rgba256At2x),
+            512 => Some(Self::Rgba1024),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if this key represents an entry
+    /// at 2x _"retina"_ density.
+    pub fn is_retina(&self) -> bool {
+        self.icon_type().pixel_density() == 2
+    }
+
+    /// Returns the `icns::IconType` entries associated
+    /// with this key are encoded as.
+    fn icon_type(&self) -> icns::IconType {
+        match self {
+            Self::Rgba16 => icns::IconType::RGB24_16x16,
+            Self::Rgba32 => icns::IconType::RGB24_32x32,
+            Self::Rgba64 => icns::IconType::RGBA32_64x64,
+            Self::Rgba128 => icns::IconType::RGB24_128x128,
+            Self::Rgba256 => icns::IconType::RGBA32_256x256,
+            Self::Rgba512 => icns::IconType::RGBA32_512x512,
+            Self::Rgba1024 => icns::IconType::RGBA32_512x512_2x,
+            Self::Rgba16At2x => icns::IconType::RGBA32_16x16_2x,
+            Self::Rgba32At2x => icns::IconType::RGBA32_32x32_2x,
+            Self::Rgba128At2x => icns::IconType::RGBA32_128x128_2x,
+            Self::Rgba256At2x => icns::IconType::RGBA32_256x256_2x,
+        }
+    }
+
+    /// Returns the names of the files associated with this
+    /// key in an `.iconset` folder.
+    #[cfg(feature = "std-fs")]
+    fn iconset_names(&self) -> &'static [&'static str] {
+        match self {
+            Self::Rgba16 => &["icon_16x16.png"],
+            Self::Rgba32 => &["icon_16x16@2x.png", "icon_32x32.png"],
+            Self::Rgba64 => &["icon_32x32@2x.png"],
+            Self::Rgba128 => &["icon_128x128.png"],
+            Self::Rgba256 => &["icon_128x128@2x.png", "icon_256x256.png"],
+            Self::Rgba512 => &["icon_256x256@2x.png", "icon_512x512.png"],
+            Self::Rgba1024 => &["icon_512x512@2x.png"],
+            Self::Rgba16At2x => &["icon_16x16@2x.png"],
+            Self::Rgba32At2x => &["icon_32x32@2x.png"],
+            Self::Rgba128At2x => &["icon_128x128@2x.png"],
+            Self::Rgba256At2x => &["icon_256x256@2x.png"],
         }
     }
 }
 
+impl FromStr for Key {
+    type Err = io::Error;
+
+    /// Parses sizes such as `"32"` or `"32x32"`.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(_)` with a descriptive message if `s` is
+    ///   malformed, describes a non-square entry or a size
+    ///   unsupported by the format.
+    /// * Otherwise returns `Ok(key)`.
+    fn from_str(s: &str) -> io::Result<Self> {
+        crate::parse_square_key(s, "ICNS", Key::try_from)
+    }
+}
+
 impl TryFrom<u32> for Key {
     type Error = io::Error;
 
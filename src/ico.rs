@@ -2,27 +2,550 @@
 
 extern crate ico;
 
-use crate::{AsSize, IconError, Icon, Image};
-use image::DynamicImage;
+use crate::{AsSize, IconError, Icon, Image, Keys, Warning};
+use image::{DynamicImage, GenericImageView, ImageFormat, RgbaImage};
 use std::{
+    collections::hash_map::DefaultHasher,
     convert::TryFrom,
     fmt::{self, Debug, Formatter},
-    io::{self, Write},
+    hash::{Hash, Hasher},
+    io::{self, Cursor, Read, Write},
     result,
+    str::FromStr,
+};
+#[cfg(feature = "std-fs")]
+use std::{
+    fs::{DirBuilder, File},
+    io::BufWriter,
+    path::Path,
 };
 
+const ICO_HEADER_LENGTH: usize = 6;
+const ICO_ENTRY_LENGTH: usize = 16;
+const DEFAULT_MASK_THRESHOLD: u8 = 128;
+const RECOMMENDED_SIZES: [u32; 4] = [16, 32, 48, 256];
+const DEBUG_MAX_ENTRIES: usize = 32;
+// The number of entries is stored as a 16-bit integer
+const MAX_ENTRIES: usize = 65535;
+// Every size from 1x1 to 256x256
+const SUPPORTED_SIZES: [u32; 256] = [
+    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+    17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32,
+    33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48,
+    49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64,
+    65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80,
+    81, 82, 83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95, 96,
+    97, 98, 99, 100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111, 112,
+    113, 114, 115, 116, 117, 118, 119, 120, 121, 122, 123, 124, 125, 126, 127, 128,
+    129, 130, 131, 132, 133, 134, 135, 136, 137, 138, 139, 140, 141, 142, 143, 144,
+    145, 146, 147, 148, 149, 150, 151, 152, 153, 154, 155, 156, 157, 158, 159, 160,
+    161, 162, 163, 164, 165, 166, 167, 168, 169, 170, 171, 172, 173, 174, 175, 176,
+    177, 178, 179, 180, 181, 182, 183, 184, 185, 186, 187, 188, 189, 190, 191, 192,
+    193, 194, 195, 196, 197, 198, 199, 200, 201, 202, 203, 204, 205, 206, 207, 208,
+    209, 210, 211, 212, 213, 214, 215, 216, 217, 218, 219, 220, 221, 222, 223, 224,
+    225, 226, 227, 228, 229, 230, 231, 232, 233, 234, 235, 236, 237, 238, 239, 240,
+    241, 242, 243, 244, 245, 246, 247, 248, 249, 250, 251, 252, 253, 254, 255, 256,
+];
+
 /// An ecoder for the `.ico` file format.
 #[derive(Clone)]
 pub struct Ico {
     icon_dir: ico::IconDir,
     keys: Vec<u32>,
+    encoding: IcoEncoding,
+    mask_threshold: u8,
+    bit_depth: BitDepth,
+    dither: bool,
+    max_entries: usize,
+    strict: bool,
+}
+
+/// The color depth of the _BMP_ entries of an `Ico`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BitDepth {
+    /// Entries are quantized to a palette of at most 16 colors.
+    Four,
+    /// Entries are quantized to a palette of at most 256 colors.
+    Eight,
+    /// Entries are encoded in full color, along with their alpha channel.
+    ThirtyTwo,
+}
+
+/// The policy used by `Ico` to choose the encoding of its entries.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum IcoEncoding {
+    /// Encodes _256x256_ entries as _PNG_ and smaller entries as _BMP_,
+    /// for compatibility with legacy _Windows_ shells.
+    Auto,
+    /// Encodes every entry as _PNG_.
+    AllPng,
+    /// Encodes every entry as _BMP_.
+    AllBmp,
 }
 
 /// The _key-type_ for `Ico`. Note that `Key(0)` represents
 /// a _256x256_ entry.
+///
+/// `Key` only describes square entries, hence it relies on the
+/// default implementation of `AsSize::as_dimensions`.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Key(pub u8);
 
+impl Ico {
+    /// Creates a new icon which encodes its entries according to `encoding`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let icon = Ico::with_encoding(IcoEncoding::AllPng);
+    /// ```
+    pub fn with_encoding(encoding: IcoEncoding) -> Self {
+        let mut icon = Ico::new();
+        icon.encoding = encoding;
+
+        icon
+    }
+
+    /// Returns the policy used to choose the encoding of new entries.
+    pub fn encoding(&self) -> IcoEncoding {
+        self.encoding
+    }
+
+    /// Sets the policy used to choose the encoding of new entries.
+    ///
+    /// Entries which have already been added are left unchanged.
+    pub fn set_encoding(&mut self, encoding: IcoEncoding) -> &mut Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Returns the alpha threshold used to derive the _1-bit_ masks of _BMP_ entries.
+    pub fn mask_threshold(&self) -> u8 {
+        self.mask_threshold
+    }
+
+    /// Sets the alpha threshold used to derive the _1-bit_ masks of _BMP_ entries.
+    ///
+    /// Legacy _Windows_ shells ignore the alpha channel of _BMP_ entries
+    /// and rely on their _AND_ mask for transparency instead. Pixels with an
+    /// alpha value below `threshold` are masked out, while the remaining ones
+    /// are rendered as opaque. Defaults to `128`.
+    ///
+    /// Entries which have already been added are left unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// icon.set_mask_threshold(64);
+    /// ```
+    pub fn set_mask_threshold(&mut self, threshold: u8) -> &mut Self {
+        self.mask_threshold = threshold;
+        self
+    }
+
+    /// Returns the color depth of new _BMP_ entries.
+    pub fn bit_depth(&self) -> BitDepth {
+        self.bit_depth
+    }
+
+    /// Sets the color depth of new _BMP_ entries. Defaults to `BitDepth::ThirtyTwo`.
+    ///
+    /// Entries of depth `BitDepth::Four` or `BitDepth::Eight` are quantized to
+    /// a median-cut palette derived from the rasterized image, and their alpha
+    /// channel is reduced to the _1-bit_ mask described by `Ico::set_mask_threshold`.
+    /// Note that palettes of up to 256 colors may still be encoded at _24 bits_
+    /// per pixel for entries smaller than _512_ pixels, where it is more compact.
+    /// _PNG_ entries are not affected.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut icon = Ico::with_encoding(IcoEncoding::AllBmp);
+    /// icon.set_bit_depth(BitDepth::Four);
+    /// ```
+    pub fn set_bit_depth(&mut self, depth: BitDepth) -> &mut Self {
+        self.bit_depth = depth;
+        self
+    }
+
+    /// Returns `true` if quantized entries are dithered.
+    pub fn dither(&self) -> bool {
+        self.dither
+    }
+
+    /// Sets whether entries quantized by `Ico::set_bit_depth` are dithered
+    /// using _Floyd-Steinberg_ error diffusion, which trades the banding
+    /// of smooth gradients for noise. Defaults to `false`.
+    ///
+    /// Dithering only affects the _RGB_ channels of opaque pixels: the
+    /// alpha channel is still reduced to a _1-bit_ mask. Entries of depth
+    /// `BitDepth::ThirtyTwo` are not affected.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut icon = Ico::with_encoding(IcoEncoding::AllBmp);
+    /// icon.set_bit_depth(BitDepth::Four).set_dither(true);
+    /// ```
+    pub fn set_dither(&mut self, dither: bool) -> &mut Self {
+        self.dither = dither;
+        self
+    }
+
+    /// Returns the maximum number of entries of the icon.
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    /// Sets the maximum number of entries of the icon, beyond which
+    /// `Icon::add_entry` and `Ico::merge` fail with `IconError::TooManyEntries`.
+    ///
+    /// Defaults to _65535_, the limit of the `.ico` file format. Larger
+    /// values are capped to that limit.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut icon = Ico::new();
+    /// icon.set_max_entries(4);
+    /// ```
+    pub fn set_max_entries(&mut self, max: usize) -> &mut Self {
+        self.max_entries = max.min(MAX_ENTRIES);
+        self
+    }
+
+    /// Returns `true` if the icon is in _strict mode_.
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Sets whether the icon is in _strict mode_. Defaults to `false`.
+    ///
+    /// In _strict mode_, adding an entry larger than a _raster_ source
+    /// image fails with `IconError::SourceTooSmall` instead of upscaling
+    /// the source, which produces blurry entries. _Vector graphics_ and
+    /// _generated images_ are resolution-independent and always accepted.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut icon = Ico::new();
+    /// icon.set_strict(true);
+    ///
+    /// // Fails if `src` is smaller than 256x256
+    /// icon.add_entry(resample::linear, &src, Key(0))?;
+    /// ```
+    pub fn set_strict(&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Writes each entry of the icon to `dir` as a _PNG_ file named
+    /// `entry_{w}x{h}.png`, creating `dir` if it does not exist.
+    ///
+    /// _BMP_ entries are decoded to _RGBA_ before being written. This is
+    /// mostly useful for inspecting icons read with `Ico::from_reader`.
+    ///
+    /// This method is only available with the `std-fs` feature.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let icon = Ico::from_reader(File::open("favicon.ico")?)?;
+    /// icon.extract_all("./favicon/")?;
+    /// ```
+    #[cfg(feature = "std-fs")]
+    pub fn extract_all<P: AsRef<Path>>(&self, dir: P) -> io::Result<()> {
+        let dir = dir.as_ref();
+
+        if !dir.exists() {
+            let mut builder = DirBuilder::new();
+            builder.recursive(true).create(dir)?;
+        }
+
+        for entry in self.icon_dir.entries() {
+            let name = format!("entry_{}x{}.png", entry.width(), entry.height());
+            let file = BufWriter::new(File::create(dir.join(name))?);
+
+            entry.decode()?.write_png(file)?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates a new icon containing the standard set of
+    /// _Windows_ icon sizes: _16x16_, _24x24_, _32x32_, _48x48_,
+    /// _64x64_, _128x128_ and _256x256_.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::WhileAdding(size, _))` if the resampling filter
+    ///   provided in the `filter` argument fails or produces results of dimensions
+    ///   other than the ones specified by the standard sizes.
+    /// * Otherwise returns `Ok(ico)`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let src = Image::open("image.svg")?;
+    /// let icon = Ico::standard_windows(resample::linear, &src)?;
+    /// ```
+    pub fn standard_windows<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        filter: F,
+        source: &Image,
+    ) -> Result<Self, IconError<Key>> {
+        let keys = [16, 24, 32, 48, 64, 128, 0];
+        let mut icon = Ico::with_capacity(keys.len());
+
+        icon.add_entries(filter, source, keys.iter().map(|&k| Key(k)))?;
+        Ok(icon)
+    }
+
+    /// Reads an existing `.ico` file, so that new entries
+    /// can be appended to it.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidData))` if
+    ///   the file is not a valid icon file.
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if
+    ///   the file contains non-square entries or entries of unsupported
+    ///   dimensions.
+    /// * Otherwise returns `Ok(ico)`.
+    ///
+    /// If the file contains several entries of the same size, such as
+    /// entries of different color depths, only the one with the most
+    /// bits per pixel is kept.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let file = File::open("favicon.ico")?;
+    /// let mut icon = Ico::from_reader(file)?;
+    ///
+    /// icon.add_entries(resample::linear, &src, vec![Key(32), Key(48)])?;
+    /// ```
+    pub fn from_reader<R: Read>(mut r: R) -> io::Result<Self> {
+        // ico::IconDir::read requires the reader to be seekable
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+
+        let icon_dir = ico::IconDir::read(Cursor::new(buf))?;
+
+        if icon_dir.resource_type() != ico::ResourceType::Icon {
+            return Err(io::Error::from(io::ErrorKind::InvalidData));
+        }
+
+        // Files often contain several color depths of the same
+        // size, of which only the deepest one is kept
+        let mut best: Vec<&ico::IconDirEntry> = Vec::with_capacity(icon_dir.entries().len());
+
+        for entry in icon_dir.entries() {
+            if entry.width() != entry.height() {
+                return Err(io::Error::from(io::ErrorKind::InvalidInput));
+            }
+
+            match best.iter_mut().find(|other| other.width() == entry.width()) {
+                Some(other) if entry.bits_per_pixel() > other.bits_per_pixel() => *other = entry,
+                Some(_) => (),
+                None => best.push(entry),
+            }
+        }
+
+        let mut entries = ico::IconDir::new(ico::ResourceType::Icon);
+        let mut keys = Vec::with_capacity(best.len());
+
+        for entry in best {
+            keys.push(Key::try_from(entry.width())?.as_size());
+            entries.add_entry(entry.clone());
+        }
+
+        Ok(Ico {
+            icon_dir: entries,
+            keys,
+            encoding: IcoEncoding::Auto,
+            mask_threshold: DEFAULT_MASK_THRESHOLD,
+            bit_depth: BitDepth::ThirtyTwo,
+            dither: false,
+            max_entries: MAX_ENTRIES,
+            strict: false,
+        })
+    }
+
+    /// Copies all the entries of `other` into `self`, without
+    /// re-encoding them.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::AlreadyIncluded(key))` if `self` already
+    ///   contains an entry associated with a key of `other`, in which
+    ///   case `self` is left unchanged.
+    /// * Returns `Err(IconError::TooManyEntries(max))` if the merged
+    ///   icon would contain more than `self.max_entries()` entries, in
+    ///   which case `self` is left unchanged.
+    /// * Otherwise returns `Ok(())`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut icon = Ico::new();
+    /// icon.add_entry(resample::linear, &src, Key(32))?;
+    ///
+    /// let mut large = Ico::new();
+    /// large.add_entry(resample::linear, &src, Key(0))?;
+    ///
+    /// icon.merge(&large)?;
+    /// ```
+    pub fn merge(&mut self, other: &Self) -> Result<(), IconError<Key>> {
+        if let Some(key) = other.keys().find(|&key| self.contains(key)) {
+            return Err(IconError::AlreadyIncluded(key));
+        }
+
+        check_entry_count(self.keys.len(), other.keys.len(), self.max_entries)?;
+
+        for entry in other.icon_dir.entries() {
+            self.icon_dir.add_entry(entry.clone());
+        }
+
+        self.keys.extend_from_slice(&other.keys);
+        Ok(())
+    }
+
+    /// Returns an iterator over the keys of the entries of the icon,
+    /// along with their encoded data, either as _PNG_ or as _BMP_.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// for (key, data) in icon.entries() {
+    ///     if data.starts_with(b"\x89PNG") {
+    ///         optimized.push((key, optimize(data)?));
+    ///     }
+    /// }
+    /// ```
+    pub fn entries(&self) -> impl Iterator<Item = (Key, &[u8])> + '_ {
+        self.keys
+            .iter()
+            .zip(self.icon_dir.entries())
+            .map(|(&size, entry)| {
+                let key = Key::try_from(size).expect("The size should be supported by ICO");
+                (key, entry.data())
+            })
+    }
+
+    /// Returns the length in bytes of the output of `self.write`,
+    /// without encoding the icon.
+    ///
+    /// This is the length of the icon directory, of _16_ bytes per
+    /// entry plus a _6_ byte header, plus the length of the encoded
+    /// data of each entry.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// if icon.encoded_len() > BUDGET {
+    ///     icon.remove_entry(Key(0));
+    /// }
+    /// ```
+    pub fn encoded_len(&self) -> usize {
+        let entries = self.icon_dir.entries();
+        let data_len: usize = entries.iter().map(|entry| entry.data().len()).sum();
+
+        ICO_HEADER_LENGTH + ICO_ENTRY_LENGTH * entries.len() + data_len
+    }
+
+    /// Returns a hash of the entries of the icon, which does not
+    /// depend on the order the entries were added in.
+    ///
+    /// The hash is only guaranteed to be stable for a single
+    /// build of the program.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+
+        hasher.finish()
+    }
+
+    /// Returns the sizes of the entries of the icon along
+    /// with their encoded data, sorted by size.
+    fn sorted_entries(&self) -> Vec<(u32, &[u8])> {
+        let mut entries: Vec<(u32, &[u8])> = self
+            .icon_dir
+            .entries()
+            .iter()
+            .map(|entry| (entry.width(), entry.data()))
+            .collect();
+
+        entries.sort();
+        entries
+    }
+
+    /// Returns the entries of the icon directory, sorted by size.
+    pub(crate) fn dir_entries(&self) -> Vec<&ico::IconDirEntry> {
+        let mut entries: Vec<&ico::IconDirEntry> = self.icon_dir.entries().iter().collect();
+
+        entries.sort_by_key(|entry| entry.width());
+        entries
+    }
+
+    /// Replaces the encoded data of the entry associated with `key`
+    /// by the _PNG_ file in `data`, without re-encoding it.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(io::Error::from(io::ErrorKind::NotFound))` if the
+    ///   icon contains no entry associated with `key`.
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidData))` if
+    ///   `data` is not a valid _PNG_ file.
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if
+    ///   the dimensions of the image in `data` do not match `key`.
+    /// * Otherwise returns `Ok(())`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let data = oxipng::optimize_from_memory(data, &oxipng::Options::default())?;
+    /// icon.replace_entry(key, &data)?;
+    /// ```
+    pub fn replace_entry(&mut self, key: Key, data: &[u8]) -> io::Result<()> {
+        let size = key.as_size();
+        let index = self
+            .keys
+            .iter()
+            .position(|&k| k == size)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+
+        let png = image::load_from_memory_with_format(data, ImageFormat::PNG)
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+
+        if png.dimensions() != key.as_dimensions() {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        // ico::IconDirEntry can't be constructed from encoded data,
+        // so it is read back from a single-entry icon file
+        let mut buf = Vec::with_capacity(22 + data.len());
+        buf.extend_from_slice(&[0, 0, 1, 0, 1, 0]);
+        buf.extend_from_slice(&[key.0, key.0, 0, 0, 1, 0, 32, 0]);
+        buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&22u32.to_le_bytes());
+        buf.extend_from_slice(data);
+
+        let entry = ico::IconDir::read(Cursor::new(buf))?.entries()[0].clone();
+
+        // ico::IconDir does not support replacing entries,
+        // so the directory has to be rebuilt
+        let mut icon_dir = ico::IconDir::new(ico::ResourceType::Icon);
+
+        for (i, e) in self.icon_dir.entries().iter().enumerate() {
+            icon_dir.add_entry(if i == index { entry.clone() } else { e.clone() });
+        }
+
+        self.icon_dir = icon_dir;
+        Ok(())
+    }
+}
+
 impl Icon for Ico {
     type Key = Key;
 
@@ -30,6 +553,12 @@ impl Icon for Ico {
         Ico {
             icon_dir: ico::IconDir::new(ico::ResourceType::Icon),
             keys: Vec::with_capacity(capacity),
+            encoding: IcoEncoding::Auto,
+            mask_threshold: DEFAULT_MASK_THRESHOLD,
+            bit_depth: BitDepth::ThirtyTwo,
+            dither: false,
+            max_entries: MAX_ENTRIES,
+            strict: false,
         }
     }
 
@@ -37,6 +566,36 @@ impl Icon for Ico {
         self.icon_dir.entries().len()
     }
 
+    /// Returns the number of keys the icon can hold without reallocating.
+    ///
+    /// See `Ico::reserve` for details.
+    fn capacity(&self) -> usize {
+        self.keys.capacity()
+    }
+
+    /// Reserves capacity for at least `additional` more keys.
+    ///
+    /// Note that only the keys of the icon are reserved: `ico::IconDir`
+    /// does not expose its entries, which therefore cannot be reserved
+    /// and may still be reallocated as new entries are added.
+    fn reserve(&mut self, additional: usize) {
+        self.keys.reserve(additional);
+    }
+
+    fn keys(&self) -> Keys<Self::Key> {
+        let keys: Vec<Key> = self
+            .keys
+            .iter()
+            .map(|&size| Key::try_from(size).expect("The size should be supported by ICO"))
+            .collect();
+
+        Keys::from(keys)
+    }
+
+    fn contains(&self, key: Self::Key) -> bool {
+        self.keys.contains(&key.as_size())
+    }
+
     fn add_entry<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
         &mut self,
         filter: F,
@@ -49,36 +608,181 @@ impl Icon for Ico {
             return Err(IconError::AlreadyIncluded(key));
         }
 
-        let icon = source.rasterize(filter, size)?;
-        let data = icon.to_rgba().into_vec();
-        let image = ico::IconImage::from_rgba_data(size, size, data);
+        check_entry_count(self.keys.len(), 1, self.max_entries)?;
+
+        let (w, h) = key.as_dimensions();
+
+        if let Image::Raster(ras) = source {
+            let (sw, sh) = ras.dimensions();
+
+            if self.strict && (sw < w || sh < h) {
+                return Err(IconError::SourceTooSmall { source: (sw, sh), requested: size });
+            }
+        }
+
+        let icon = source.rasterize_to(filter, (w, h))?;
+        let mut data = icon.to_rgba().into_vec();
+
+        let bmp = match self.encoding {
+            IcoEncoding::Auto => size < 256,
+            IcoEncoding::AllPng => false,
+            IcoEncoding::AllBmp => true,
+        };
+
+        let entry = if bmp {
+            match self.bit_depth {
+                BitDepth::Four => quantize(&mut data, w, 16, self.mask_threshold, self.dither),
+                BitDepth::Eight => quantize(&mut data, w, 256, self.mask_threshold, self.dither),
+                BitDepth::ThirtyTwo => {}
+            }
+
+            let image = ico::IconImage::from_rgba_data(w, h, data);
+            let entry = ico::IconDirEntry::encode_as_bmp(&image)?;
 
-        let entry = ico::IconDirEntry::encode(&image)?;
+            threshold_mask(entry, image.rgba_data(), (w, h), self.mask_threshold)?
+        } else {
+            ico::IconDirEntry::encode_as_png(&ico::IconImage::from_rgba_data(w, h, data))?
+        };
         self.icon_dir.add_entry(entry);
+        self.keys.push(size);
 
         Ok(())
     }
 
+    fn get_entry(&self, key: Self::Key) -> Option<DynamicImage> {
+        let (w, h) = key.as_dimensions();
+
+        let entry = self
+            .icon_dir
+            .entries()
+            .iter()
+            .find(|entry| entry.width() == w && entry.height() == h)?;
+
+        let image = entry.decode().ok()?;
+        let buf = RgbaImage::from_raw(w, h, image.rgba_data().to_vec())?;
+
+        Some(DynamicImage::ImageRgba8(buf))
+    }
+
+    /// Reports the entries recommended for _Windows_ icons which are missing
+    /// from the icon, namely _16x16_, _32x32_, _48x48_ and _256x256_, as well
+    /// as the entries reported by `Icon::duplicate_content`.
+    fn validate(&self) -> Vec<Warning<Self::Key>> {
+        RECOMMENDED_SIZES
+            .iter()
+            .filter(|size| !self.keys.contains(size))
+            .map(|&size| Key::try_from(size).expect("The size should be supported by ICO"))
+            .map(Warning::MissingRecommendedSize)
+            .chain(self.duplicate_content().into_iter().map(|(a, b)| Warning::DuplicateContent(a, b)))
+            .collect()
+    }
+
+    fn remove_entry(&mut self, key: Self::Key) -> bool {
+        let size = key.as_size();
+
+        if let Some(index) = self.keys.iter().position(|&k| k == size) {
+            self.keys.remove(index);
+
+            // ico::IconDir does not support removing entries,
+            // so the directory has to be rebuilt
+            let mut icon_dir = ico::IconDir::new(ico::ResourceType::Icon);
+
+            for entry in self.icon_dir.entries() {
+                if entry.width() != size {
+                    icon_dir.add_entry(entry.clone());
+                }
+            }
+
+            self.icon_dir = icon_dir;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn clear(&mut self) {
+        // ico::IconDir does not support removing entries
+        self.icon_dir = ico::IconDir::new(ico::ResourceType::Icon);
+        self.keys.clear();
+    }
+
     fn write<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
-        self.icon_dir.write(w)
+        // Entries are written in ascending order of size, regardless of
+        // the order in which they were added, so that the output is
+        // reproducible
+        let mut icon_dir = ico::IconDir::new(ico::ResourceType::Icon);
+        for entry in self.dir_entries() {
+            icon_dir.add_entry(entry.clone());
+        }
+
+        icon_dir.write(w)
+    }
+}
+
+impl IntoIterator for &Ico {
+    type Item = Key;
+    type IntoIter = Keys<Key>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys()
+    }
+}
+
+impl PartialEq for Ico {
+    /// Two icons are equal if they contain the same entries, with the
+    /// same encoded data, regardless of the order they were added in.
+    fn eq(&self, other: &Self) -> bool {
+        self.sorted_entries() == other.sorted_entries()
+    }
+}
+
+impl Eq for Ico {}
+
+impl Hash for Ico {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sorted_entries().hash(state);
     }
 }
 
 impl Debug for Ico {
     fn fmt(&self, f: &mut Formatter) -> result::Result<(), fmt::Error> {
-        let n_entries = self.icon_dir.entries().len();
-        let mut entries_str = String::with_capacity(42 * n_entries);
+        let entries = self.icon_dir.entries();
+        let mut entries_strs: Vec<String> = entries
+            .iter()
+            .take(DEBUG_MAX_ENTRIES)
+            .map(|entry| format!("{}x{}", entry.width(), entry.height()))
+            .collect();
 
-        for _ in 0..n_entries {
-            entries_str.push_str("ico::IconDirEntry {{ /* fields omitted */ }}, ");
+        if entries.len() > DEBUG_MAX_ENTRIES {
+            entries_strs.push(format!("... {} more", entries.len() - DEBUG_MAX_ENTRIES));
         }
 
-        let icon_dir = format!(
-            "ico::IconDir {{ restype: ico::ResourceType::Icon, entries: [{:?}] }}",
-            entries_str
-        );
+        write!(f, "iconwriter::Ico {{ entries: [{}] }}", entries_strs.join(", "))
+    }
+}
+
+impl Key {
+    /// Returns the sizes supported by the `.ico` file format, that is,
+    /// every size from _1x1_ to _256x256_ in ascending order.
+    ///
+    /// Each of these sizes can be converted to a `Key` with `Key::try_from`.
+    pub fn supported_sizes() -> &'static [u32] {
+        &SUPPORTED_SIZES
+    }
 
-        write!(f, "iconwriter::Ico {{ icon_dir: {} }} ", icon_dir)
+    /// Returns the key of the supported size closest to `size`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// assert_eq!(Key::nearest_supported(512), Key(0));
+    /// ```
+    pub fn nearest_supported(size: u32) -> Self {
+        match size {
+            0 => Key(1),
+            n if n < 256 => Key(n as u8),
+            _ => Key(0),
+        }
     }
 }
 
@@ -92,6 +796,22 @@ impl AsSize for Key {
     }
 }
 
+impl FromStr for Key {
+    type Err = io::Error;
+
+    /// Parses sizes such as `"32"` or `"32x32"`.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(_)` with a descriptive message if `s` is
+    ///   malformed, describes a non-square entry or a size
+    ///   unsupported by the format.
+    /// * Otherwise returns `Ok(key)`.
+    fn from_str(s: &str) -> io::Result<Self> {
+        crate::parse_square_key(s, "ICO", Key::try_from)
+    }
+}
+
 impl TryFrom<u32> for Key {
     type Error = io::Error;
 
@@ -104,3 +824,200 @@ impl TryFrom<u32> for Key {
         }
     }
 }
+
+/// Checks that an icon containing `len` entries can hold
+/// `additional` more without exceeding `max` entries.
+fn check_entry_count(len: usize, additional: usize, max: usize) -> Result<(), IconError<Key>> {
+    if len + additional > max {
+        Err(IconError::TooManyEntries(max))
+    } else {
+        Ok(())
+    }
+}
+
+/// Rewrites the _AND_ mask of the _BMP_ encoded `entry`, masking out
+/// the pixels of `rgba` whose alpha value is below `threshold`.
+fn threshold_mask(
+    entry: ico::IconDirEntry,
+    rgba: &[u8],
+    (w, h): (u32, u32),
+    threshold: u8,
+) -> io::Result<ico::IconDirEntry> {
+    // ico::IconDirEntry does not expose its data mutably, so the
+    // entry is written to a single-entry icon file and read back
+    let mut icon_dir = ico::IconDir::new(ico::ResourceType::Icon);
+    icon_dir.add_entry(entry);
+
+    let mut buf = Vec::new();
+    icon_dir.write(&mut buf)?;
+
+    // The mask is stored bottom-up at the end of the entry,
+    // with each row padded to a multiple of 32 bits
+    let (w, h) = (w as usize, h as usize);
+    let row_size = 4 * ((w + 31) >> 5);
+    let mask_start = buf.len() - row_size * h;
+    let mask = &mut buf[mask_start..];
+
+    for byte in mask.iter_mut() {
+        *byte = 0;
+    }
+
+    for y in 0..h {
+        let row = (h - y - 1) * row_size;
+
+        for x in 0..w {
+            if rgba[4 * (y * w + x) + 3] < threshold {
+                mask[row + x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    }
+
+    Ok(ico::IconDir::read(Cursor::new(buf))?.entries()[0].clone())
+}
+
+/// Reduces the colors of the pixels of `rgba` to a median-cut palette of at
+/// most `n_colors` colors, making the pixels whose alpha value is below
+/// `threshold` fully transparent and the remaining ones fully opaque.
+///
+/// If `dither` is `true`, the quantization error of each opaque pixel is
+/// diffused to its opaque neighbours using _Floyd-Steinberg_ dithering,
+/// assuming `rgba` is an image `width` pixels wide.
+fn quantize(rgba: &mut [u8], width: u32, n_colors: usize, threshold: u8, dither: bool) {
+    let colors: Vec<[u8; 3]> = rgba
+        .chunks(4)
+        .filter(|pixel| pixel[3] >= threshold)
+        .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+
+    let palette = median_cut(colors, n_colors);
+    let width = width as usize;
+    let opaque: Vec<bool> = rgba.chunks(4).map(|pixel| pixel[3] >= threshold).collect();
+    // The error diffused to each pixel, in sixteenths
+    let mut errors = vec![[0i32; 3]; if dither { opaque.len() } else { 0 }];
+
+    for (i, pixel) in rgba.chunks_mut(4).enumerate() {
+        // Transparent pixels reuse a palette color,
+        // so that they don't take up a palette entry
+        if !opaque[i] {
+            pixel[..3].copy_from_slice(&palette.first().cloned().unwrap_or([0, 0, 0]));
+            pixel[3] = 0;
+            continue;
+        }
+
+        let mut target = [pixel[0], pixel[1], pixel[2]];
+
+        if dither {
+            for c in 0..3 {
+                target[c] = clamp_u8(i32::from(target[c]) + errors[i][c] / 16);
+            }
+        }
+
+        let color = nearest_color(&palette, target);
+
+        if dither {
+            let (x, y) = ((i % width) as isize, i / width);
+
+            for &(dx, dy, weight) in &[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)] {
+                let nx = x + dx;
+
+                if nx < 0 || nx >= width as isize {
+                    continue;
+                }
+
+                // Only opaque pixels are dithered
+                let j = (y + dy) * width + nx as usize;
+
+                if j < opaque.len() && opaque[j] {
+                    for c in 0..3 {
+                        errors[j][c] += (i32::from(target[c]) - i32::from(color[c])) * weight;
+                    }
+                }
+            }
+        }
+
+        pixel[..3].copy_from_slice(&color);
+        pixel[3] = 255;
+    }
+}
+
+/// Clamps `value` to the range of a `u8`.
+fn clamp_u8(value: i32) -> u8 {
+    if value <= 0 {
+        0
+    } else if value >= 255 {
+        255
+    } else {
+        value as u8
+    }
+}
+
+/// Computes a palette of at most `n_colors` colors for `colors` by repeatedly
+/// splitting the box with the widest channel range at its median.
+fn median_cut(colors: Vec<[u8; 3]>, n_colors: usize) -> Vec<[u8; 3]> {
+    // Returns the channel with the widest range and its range
+    fn widest_channel(colors: &[[u8; 3]]) -> (usize, u8) {
+        (0..3)
+            .map(|c| {
+                let min = colors.iter().map(|color| color[c]).min().unwrap_or(0);
+                let max = colors.iter().map(|color| color[c]).max().unwrap_or(0);
+                (c, max - min)
+            })
+            .max_by_key(|&(_, range)| range)
+            .unwrap_or((0, 0))
+    }
+
+    let mut boxes = if colors.is_empty() { Vec::new() } else { vec![colors] };
+
+    while boxes.len() < n_colors {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .map(|(i, colors)| (i, widest_channel(colors)))
+            .filter(|&(_, (_, range))| range > 0)
+            .max_by_key(|&(_, (_, range))| range);
+
+        let (i, c) = match widest {
+            Some((i, (c, _))) => (i, c),
+            None => break,
+        };
+
+        let mut colors = boxes.swap_remove(i);
+        colors.sort_by_key(|color| color[c]);
+
+        let upper = colors.split_off(colors.len() / 2);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .map(|colors| {
+            let n = colors.len() as u64;
+            let mut sum = [0u64; 3];
+
+            for color in colors {
+                for c in 0..3 {
+                    sum[c] += u64::from(color[c]);
+                }
+            }
+
+            [(sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8]
+        })
+        .collect()
+}
+
+/// Returns the color of `palette` closest to `color`.
+fn nearest_color(palette: &[[u8; 3]], color: [u8; 3]) -> [u8; 3] {
+    palette
+        .iter()
+        .cloned()
+        .min_by_key(|candidate| {
+            (0..3)
+                .map(|c| {
+                    let d = i32::from(candidate[c]) - i32::from(color[c]);
+                    d * d
+                })
+                .sum::<i32>()
+        })
+        .unwrap_or(color)
+}
@@ -94,33 +94,61 @@
 pub extern crate image;
 pub extern crate resvg;
 
-use crate::usvg::Tree;
-use image::{DynamicImage, GenericImageView, ImageError};
+use crate::{cache::RasterCache, usvg::{NodeExt, Tree}};
+use image::{DynamicImage, GenericImageView, ImageError, ImageFormat, Rgba, RgbaImage};
 pub use resvg::{
     raqote,
     usvg::{self, XmlIndent, XmlOptions},
 };
 use std::{
+    borrow::Borrow,
     convert::From,
     error,
     fmt::{self, Debug, Display, Formatter},
-    fs::File,
-    io::{self, Write},
-    path::Path,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    io::{self, Cursor, Read, Write},
+    iter::once,
+    sync::Arc,
 };
+#[cfg(feature = "std-fs")]
+use crate::{icns::Icns, ico::Ico};
+#[cfg(feature = "std-fs")]
+use std::{convert::TryFrom, fs::File, path::Path};
 
+pub mod ani;
+#[cfg(feature = "std-fs")]
+pub mod bundle;
+pub mod cache;
+pub mod convert;
+pub mod diff;
+#[cfg(feature = "pe")]
+pub mod embed;
 pub mod favicon;
 pub mod icns;
 pub mod ico;
+pub mod png_sequence;
 pub mod resample;
 pub mod encode;
+#[cfg(feature = "serde")]
+mod serialize;
 #[cfg(test)]
 mod test;
 
 const STD_CAPACITY: usize = 7;
+const DOMINANT_COLOR_SIZE: u32 = 16;
 const MISMATCHED_DIM_ERR: &str =
     "a resampling filter returned an image of dimensions other than the ones specified by it's arguments";
 
+/// The default size limit, in pixels, of the entries of the icon formats
+/// supporting arbitrary sizes, such as `Favicon` and `PngSequence`.
+///
+/// Requesting larger entries fails with `IconError::InvalidSize` instead
+/// of allocating a buffer of several gigabytes. The limit can be raised
+/// with the `max_size` method of the icon, such as `Favicon::max_size`.
+/// `Ico` and `Icns` entries are already bounded by their file formats.
+pub const DEFAULT_MAX_SIZE: u32 = 4096;
+
 /// A generic representation of an icon encoder.
 pub trait Icon
 where
@@ -151,6 +179,54 @@ where
     /// Returns the number of _entries_ contained in the icon.
     fn len(&self) -> usize;
 
+    /// Returns `true` if the icon includes no _entries_.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of _entries_ the icon can hold without reallocating.
+    ///
+    /// Encoders that do not keep track of their capacity report their length.
+    fn capacity(&self) -> usize {
+        self.len()
+    }
+
+    /// Reserves capacity for at least `additional` more _entries_.
+    ///
+    /// The default implementation does nothing. Encoders may also only be
+    /// able to reserve part of their storage, as is the case of `Ico`.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let mut icon = Ico::new();
+    /// icon.reserve(20);
+    ///
+    /// assert!(icon.capacity() >= 20);
+    /// ```
+    fn reserve(&mut self, _additional: usize) {}
+
+    /// Returns an iterator over the keys of the _entries_ contained in the icon.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// for key in icon.keys() {
+    ///     println!("{}x{}", key.as_size(), key.as_size());
+    /// }
+    /// ```
+    fn keys(&self) -> Keys<Self::Key>;
+
+    /// Returns `true` if the icon includes an entry associated with `key`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// if !icon.contains(Key(32)) {
+    ///     icon.add_entry(resample::linear, &src, Key(32))?;
+    /// }
+    /// ```
+    fn contains(&self, key: Self::Key) -> bool;
+
     /// Adds an individual entry to the icon.
     ///
     /// # Arguments
@@ -187,6 +263,49 @@ where
         key: Self::Key,
     ) -> Result<(), IconError<Self::Key>>;
 
+    /// Adds an individual entry to the icon, replacing the entry
+    /// associated with `key` if there is one.
+    ///
+    /// The previous entry is removed through `Icon::remove_entry`
+    /// before the new one is added, hence it is lost even if adding
+    /// the new entry fails.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::AlreadyIncluded(_))` if the icon already contains
+    ///   an entry associated with `key` and does not support removing entries.
+    /// * Returns `Err(IconError::Resample(_))` if the resampling filter provided in
+    ///   the `filter` argument fails produces results of dimensions other than the
+    ///   ones specified by `key`.
+    /// * Otherwise returns `Ok(())`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// use iconwriter::{ico::{Ico, Key}, resample, Image, Icon, IconError};
+    ///
+    /// fn example() -> Result<(), IconError<Key>> {
+    ///     let mut icon = Ico::new();
+    ///     let draft = Image::open("draft.svg")?;
+    ///     let src = Image::open("image.svg")?;
+    ///
+    ///     icon.set_entry(resample::linear, &draft, Key(32))?;
+    ///     icon.set_entry(resample::linear, &src, Key(32))
+    /// }
+    /// ```
+    fn set_entry<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        filter: F,
+        source: &Image,
+        key: Self::Key,
+    ) -> Result<(), IconError<Self::Key>>
+    where
+        Self::Key: Clone,
+    {
+        self.remove_entry(key.clone());
+        self.add_entry(filter, source, key)
+    }
+
     /// Adds a series of entries to the icon.
     ///
     /// # Arguments
@@ -199,9 +318,10 @@ where
     ///
     /// * Returns `Err(IconError::AlreadyIncluded(_))` if the icon already contains an
     ///   entry associated with any of the items of `keys`.
-    /// * Returns `Err(IconError::Resample(_))` if the resampling filter provided in
-    ///   the `filter` argument fails or produces results of dimensions other than the
-    ///   ones specified by the items of `keys`.
+    /// * Returns `Err(IconError::WhileAdding(size, _))` if the resampling filter
+    ///   provided in the `filter` argument fails or produces results of dimensions
+    ///   other than the ones specified by the items of `keys`, where `size` is the
+    ///   size of the entry that failed.
     /// * Otherwise returns `Ok(())`.
     ///
     /// # Example
@@ -227,165 +347,1424 @@ where
         keys: I,
     ) -> Result<(), IconError<Self::Key>> {
         for key in keys {
-            self.add_entry(|src, size| filter(src, size), source, key)?;
+            let size = key.as_size();
+
+            self.add_entry(|src, size| filter(src, size), source, key)
+                .map_err(|err| err.while_adding(size))?;
         }
 
         Ok(())
     }
 
-    /// Writes the contents of the icon to `w`.
+    /// Adds a series of entries to the icon, calling `progress` after each
+    /// of them is added.
     ///
-    /// # Example
+    /// `progress` is called with the key of the entry, its index and the
+    /// total number of entries, which makes it suitable for reporting the
+    /// progress of large icons.
     ///
-    /// ```rust, ignore
-    /// use iconwriter::favicon::Favicon;
-    /// use std::{io, fs::File};
-    ///  
-    /// fn example() -> io::Result<()> {
-    ///     let icon = Favicon::new();
+    /// # Return Value
     ///
-    ///     /* Process the icon */
+    /// See `Icon::add_entries`. `progress` is not called for the entry that failed
+    /// nor for any of the following ones.
     ///
-    ///     let file = File::create("out.icns")?;
-    ///     icon.write(file)
-    /// }
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// icon.add_entries_with_progress(resample::linear, &src, vec![Key(16), Key(32)], |key, i, n| {
+    ///     bar.set_value((i + 1) as f32 / n as f32);
+    /// })?;
     /// ```
-    fn write<W: Write>(&mut self, w: &mut W) -> io::Result<()>;
+    fn add_entries_with_progress<F, I, P>(
+        &mut self,
+        mut filter: F,
+        source: &Image,
+        keys: I,
+        mut progress: P,
+    ) -> Result<(), IconError<Self::Key>>
+    where
+        Self::Key: Clone,
+        F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>,
+        I: IntoIterator<Item = Self::Key>,
+        I::IntoIter: ExactSizeIterator,
+        P: FnMut(Self::Key, usize, usize),
+    {
+        let keys = keys.into_iter();
+        let total = keys.len();
 
-    /// Writes the contents of the icon to a file on disk.
+        for (i, key) in keys.enumerate() {
+            let size = key.as_size();
+
+            self.add_entry(|src, size| filter(src, size), source, key.clone())
+                .map_err(|err| err.while_adding(size))?;
+
+            progress(key, i, total);
+        }
+
+        Ok(())
+    }
+
+    /// Adds a series of entries to the icon, each of which is
+    /// re-scaled with its own resampling filter.
+    ///
+    /// Since all of the filters must have the same type, use function
+    /// pointers or boxed closures to mix different filters.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` A reference to the source image the entries will be based on.
+    /// * `entries` A container for the information on the target entries,
+    ///   along with the resampling filters used to re-scale `source` for them.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::AlreadyIncluded(_))` if the icon already contains an
+    ///   entry associated with any of the keys of `entries`.
+    /// * Returns `Err(IconError::WhileAdding(size, _))` if any of the resampling
+    ///   filters fails or produces results of dimensions other than the ones
+    ///   specified by its key, where `size` is the size of the entry that failed.
+    /// * Otherwise returns `Ok(())`.
     ///
     /// # Example
     ///
     /// ```rust, ignore
-    /// use iconwriter::favicon::Favicon;
-    /// use std::{io, fs::File};
-    ///  
-    /// fn example() -> io::Result<()> {
-    ///     let icon = Favicon::new();
+    /// use iconwriter::{Ico, Image, Icon, IconError, ico::Key, resample};
+    /// use image::DynamicImage;
+    /// use std::io;
     ///
-    ///     /* Process the icon */
+    /// type Filter = fn(&DynamicImage, u32) -> io::Result<DynamicImage>;
+    ///  
+    /// fn example() -> Result<(), IconError> {
+    ///     let mut icon = Ico::new();
+    ///     let src = Image::open("image.png")?;
+    ///     let nearest: Filter = resample::nearest;
+    ///     let lanczos3: Filter = resample::lanczos3;
     ///
-    ///     icon.save("./output/")
+    ///     icon.add_entries_with(
+    ///         &src,
+    ///         vec![(Key(16), nearest), (Key(0), lanczos3)]
+    ///     )
     /// }
     /// ```
-    fn save<P: AsRef<Path>>(&mut self, path: &P) -> io::Result<()> {
-        let mut file = File::create(path.as_ref())?;
-        self.write(&mut file)
-    }
-}
-
-/// A trait for types that represent the dimesions of an icon.
-pub trait AsSize {
-    fn as_size(&self) -> u32;
-}
-
-#[derive(Clone)]
-/// A uniun type for raster and vector graphics.
-pub enum Image {
-    /// A generic raster image.
-    Raster(DynamicImage),
-    /// A svg-encoded vector image.
-    Svg(Tree),
-}
+    fn add_entries_with<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>, I: IntoIterator<Item = (Self::Key, F)>>(
+        &mut self,
+        source: &Image,
+        entries: I,
+    ) -> Result<(), IconError<Self::Key>> {
+        for (key, filter) in entries {
+            let size = key.as_size();
 
-/// The error type for operations of the `Icon` trait.
-pub enum IconError<K: AsSize + Send + Sync> {
-    /// The `Icon` instance already includes an entry associated with this key.
-    AlreadyIncluded(K),
-    /// A resampling error.
-    Resample(ResReResampleError),
-}
+            self.add_entry(filter, source, key)
+                .map_err(|err| err.while_adding(size))?;
+        }
 
-#[derive(Debug)]
-/// The error type for resampling operations.
-pub enum ResReResampleError {
-    /// Generic I/O error.
-    Io(io::Error),
-    /// A resampling filter produced results of dimensions
-    /// other the ones specified by it's arguments.
-    MismatchedDimensions(u32, (u32, u32)),
-}
+        Ok(())
+    }
 
-impl Image {
-    /// Attempts to create a `Image` from a given path.
+    /// Adds a series of entries to the icon, each of which is based
+    /// on its own source image, such as hand-tuned images for
+    /// the smallest sizes.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` The resampling filter that will be used to re-scale the sources.
+    /// * `entries` A container for the information on the target entries,
+    ///   along with the source images they will be based on.
     ///
     /// # Return Value
-    /// 
-    /// * Returns `Ok(src)` if the file indicated by the `path` argument could be
-    ///   successfully parsed into an image.
-    /// * Returns `Err(io::Error::from(io::ErrorKind::Other))` if the image allocation failed
-    ///   or if the file was not able to be accessed.
-    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if the image format is not
-    ///   supported by `iconwriter`.
-    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidData))` otherwise.
+    ///
+    /// * Returns `Err(IconError::AlreadyIncluded(_))` if the icon already contains an
+    ///   entry associated with any of the keys of `entries`.
+    /// * Returns `Err(IconError::WhileAdding(size, _))` if the resampling filter
+    ///   provided in the `filter` argument fails or produces results of dimensions
+    ///   other than the ones specified by the keys of `entries`, where `size` is
+    ///   the size of the entry that failed.
+    /// * Otherwise returns `Ok(())`.
     ///
     /// # Example
+    ///
     /// ```rust, ignore
-    /// let img = Image::open("source.png")?;
+    /// use iconwriter::{Ico, Image, Icon, IconError, ico::Key};
+    ///  
+    /// fn example() -> Result<(), IconError> {
+    ///     let mut icon = Ico::new();
+    ///
+    ///     icon.add_entries_from(
+    ///         resample::linear,
+    ///         vec![
+    ///             (Image::open("16.png")?, Key(16)),
+    ///             (Image::open("32.png")?, Key(32)),
+    ///             (Image::open("master.svg")?, Key(0))
+    ///         ]
+    ///     )
+    /// }
     /// ```
-    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
-        match image::open(&path) {
-            Ok(img) => Ok(Image::from(img)),
-            Err(ImageError::InsufficientMemory) => Err(io::Error::from(io::ErrorKind::Other)),
-            Err(ImageError::IoError(err)) => Err(err),
-            Err(ImageError::UnsupportedError(_)) => {
-                match Tree::from_file(path, &usvg::Options::default()) {
-                    Ok(img) => Ok(Image::from(img)),
-                    Err(usvg::Error::InvalidFileSuffix) => {
-                        Err(io::Error::from(io::ErrorKind::InvalidInput))
-                    }
-                    Err(usvg::Error::FileOpenFailed) => Err(io::Error::from(io::ErrorKind::Other)),
-                    _ => Err(io::Error::from(io::ErrorKind::InvalidData)),
-                }
-            }
-            _ => Err(io::Error::from(io::ErrorKind::InvalidData)),
+    fn add_entries_from<
+        F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>,
+        S: Borrow<Image>,
+        I: IntoIterator<Item = (S, Self::Key)>,
+    >(
+        &mut self,
+        mut filter: F,
+        entries: I,
+    ) -> Result<(), IconError<Self::Key>> {
+        for (source, key) in entries {
+            let size = key.as_size();
+
+            self.add_entry(&mut filter, source.borrow(), key)
+                .map_err(|err| err.while_adding(size))?;
         }
+
+        Ok(())
     }
 
-    /// Rasterizes the `Image` to a `DynamicImage`.
-    /// 
-    /// For _raster graphics_ the moethod simply applies the resampling filter
-    /// specified by the `filter` argument. For _vector graphics_, the method
-    /// rasterizes the image to fit the dimensions specified `size` using
-    /// linear interpolation and antialiasing.
-    pub fn rasterize<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
-        &self,
+    #[cfg(feature = "rayon")]
+    /// Adds a series of entries to the icon, rasterizing `source`
+    /// concurrently for each of the items of `keys`.
+    ///
+    /// Only the rasterization step runs in parallel: the resulting
+    /// entries are inserted sequentially, in the order in which they
+    /// appear in `keys`. The icon is checked for entries associated
+    /// with any of the items of `keys` before `source` is rasterized.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` The resampling filter that will be used to re-scale `source`.
+    /// * `source` A reference to the source image this entry will be based on.
+    /// * `keys` A container for the information on the target entries.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::AlreadyIncluded(_))` if the icon already contains an
+    ///   entry associated with any of the items of `keys`.
+    /// * Returns `Err(IconError::WhileAdding(size, _))` if the resampling filter
+    ///   provided in the `filter` argument fails or produces results of dimensions
+    ///   other than the ones specified by the items of `keys`, where `size` is the
+    ///   size of the entry that failed.
+    /// * Otherwise returns `Ok(())`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// use iconwriter::{Icns, Image, Icon, IconError};
+    ///  
+    /// fn example() -> Result<(), IconError> {
+    ///     let icon = Icns::new();
+    ///     let src = Image::open("image.svg")?;
+    ///
+    ///     icon.par_add_entries(
+    ///         resample::linear,
+    ///         &src,
+    ///         vec![32, 64, 128]
+    ///     )
+    /// }
+    /// ```
+    fn par_add_entries<F: Fn(&DynamicImage, u32) -> io::Result<DynamicImage> + Sync, I: IntoIterator<Item = Self::Key>>(
+        &mut self,
         filter: F,
-        size: u32,
-    ) -> Result<DynamicImage, ResReResampleError> {
-        match self {
-            Self::Raster(ras) => resample::apply(filter, ras, size),
-            Self::Svg(svg) => resample::svg(svg, size),
-        }
-    }
+        source: &Image,
+        keys: I,
+    ) -> Result<(), IconError<Self::Key>>
+    where
+        Self::Key: Clone,
+    {
+        use rayon::prelude::*;
 
-    /// Returns the width of the image in pixels.
-    pub fn width(&self) -> f64 {
-        match self {
-            Image::Raster(ras) => ras.width() as f64,
-            Image::Svg(svg) => svg.svg_node().view_box.rect.width(),
-        }
-    }
+        let keys: Vec<Self::Key> = keys.into_iter().collect();
 
-    /// Returns the height of the image in pixels.
-    pub fn height(&self) -> f64 {
-        match self {
-            Image::Raster(ras) => ras.height() as f64,
-            Image::Svg(svg) => svg.svg_node().view_box.rect.height(),
+        if let Some(key) = keys.iter().find(|&key| self.contains(key.clone())) {
+            return Err(IconError::AlreadyIncluded(key.clone()));
         }
-    }
 
-    /// Returns the dimensions of the image in pixels.
-    pub fn dimensions(&self) -> (f64, f64) {
-        (self.width(), self.height())
-    }
-}
+        let icons: Vec<(Self::Key, Result<DynamicImage, ResReResampleError>)> = keys
+            .into_par_iter()
+            .map(|key| {
+                let icon = source.rasterize_to(&filter, key.as_dimensions());
+                (key, icon)
+            })
+            .collect();
 
-impl From<Tree> for Image {
-    fn from(svg: Tree) -> Self {
-        Image::Svg(svg)
+        for (key, icon) in icons {
+            let icon = icon.map_err(|err| IconError::WhileAdding(key.as_size(), err))?;
+            add_rasterized(self, icon, key)?;
+        }
+
+        Ok(())
     }
-}
+
+    /// Adds an individual entry to the icon, consulting `cache` to
+    /// avoid rasterizing `source` more than once for a given size.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` The resampling filter that will be used to re-scale `source`.
+    /// * `filter_id` An identifier for `filter`, used as part of the cache key.
+    /// * `source` A reference to the source image this entry will be based on.
+    /// * `key` Information on the target entry.
+    /// * `cache` The cache of rasterized versions of `source`.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::AlreadyIncluded(_))` if the icon already contains
+    ///   an entry associated with `key`.
+    /// * Returns `Err(IconError::Resample(_))` if the resampling filter provided in
+    ///   the `filter` argument fails produces results of dimensions other than the
+    ///   ones specified by `key`.
+    /// * Otherwise returns `Ok(())`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// use iconwriter::{cache::RasterCache, Ico, Image, Icon, IconError};
+    ///  
+    /// fn example() -> Result<(), IconError> {
+    ///     let icon = Ico::new();
+    ///     let mut cache = RasterCache::new();
+    ///     let src = Image::open("image.svg")?;
+    ///
+    ///     icon.add_entry_cached(resample::linear, "linear", &img, 32, &mut cache)
+    /// }
+    /// ```
+    fn add_entry_cached<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        filter: F,
+        filter_id: &str,
+        source: &Image,
+        key: Self::Key,
+        cache: &mut RasterCache,
+    ) -> Result<(), IconError<Self::Key>> {
+        let icon = cache.rasterize(filter, filter_id, source, key.as_dimensions())?;
+        add_rasterized(self, icon, key)
+    }
+
+    /// Adds an individual entry to the icon from an image which is
+    /// already of the size of `key`, skipping resampling altogether.
+    ///
+    /// This is useful when the pixels of the entry were produced by
+    /// another pipeline.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::AlreadyIncluded(_))` if the icon already contains
+    ///   an entry associated with `key`.
+    /// * Returns `Err(IconError::Resample(ResReResampleError::MismatchedDimensions(_, _)))`
+    ///   if the dimensions of `image` are other than the ones specified by `key`.
+    /// * Otherwise returns `Ok(())`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let rgba = RgbaImage::from_raw(32, 32, pixels).unwrap();
+    /// icon.add_raw_entry(DynamicImage::ImageRgba8(rgba), Key(32))?;
+    /// ```
+    fn add_raw_entry(&mut self, image: DynamicImage, key: Self::Key) -> Result<(), IconError<Self::Key>> {
+        let dims = image.dimensions();
+
+        if dims != key.as_dimensions() {
+            return Err(IconError::Resample(ResReResampleError::MismatchedDimensions(key.as_size(), dims)));
+        }
+
+        add_rasterized(self, image, key)
+    }
+
+    /// Adds an individual entry to the icon, compositing it onto an
+    /// opaque `background` color.
+    ///
+    /// See `Image::rasterize_on_background` for details.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(IconError::AlreadyIncluded(_))` if the icon already contains
+    ///   an entry associated with `key`.
+    /// * Returns `Err(IconError::Resample(_))` if the resampling filter provided in
+    ///   the `filter` argument fails produces results of dimensions other than the
+    ///   ones specified by `key`.
+    /// * Otherwise returns `Ok(())`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// use iconwriter::{Ico, Image, Icon, IconError, image::Rgba};
+    ///  
+    /// fn example() -> Result<(), IconError> {
+    ///     let icon = Ico::new();
+    ///     let src = Image::open("image.svg")?;
+    ///
+    ///     icon.add_entry_on_background(resample::linear, &img, 32, Rgba([255, 255, 255, 255]))
+    /// }
+    /// ```
+    fn add_entry_on_background<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        filter: F,
+        source: &Image,
+        key: Self::Key,
+        background: Rgba<u8>,
+    ) -> Result<(), IconError<Self::Key>> {
+        let (w, h) = key.as_dimensions();
+        let icon = flatten(&source.rasterize_to(filter, (w, h))?, background);
+
+        add_rasterized(self, icon, key)
+    }
+
+    /// Returns the image associated with `key`, as it was encoded in the icon.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Some(image)` if the icon contains an entry associated with `key`.
+    /// * Otherwise returns `None`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// if let Some(image) = icon.get_entry(Key(32)) {
+    ///     preview.show(&image);
+    /// }
+    /// ```
+    fn get_entry(&self, _key: Self::Key) -> Option<DynamicImage> {
+        None
+    }
+
+    /// Removes the entry associated with `key` from the icon.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `true` if the icon contained an entry associated with `key`.
+    /// * Otherwise returns `false`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// use iconwriter::{Ico, Image, Icon, IconError};
+    ///  
+    /// fn example() -> Result<(), IconError> {
+    ///     let icon = Ico::new();
+    ///     let src = Image::open("image.svg")?;
+    ///
+    ///     icon.add_entry(resample::linear, &img, 32)?;
+    ///     assert!(icon.remove_entry(32));
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    fn remove_entry(&mut self, _key: Self::Key) -> bool {
+        false
+    }
+
+    /// Removes all the entries of the icon, retaining its capacity and
+    /// its configuration, so that it can be reused for another output.
+    ///
+    /// After calling this method, `self.len()` is `0` and entries of
+    /// previously used sizes can be added again.
+    ///
+    /// The default implementation calls `Icon::remove_entry` for each key.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut icon = Ico::new();
+    ///
+    /// for src in sources {
+    ///     icon.add_entries(resample::linear, &src, keys.iter().cloned())?;
+    ///     icon.save(&output_path(&src))?;
+    ///     icon.clear();
+    /// }
+    /// ```
+    fn clear(&mut self) {
+        for key in self.keys() {
+            self.remove_entry(key);
+        }
+    }
+
+    /// Checks the icon for common issues, such as missing entries of
+    /// the sizes recommended by the platform the icon format targets.
+    ///
+    /// The checks are advisory only and never prevent the icon from
+    /// being written. The default implementation performs no checks.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// for warning in icon.validate() {
+    ///     eprintln!("warning: {}", warning);
+    /// }
+    /// ```
+    fn validate(&self) -> Vec<Warning<Self::Key>> {
+        Vec::new()
+    }
+
+    /// Returns the pairs of entries with identical contents, regardless of
+    /// their sizes.
+    ///
+    /// Entries which are exact integer upscales of one another, such as
+    /// the entries rasterized from a source smaller than all of them with
+    /// `resample::nearest_integer`, are reported as duplicates, since the
+    /// larger ones add no detail. Entries which cannot be decoded by
+    /// `Icon::get_entry` are skipped.
+    ///
+    /// Only exact matches are reported: entries upscaled with an
+    /// interpolating filter, such as the default `resample::linear`,
+    /// never match unless the source is a uniform color.
+    ///
+    /// The pairs are also reported as `Warning::DuplicateContent` by
+    /// `Icon::validate`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// for (a, b) in icon.duplicate_content() {
+    ///     eprintln!("warning: the {0}x{0} and {1}x{1} entries are identical", a.as_size(), b.as_size());
+    /// }
+    /// ```
+    fn duplicate_content(&self) -> Vec<(Self::Key, Self::Key)>
+    where
+        Self::Key: Clone,
+    {
+        let hashes: Vec<(Self::Key, u64)> = self
+            .keys()
+            .filter_map(|key| {
+                let entry = self.get_entry(key.clone())?;
+                Some((key, upscale_base_hash(&entry)))
+            })
+            .collect();
+
+        let mut pairs = Vec::new();
+
+        for (i, (a, hash_a)) in hashes.iter().enumerate() {
+            for (b, hash_b) in &hashes[i + 1..] {
+                if hash_a == hash_b {
+                    pairs.push((a.clone(), b.clone()));
+                }
+            }
+        }
+
+        pairs
+    }
+
+    /// Writes the contents of the icon to `w`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// use iconwriter::favicon::Favicon;
+    /// use std::{io, fs::File};
+    ///  
+    /// fn example() -> io::Result<()> {
+    ///     let icon = Favicon::new();
+    ///
+    ///     /* Process the icon */
+    ///
+    ///     let file = File::create("out.icns")?;
+    ///     icon.write(file)
+    /// }
+    /// ```
+    fn write<W: Write>(&mut self, w: &mut W) -> io::Result<()>;
+
+    /// Writes the contents of the icon to `w` and flushes it.
+    ///
+    /// Encoders which need to finalize their output, such as streaming
+    /// encoders, can override this method. The default implementation
+    /// calls `self.write` followed by `w.flush`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// use iconwriter::ico::Ico;
+    /// use std::{io::{self, BufWriter}, fs::File};
+    ///  
+    /// fn example() -> io::Result<()> {
+    ///     let icon = Ico::new();
+    ///
+    ///     /* Process the icon */
+    ///
+    ///     let mut file = BufWriter::new(File::create("out.ico")?);
+    ///     icon.finish(&mut file)
+    /// }
+    /// ```
+    fn finish<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        self.write(w)?;
+        w.flush()
+    }
+
+    /// Writes the contents of the icon to a buffer in memory.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// use iconwriter::ico::Ico;
+    /// use std::io;
+    ///  
+    /// fn example() -> io::Result<()> {
+    ///     let icon = Ico::new();
+    ///
+    ///     /* Process the icon */
+    ///
+    ///     let buf = icon.write_to_vec()?;
+    ///     upload(&buf)
+    /// }
+    /// ```
+    fn write_to_vec(&mut self) -> io::Result<Vec<u8>> {
+        let mut cursor = Cursor::new(Vec::new());
+        self.write(&mut cursor)?;
+
+        Ok(cursor.into_inner())
+    }
+
+    /// Writes the contents of the icon to a file on disk.
+    ///
+    /// This method is only available with the `std-fs` feature.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// use iconwriter::favicon::Favicon;
+    /// use std::{io, fs::File};
+    ///  
+    /// fn example() -> io::Result<()> {
+    ///     let icon = Favicon::new();
+    ///
+    ///     /* Process the icon */
+    ///
+    ///     icon.save("./output/")
+    /// }
+    /// ```
+    #[cfg(feature = "std-fs")]
+    fn save<P: AsRef<Path>>(&mut self, path: &P) -> io::Result<()> {
+        let mut file = File::create(path.as_ref())?;
+        self.finish(&mut file)
+    }
+}
+
+/// Saves an icon containing entries of the specified `sizes` to `path`,
+/// choosing the encoder based on the extension of `path`.
+///
+/// `.ico` files are encoded with `Ico` and `.icns` files are encoded with
+/// `Icns`. Entries are re-scaled using `resample::linear`.
+///
+/// This function is only available with the `std-fs` feature.
+///
+/// # Return Value
+///
+/// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if the extension
+///   of `path` is not recognized or if any of the `sizes` is not supported by
+///   the chosen encoder.
+/// * Returns `Err(_)` if the entries could not be rasterized or if the file
+///   could not be written.
+/// * Otherwise returns `Ok(())`.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let src = Image::open("image.svg")?;
+/// iconwriter::save_inferred(&src, &[16, 32, 64], "icon.icns")?;
+/// ```
+#[cfg(feature = "std-fs")]
+pub fn save_inferred<P: AsRef<Path>>(source: &Image, sizes: &[u32], path: P) -> io::Result<()> {
+    let path = path.as_ref();
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("ico") => save_sizes::<Ico>(source, sizes, path),
+        Some(ext) if ext.eq_ignore_ascii_case("icns") => save_sizes::<Icns>(source, sizes, path),
+        _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+    }
+}
+
+/// Returns the physical sizes needed to display icons of the `logical`
+/// sizes at both _1x_ and _2x_ _"retina"_ density, sorted and without
+/// duplicates.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// // 32 is both 32@1x and 16@2x
+/// assert_eq!(iconwriter::retina_sizes(&[16, 32]), vec![16, 32, 64]);
+/// ```
+pub fn retina_sizes(logical: &[u32]) -> Vec<u32> {
+    let mut sizes: Vec<u32> = logical
+        .iter()
+        .flat_map(|&size| once(size).chain(size.checked_mul(2)))
+        .collect();
+
+    sizes.sort();
+    sizes.dedup();
+    sizes
+}
+
+/// Saves an icon of type `I` containing entries of the specified `sizes` to `path`.
+#[cfg(feature = "std-fs")]
+fn save_sizes<I>(source: &Image, sizes: &[u32], path: &Path) -> io::Result<()>
+where
+    I: Icon,
+    I::Key: TryFrom<u32>,
+{
+    let keys = sizes
+        .iter()
+        .map(|&size| I::Key::try_from(size).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput)))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut icon = I::with_capacity(keys.len());
+    icon.add_entries(resample::linear, source, keys)
+        .map_err(Into::<io::Error>::into)?;
+
+    icon.save(&path)
+}
+
+/// Adds an entry based on an already rasterized image to `icon`.
+fn add_rasterized<I: Icon>(
+    icon: &mut I,
+    image: DynamicImage,
+    key: I::Key,
+) -> Result<(), IconError<I::Key>> {
+    icon.add_entry(|src, _| Ok(src.clone()), &Image::from(image), key)
+}
+
+/// Parses the size of a square entry, such as `"32"` or `"32x32"`, for
+/// the `FromStr` implementations of the key types. `format` is the name
+/// of the icon format reported in errors, in which `key` is validated by
+/// `validate`.
+pub(crate) fn parse_square_key<K, V>(s: &str, format: &str, validate: V) -> io::Result<K>
+where
+    V: FnOnce(u32) -> io::Result<K>,
+{
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let parse = |n: &str| n.trim().parse::<u32>().map_err(|_| invalid(format!("malformed size `{}`", s)));
+
+    let size = match s.find('x') {
+        Some(i) => {
+            let (w, h) = (parse(&s[..i])?, parse(&s[i + 1..])?);
+
+            if w != h {
+                return Err(invalid(format!("{} entries must be square, not `{}`", format, s)));
+            }
+
+            w
+        }
+        None => parse(s)?,
+    };
+
+    validate(size).map_err(|_| invalid(format!("{} does not support entries of size `{}`", format, s)))
+}
+
+/// Hashes the contents of `image`, reduced to the smallest image
+/// it is an integer upscale of.
+fn upscale_base_hash(image: &DynamicImage) -> u64 {
+    let base = block_base(image.to_rgba());
+    let mut hasher = DefaultHasher::new();
+
+    base.dimensions().hash(&mut hasher);
+    base.into_raw().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns the smallest image which `image` is a nearest-neighbor
+/// upscale of, by an integer factor.
+fn block_base(image: RgbaImage) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    let (mut a, mut b) = (w, h);
+
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+
+    // Larger factors are tried first, `a` being the greatest
+    // common divisor of the dimensions of the image
+    for k in (2..=a).rev().filter(|k| a % k == 0) {
+        let is_upscale = image
+            .enumerate_pixels()
+            .all(|(x, y, px)| px == image.get_pixel(x - x % k, y - y % k));
+
+        if is_upscale {
+            return RgbaImage::from_fn(w / k, h / k, |x, y| *image.get_pixel(x * k, y * k));
+        }
+    }
+
+    image
+}
+
+/// A trait for types that represent the dimesions of an icon.
+pub trait AsSize {
+    fn as_size(&self) -> u32;
+
+    /// Returns the dimensions of the entry associated with
+    /// this key, in the form `(width, height)`.
+    ///
+    /// Defaults to `(self.as_size(), self.as_size())`, which
+    /// is appropriate for keys of square entries.
+    fn as_dimensions(&self) -> (u32, u32) {
+        (self.as_size(), self.as_size())
+    }
+}
+
+#[derive(Clone, Debug)]
+/// An iterator over the keys of an icon.
+pub struct Keys<K> {
+    keys: std::vec::IntoIter<K>,
+}
+
+#[derive(Clone)]
+#[non_exhaustive]
+/// A uniun type for raster and vector graphics.
+///
+/// New kinds of sources may be added without a major release, so
+/// matches on `Image` outside of this crate require a wildcard arm.
+pub enum Image {
+    /// A generic raster image.
+    Raster(DynamicImage),
+    /// A svg-encoded vector image.
+    Svg(Tree),
+    /// A procedurally generated image, drawn directly at the
+    /// requested size by a closure. See `Image::generated`.
+    Generated(Arc<dyn Fn(u32) -> DynamicImage + Send + Sync>),
+}
+
+/// The error type for operations of the `Icon` trait.
+///
+/// `IconError` implements `std::error::Error`: the `io::Error` raised by a
+/// failing resampling filter is exposed through `Error::source`, while errors
+/// raised by **IconWriter** itself have no source.
+pub enum IconError<K: AsSize + Send + Sync> {
+    /// The `Icon` instance already includes an entry associated with this key.
+    AlreadyIncluded(K),
+    /// A resampling error.
+    Resample(ResReResampleError),
+    /// The icon format does not support entries of the size
+    /// associated with this key.
+    UnsupportedSize(K),
+    /// A resampling error raised while adding an entry
+    /// of the specified size.
+    WhileAdding(u32, ResReResampleError),
+    /// The dimensions of the raster source image are smaller than the
+    /// requested size, which is reported instead of upscaling the source
+    /// by icons in _strict mode_, such as `Ico::set_strict`.
+    SourceTooSmall {
+        /// The dimensions of the source image.
+        source: (u32, u32),
+        /// The size of the requested entry.
+        requested: u32,
+    },
+    /// The requested size exceeds the size limit of the icon. See
+    /// `DEFAULT_MAX_SIZE`.
+    InvalidSize {
+        /// The size of the requested entry.
+        requested: u32,
+        /// The size limit of the icon.
+        max: u32,
+    },
+    /// Adding the entry would exceed the maximum number of
+    /// entries of the icon format, such as _65535_ for `Ico`.
+    TooManyEntries(usize),
+    /// The icon format cannot hold entries of this logical size at
+    /// both _1x_ and _2x_ _"retina"_ density. See `Icns::add_retina_set`.
+    UnsupportedRetinaSize(u32),
+    /// The _hotspot_ of a cursor lies outside of the bounds of its
+    /// image. See `Ani::add_frame`.
+    HotspotOutOfBounds((u16, u16)),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// An advisory warning reported by `Icon::validate`.
+pub enum Warning<K: AsSize + Send + Sync> {
+    /// The icon does not contain an entry associated with this
+    /// key, which is recommended by the targeted platform.
+    MissingRecommendedSize(K),
+    /// The entries associated with these keys have identical contents,
+    /// as reported by `Icon::duplicate_content`.
+    DuplicateContent(K, K),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+/// The icon formats supported by **IconWriter**.
+///
+/// With the `serde` feature, `FormatKind` is (de)serialized as the
+/// _snake case_ name of the variant, such as `"png_sequence"`.
+pub enum FormatKind {
+    /// The `.ico` file format. See `Ico`.
+    Ico,
+    /// The `.icns` file format. See `Icns`.
+    Icns,
+    /// A _favicon_ directory. See `favicon::Favicon`.
+    Favicon,
+    /// A `.tar` archive of _PNG_ files. See `png_sequence::PngSequence`.
+    PngSequence,
+    /// A `.zip` archive of _PNG_ files. See `png_sequence::PngZip`.
+    PngZip,
+}
+
+#[derive(Debug)]
+/// The error type for resampling operations.
+pub enum ResReResampleError {
+    /// Generic I/O error.
+    Io(io::Error),
+    /// A resampling filter produced results of dimensions
+    /// other the ones specified by it's arguments.
+    MismatchedDimensions(u32, (u32, u32)),
+}
+
+impl Image {
+    /// Attempts to create a `Image` from a given path.
+    ///
+    /// # Return Value
+    /// 
+    /// * Returns `Ok(src)` if the file indicated by the `path` argument could be
+    ///   successfully parsed into an image.
+    /// * Returns the underlying `io::Error` if the file was not able to be accessed,
+    ///   such as an error of kind `io::ErrorKind::NotFound` for missing files.
+    /// * Returns `Err(io::Error::from(io::ErrorKind::Other))` if the image allocation failed.
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if the image format is not
+    ///   supported by `iconwriter`.
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidData))` otherwise.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let img = Image::open("source.png")?;
+    /// ```
+    #[cfg(feature = "std-fs")]
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        Self::open_with_options(path, &usvg::Options::default())
+    }
+
+    /// Attempts to create a `Image` from a given path, parsing _SVG_ files
+    /// with the specified options, such as the target _DPI_ or the default
+    /// font family used to render text.
+    ///
+    /// `Image::open` is equivalent to calling this method with
+    /// `usvg::Options::default()`. The options are ignored for raster graphics.
+    ///
+    /// # Return Value
+    ///
+    /// See `Image::open`.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let opts = usvg::Options { dpi: 72.0, ..usvg::Options::default() };
+    /// let img = Image::open_with_options("source.svg", &opts)?;
+    /// ```
+    #[cfg(feature = "std-fs")]
+    pub fn open_with_options<P: AsRef<Path>>(
+        path: P,
+        options: &usvg::Options,
+    ) -> Result<Self, io::Error> {
+        match image::open(&path) {
+            Ok(img) => Ok(Image::from(img)),
+            Err(ImageError::InsufficientMemory) => Err(io::Error::from(io::ErrorKind::Other)),
+            Err(ImageError::IoError(err)) => Err(err),
+            Err(ImageError::UnsupportedError(_)) => {
+                match Tree::from_file(path, options) {
+                    Ok(img) => Ok(Image::from(img)),
+                    Err(usvg::Error::InvalidFileSuffix) => {
+                        Err(io::Error::from(io::ErrorKind::InvalidInput))
+                    }
+                    Err(usvg::Error::FileOpenFailed) => Err(io::Error::from(io::ErrorKind::Other)),
+                    _ => Err(io::Error::from(io::ErrorKind::InvalidData)),
+                }
+            }
+            _ => Err(io::Error::from(io::ErrorKind::InvalidData)),
+        }
+    }
+
+    /// Attempts to create a `Image` from a buffer of encoded data.
+    ///
+    /// The buffer is first decoded as a _raster graphic_ and, if its format
+    /// can't be recognized, as a _SVG_ document (either plain or gzipped).
+    /// No file extension is required to detect _SVG_ data.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Ok(src)` if `data` could be successfully parsed into an image.
+    /// * Returns `Err(io::Error::from(io::ErrorKind::Other))` if the image allocation failed.
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidData))` otherwise.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let img = Image::from_bytes(&buf)?;
+    /// ```
+    pub fn from_bytes(data: &[u8]) -> Result<Self, io::Error> {
+        Self::from_bytes_with_options(data, &usvg::Options::default())
+    }
+
+    /// Attempts to create a `Image` from a buffer of encoded data, parsing
+    /// _SVG_ documents with the specified options.
+    ///
+    /// `Image::from_bytes` is equivalent to calling this method with
+    /// `usvg::Options::default()`. The options are ignored for raster graphics.
+    ///
+    /// # Return Value
+    ///
+    /// See `Image::from_bytes`.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let opts = usvg::Options { font_family: "DejaVu Sans".into(), ..usvg::Options::default() };
+    /// let img = Image::from_bytes_with_options(&buf, &opts)?;
+    /// ```
+    pub fn from_bytes_with_options(
+        data: &[u8],
+        options: &usvg::Options,
+    ) -> Result<Self, io::Error> {
+        match image::load_from_memory(data) {
+            Ok(img) => Ok(Image::from(img)),
+            Err(ImageError::InsufficientMemory) => Err(io::Error::from(io::ErrorKind::Other)),
+            Err(ImageError::IoError(err)) => Err(err),
+            Err(ImageError::UnsupportedError(_)) => {
+                match Tree::from_data(data, options) {
+                    Ok(img) => Ok(Image::from(img)),
+                    Err(_) => Err(io::Error::from(io::ErrorKind::InvalidData)),
+                }
+            }
+            _ => Err(io::Error::from(io::ErrorKind::InvalidData)),
+        }
+    }
+
+    /// Attempts to create a `Image` from a buffer of _raster graphics_
+    /// encoded in the specified `format`, bypassing format detection.
+    ///
+    /// This is useful for formats which can't be reliably detected from
+    /// their contents, such as _TGA_. _SVG_ documents are not covered by
+    /// `ImageFormat`, use `Image::from_bytes` to decode them instead.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Ok(src)` if `data` could be successfully decoded as `format`.
+    /// * Returns `Err(io::Error::from(io::ErrorKind::Other))` if the image allocation failed.
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if `format` is not
+    ///   supported by the `image` crate.
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidData))` otherwise.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let img = Image::from_bytes_with_format(&buf, ImageFormat::TGA)?;
+    /// ```
+    pub fn from_bytes_with_format(data: &[u8], format: ImageFormat) -> Result<Self, io::Error> {
+        match image::load_from_memory_with_format(data, format) {
+            Ok(img) => Ok(Image::from(img)),
+            Err(ImageError::InsufficientMemory) => Err(io::Error::from(io::ErrorKind::Other)),
+            Err(ImageError::IoError(err)) => Err(err),
+            Err(ImageError::UnsupportedError(_)) => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+            Err(_) => Err(io::Error::from(io::ErrorKind::InvalidData)),
+        }
+    }
+
+    /// Attempts to create a `Image` from the contents of a reader.
+    ///
+    /// See `Image::from_bytes` for details.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let img = Image::from_reader(request.body())?;
+    /// ```
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, io::Error> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        Self::from_bytes(&data)
+    }
+
+    /// Creates a `Image` which is drawn by `generate` at the size it
+    /// gets rasterized to, bypassing the resampling filter.
+    ///
+    /// This keeps procedurally drawn icons as crisp as _vector graphics_
+    /// at every size. `generate` must return a _`size`x`size`_ image.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let img = Image::generated(|size| {
+    ///     DynamicImage::ImageRgba8(RgbaImage::from_pixel(size, size, Rgba([255, 0, 0, 255])))
+    /// });
+    /// ```
+    pub fn generated<F: Fn(u32) -> DynamicImage + Send + Sync + 'static>(generate: F) -> Self {
+        Image::Generated(Arc::new(generate))
+    }
+
+    /// Rasterizes the `Image` to a `DynamicImage`.
+    /// 
+    /// For _raster graphics_ the moethod simply applies the resampling filter
+    /// specified by the `filter` argument. For _vector graphics_, the method
+    /// rasterizes the image to fit the dimensions specified `size` using
+    /// linear interpolation and antialiasing. _Generated images_ are drawn
+    /// directly at `size`, and must match it.
+    pub fn rasterize<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &self,
+        filter: F,
+        size: u32,
+    ) -> Result<DynamicImage, ResReResampleError> {
+        match self {
+            Self::Raster(ras) => resample::apply(filter, ras, size),
+            Self::Svg(svg) => resample::svg(svg, size),
+            Self::Generated(generate) => {
+                let icon = generate(size);
+
+                match icon.dimensions() {
+                    dims if dims == (size, size) => Ok(icon),
+                    dims => Err(ResReResampleError::MismatchedDimensions(size, dims)),
+                }
+            }
+        }
+    }
+
+    /// Rasterizes the `Image` to a square `DynamicImage`, preserving
+    /// the aspect ratio of the source.
+    ///
+    /// For _raster graphics_ the source is first centered on a transparent
+    /// square canvas whose side matches its longest edge, so that `filter`
+    /// scales the longest edge to `size` and the shorter one is padded with
+    /// transparent bars. This holds even for filters which stretch
+    /// non-square images. _Vector graphics_ are always rasterized this way.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let icon = Image::open("banner.png")?.rasterize_fit(resample::linear, 32)?;
+    /// ```
+    pub fn rasterize_fit<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &self,
+        filter: F,
+        size: u32,
+    ) -> Result<DynamicImage, ResReResampleError> {
+        match self {
+            Self::Raster(ras) if ras.width() != ras.height() => {
+                let side = ras.width().max(ras.height());
+                resample::apply(filter, &resample::fit(ras, side, side), size)
+            }
+            _ => self.rasterize(filter, size),
+        }
+    }
+
+    /// Rasterizes the `Image` to a `DynamicImage` of dimensions
+    /// `width`x`height`.
+    ///
+    /// The resampling filter is applied to fit the smallest of the two
+    /// dimensions, and the resulting square image is then padded with
+    /// transparent borders to fill the requested rectangle.
+    pub fn rasterize_to<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &self,
+        filter: F,
+        (width, height): (u32, u32),
+    ) -> Result<DynamicImage, ResReResampleError> {
+        let icon = self.rasterize(filter, width.min(height))?;
+
+        if icon.dimensions() == (width, height) {
+            Ok(icon)
+        } else {
+            Ok(resample::fit(&icon, width, height))
+        }
+    }
+
+    /// Rasterizes the `Image` to a `DynamicImage` and alpha-composites
+    /// the result onto an opaque `background` color.
+    ///
+    /// The alpha channel of `background` is ignored, so that
+    /// the output contains no transparency.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let icon = img.rasterize_on_background(resample::linear, 32, Rgba([255, 255, 255, 255]))?;
+    /// ```
+    pub fn rasterize_on_background<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &self,
+        filter: F,
+        size: u32,
+        background: Rgba<u8>,
+    ) -> Result<DynamicImage, ResReResampleError> {
+        Ok(flatten(&self.rasterize(filter, size)?, background))
+    }
+
+    /// Rasterizes the `Image` to a `DynamicImage`, rendering _vector graphics_
+    /// onto a canvas filled with `background` instead of a transparent one.
+    ///
+    /// This is meant for _SVG_ documents which rely on the white page of
+    /// browsers as their background. Unlike `Image::rasterize_on_background`,
+    /// only the canvas of the document is filled: the transparent borders
+    /// added to fit non-square documents are left transparent. _Raster
+    /// graphics_ and _generated images_ are rasterized as by `Image::rasterize`.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let icon = img.rasterize_with_background(resample::linear, 32, usvg::Color::new(255, 255, 255))?;
+    /// ```
+    pub fn rasterize_with_background<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &self,
+        filter: F,
+        size: u32,
+        background: usvg::Color,
+    ) -> Result<DynamicImage, ResReResampleError> {
+        match self {
+            Self::Svg(svg) => resample::svg_with_background(svg, size, Some(background)),
+            _ => self.rasterize(filter, size),
+        }
+    }
+
+    /// Rasterizes the `Image` as `Image::rasterize` does, writing the
+    /// _RGBA_ pixels of the output to `buf` in row-major order.
+    ///
+    /// The previous contents of `buf` are discarded, but its allocation is
+    /// kept, so that a single buffer can be reused across entries instead
+    /// of allocating a new image per size. _Square vector graphics_ are
+    /// converted directly from the rendered surface, without allocating
+    /// any intermediate image.
+    ///
+    /// # Buffer Sizing
+    ///
+    /// On success, `buf` contains exactly `4 * size * size` bytes. `buf`
+    /// only grows when that exceeds its capacity, so reserving room for
+    /// the largest size upfront avoids any reallocation.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let mut buf = Vec::with_capacity(4 * 256 * 256);
+    ///
+    /// for &size in &[16, 32, 256] {
+    ///     img.rasterize_into(resample::linear, size, &mut buf)?;
+    ///     save_entry(size, &buf)?;
+    /// }
+    /// ```
+    pub fn rasterize_into<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &self,
+        filter: F,
+        size: u32,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), ResReResampleError> {
+        buf.clear();
+
+        match self {
+            Self::Svg(svg) => resample::svg_into(svg, size, buf),
+            _ => {
+                match self.rasterize(filter, size)? {
+                    DynamicImage::ImageRgba8(icon) => buf.extend_from_slice(&icon),
+                    icon => buf.extend_from_slice(&icon.to_rgba()),
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns a copy of the image with its transparent borders trimmed.
+    ///
+    /// For _raster graphics_ the image is cropped to the bounding box
+    /// of its non-transparent pixels. For _vector graphics_, the view box
+    /// of the image is tightened to the bounding box of its contents.
+    ///
+    /// Fully transparent or empty images, as well as _generated images_,
+    /// are returned unchanged.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let img = Image::open("source.png")?.trimmed();
+    /// ```
+    pub fn trimmed(&self) -> Self {
+        match self {
+            Image::Raster(ras) => Image::Raster(trim_raster(ras)),
+            Image::Svg(svg) => Image::Svg(trim_svg(svg)),
+            Image::Generated(_) => self.clone(),
+        }
+    }
+
+    /// Returns a copy of the _vector graphic_ with its view box replaced by
+    /// the rectangle at `(x, y)` of dimensions `w`x`h`, which crops or extends
+    /// the region that gets rasterized.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if the
+    ///   image is not a _vector graphic_ or if `w` or `h` is not a positive number.
+    /// * Otherwise returns `Ok(svg)`.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// // Renders the top left quadrant of a 64x64 image
+    /// let img = Image::open("source.svg")?.with_viewbox(0.0, 0.0, 32.0, 32.0)?;
+    /// ```
+    pub fn with_viewbox(&self, x: f64, y: f64, w: f64, h: f64) -> io::Result<Self> {
+        let svg = match self {
+            Image::Svg(svg) => svg,
+            _ => return Err(io::Error::from(io::ErrorKind::InvalidInput)),
+        };
+
+        if !(x.is_finite() && y.is_finite() && w.is_finite() && h.is_finite() && w > 0.0 && h > 0.0) {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        let rect = usvg::Rect::new(x, y, w, h)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+        // Cloning a `Tree` does not clone its nodes, so modifying
+        // the clone would affect the original image
+        let mut root = svg.root().make_deep_copy();
+
+        if let usvg::NodeKind::Svg(ref mut node) = *root.borrow_mut() {
+            node.view_box.rect = rect;
+            node.size = rect.size();
+        }
+
+        Ok(Image::Svg(root.tree()))
+    }
+
+    /// Returns the most frequent color of the image, suitable for use as
+    /// the `theme_color` of a web manifest.
+    ///
+    /// The image is rasterized to a 16x16 thumbnail using nearest-neighbor
+    /// sampling and the colors of its non-transparent pixels are counted.
+    /// Ties are broken in favor of the smallest color value.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(_)` if the image cannot be rasterized, as described
+    ///   by `Image::rasterize`.
+    /// * Returns `Ok(Rgba([0, 0, 0, 255]))`, that is opaque black, if the
+    ///   image is fully transparent.
+    /// * Otherwise returns the dominant color with its alpha channel set to `255`.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let theme_color = Image::open("source.svg")?.dominant_color()?;
+    /// ```
+    pub fn dominant_color(&self) -> Result<Rgba<u8>, ResReResampleError> {
+        let thumbnail = self.rasterize(resample::nearest, DOMINANT_COLOR_SIZE)?.to_rgba();
+
+        let mut histogram = HashMap::new();
+        for pixel in thumbnail.pixels().filter(|pixel| pixel[3] != 0) {
+            *histogram.entry([pixel[0], pixel[1], pixel[2]]).or_insert(0u32) += 1;
+        }
+
+        Ok(histogram
+            .into_iter()
+            .max_by(|(c1, n1), (c2, n2)| n1.cmp(n2).then(c2.cmp(c1)))
+            .map(|([r, g, b], _)| Rgba([r, g, b, 255]))
+            .unwrap_or(Rgba([0, 0, 0, 255])))
+    }
+
+    /// Returns the width of the image in pixels.
+    ///
+    /// _Generated images_ have no intrinsic dimensions, so their width is `0.0`.
+    pub fn width(&self) -> f64 {
+        match self {
+            Image::Raster(ras) => ras.width() as f64,
+            Image::Svg(svg) => svg.svg_node().view_box.rect.width(),
+            Image::Generated(_) => 0.0,
+        }
+    }
+
+    /// Returns the height of the image in pixels.
+    ///
+    /// _Generated images_ have no intrinsic dimensions, so their height is `0.0`.
+    pub fn height(&self) -> f64 {
+        match self {
+            Image::Raster(ras) => ras.height() as f64,
+            Image::Svg(svg) => svg.svg_node().view_box.rect.height(),
+            Image::Generated(_) => 0.0,
+        }
+    }
+
+    /// Returns the dimensions of the image in pixels.
+    pub fn dimensions(&self) -> (f64, f64) {
+        (self.width(), self.height())
+    }
+
+    /// Returns the underlying raster image, if the image
+    /// is a _raster graphic_.
+    pub fn as_raster(&self) -> Option<&DynamicImage> {
+        match self {
+            Image::Raster(ras) => Some(ras),
+            _ => None,
+        }
+    }
+
+    /// Returns the underlying _SVG_ tree, if the image
+    /// is a _vector graphic_.
+    pub fn as_svg(&self) -> Option<&Tree> {
+        match self {
+            Image::Svg(svg) => Some(svg),
+            _ => None,
+        }
+    }
+}
+
+/// Alpha-composites `icon` onto an opaque `background` color.
+fn flatten(icon: &DynamicImage, background: Rgba<u8>) -> DynamicImage {
+    let mut output = icon.to_rgba();
+
+    for pixel in output.pixels_mut() {
+        let a = pixel[3] as u32;
+
+        for c in 0..3 {
+            let blended = pixel[c] as u32 * a + background[c] as u32 * (255 - a);
+            pixel[c] = ((blended + 127) / 255) as u8;
+        }
+
+        pixel[3] = 255;
+    }
+
+    DynamicImage::ImageRgba8(output)
+}
+
+/// Crops `ras` to the bounding box of its non-transparent pixels.
+fn trim_raster(ras: &DynamicImage) -> DynamicImage {
+    let rgba = ras.to_rgba();
+    let (w, h) = rgba.dimensions();
+    let (mut x0, mut y0, mut x1, mut y1) = (w, h, 0, 0);
+
+    for (x, y, pixel) in rgba.enumerate_pixels() {
+        if pixel[3] != 0 {
+            x0 = x0.min(x);
+            y0 = y0.min(y);
+            x1 = x1.max(x + 1);
+            y1 = y1.max(y + 1);
+        }
+    }
+
+    if x0 >= x1 || y0 >= y1 {
+        ras.clone()
+    } else {
+        ras.clone().crop(x0, y0, x1 - x0, y1 - y0)
+    }
+}
+
+/// Tightens the view box of `svg` to the bounding box of its contents.
+fn trim_svg(svg: &Tree) -> Tree {
+    // Cloning a `Tree` does not clone its nodes, so modifying
+    // the clone would affect the original image
+    let mut root = svg.root().make_deep_copy();
+
+    let bbox = root
+        .calculate_bbox()
+        .and_then(|bbox| usvg::Rect::new(bbox.x(), bbox.y(), bbox.width(), bbox.height()))
+        .filter(|bbox| bbox.width() > 0.0 && bbox.height() > 0.0 && bbox.width().is_finite());
+
+    if let Some(bbox) = bbox {
+        if let usvg::NodeKind::Svg(ref mut node) = *root.borrow_mut() {
+            node.view_box.rect = bbox;
+            node.size = bbox.size();
+        }
+    }
+
+    root.tree()
+}
+
+impl<K> From<Vec<K>> for Keys<K> {
+    fn from(keys: Vec<K>) -> Self {
+        Keys { keys: keys.into_iter() }
+    }
+}
+
+impl<K> Iterator for Keys<K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.keys.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl<K> ExactSizeIterator for Keys<K> {}
+
+impl From<Tree> for Image {
+    fn from(svg: Tree) -> Self {
+        Image::Svg(svg)
+    }
+}
 
 impl From<DynamicImage> for Image {
     fn from(bit: DynamicImage) -> Self {
@@ -405,6 +1784,35 @@ impl<K: AsSize + Send + Sync> IconError<K> {
         match self {
             Self::AlreadyIncluded(e) => IconError::AlreadyIncluded(f(e)),
             Self::Resample(err) => IconError::Resample(err),
+            Self::UnsupportedSize(e) => IconError::UnsupportedSize(f(e)),
+            Self::WhileAdding(size, err) => IconError::WhileAdding(size, err),
+            Self::SourceTooSmall { source, requested } => IconError::SourceTooSmall { source, requested },
+            Self::InvalidSize { requested, max } => IconError::InvalidSize { requested, max },
+            Self::TooManyEntries(max) => IconError::TooManyEntries(max),
+            Self::UnsupportedRetinaSize(size) => IconError::UnsupportedRetinaSize(size),
+            Self::HotspotOutOfBounds(hotspot) => IconError::HotspotOutOfBounds(hotspot),
+        }
+    }
+
+    /// Attaches the size of `key` to resampling errors, so that
+    /// they report which entry they were raised while adding.
+    ///
+    /// Other errors already identify their key and are returned unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// icon.add_entry(resample::linear, &src, key).map_err(|err| err.context(&key))?;
+    /// ```
+    pub fn context(self, key: &K) -> Self {
+        self.while_adding(key.as_size())
+    }
+
+    /// Converts `IconError::Resample` errors to `IconError::WhileAdding`.
+    fn while_adding(self, size: u32) -> Self {
+        match self {
+            Self::Resample(err) => Self::WhileAdding(size, err),
+            err => err,
         }
     }
 }
@@ -417,6 +1825,59 @@ impl<K: AsSize + Send + Sync> Display for IconError<K> {
                 "the icon already contains an entry associated with this key"
             ),
             Self::Resample(err) => <ResReResampleError as Display>::fmt(&err, f),
+            Self::UnsupportedSize(e) => write!(
+                f,
+                "the icon does not support entries of size {}",
+                e.as_size()
+            ),
+            Self::WhileAdding(size, err) => write!(
+                f,
+                "failed adding {}x{} entry: {}",
+                size, size, err
+            ),
+            Self::SourceTooSmall { source: (w, h), requested } => write!(
+                f,
+                "the {}x{} source image is smaller than the requested size of {}",
+                w, h, requested
+            ),
+            Self::InvalidSize { requested, max } => write!(
+                f,
+                "the requested size of {} exceeds the size limit of {}",
+                requested, max
+            ),
+            Self::TooManyEntries(max) => write!(
+                f,
+                "the icon cannot contain more than {} entries",
+                max
+            ),
+            Self::UnsupportedRetinaSize(size) => write!(
+                f,
+                "the icon does not support entries of {0}x{0} points at both 1x and 2x density",
+                size
+            ),
+            Self::HotspotOutOfBounds((x, y)) => write!(
+                f,
+                "the hotspot at ({}, {}) lies outside of the cursor",
+                x, y
+            ),
+        }
+    }
+}
+
+impl<K: AsSize + Send + Sync> Display for Warning<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingRecommendedSize(key) => write!(
+                f,
+                "the icon is missing a recommended entry of size {}",
+                key.as_size()
+            ),
+            Self::DuplicateContent(a, b) => write!(
+                f,
+                "the entries of size {} and {} have identical contents",
+                a.as_size(),
+                b.as_size()
+            ),
         }
     }
 }
@@ -426,16 +1887,31 @@ impl<K: AsSize + Send + Sync + Debug> Debug for IconError<K> {
         match self {
             Self::AlreadyIncluded(e) => write!(f, "Error::AlreadyIncluded({:?})", e),
             Self::Resample(err) => <ResReResampleError as Debug>::fmt(&err, f),
+            Self::UnsupportedSize(e) => write!(f, "Error::UnsupportedSize({:?})", e),
+            Self::WhileAdding(size, err) => write!(f, "Error::WhileAdding({}, {:?})", size, err),
+            Self::SourceTooSmall { source, requested } => write!(
+                f,
+                "Error::SourceTooSmall {{ source: {:?}, requested: {} }}",
+                source, requested
+            ),
+            Self::InvalidSize { requested, max } => write!(
+                f,
+                "Error::InvalidSize {{ requested: {}, max: {} }}",
+                requested, max
+            ),
+            Self::TooManyEntries(max) => write!(f, "Error::TooManyEntries({})", max),
+            Self::UnsupportedRetinaSize(size) => write!(f, "Error::UnsupportedRetinaSize({})", size),
+            Self::HotspotOutOfBounds(hotspot) => write!(f, "Error::HotspotOutOfBounds({:?})", hotspot),
         }
     }
 }
 
 impl<K: AsSize + Send + Sync + Debug> error::Error for IconError<K> {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        if let Self::Resample(ref err) = self {
-            err.source()
-        } else {
-            None
+        match self {
+            Self::Resample(ref err) => err.source(),
+            Self::WhileAdding(_, ref err) => Some(err),
+            _ => None,
         }
     }
 }
@@ -454,10 +1930,9 @@ impl<K: AsSize + Send + Sync> From<io::Error> for IconError<K> {
 
 impl<K: AsSize + Send + Sync> Into<io::Error> for IconError<K> {
     fn into(self) -> io::Error {
-        if let Self::Resample(err) = self {
-            err.into()
-        } else {
-            io::Error::from(io::ErrorKind::InvalidInput)
+        match self {
+            Self::Resample(err) | Self::WhileAdding(_, err) => err.into(),
+            _ => io::Error::new(io::ErrorKind::InvalidInput, self.to_string()),
         }
     }
 }
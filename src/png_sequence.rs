@@ -0,0 +1,669 @@
+//! Structs for encoding _png sequences_.
+
+extern crate tar;
+extern crate zip;
+
+use crate::{encode, AsSize, IconError, Icon, Image, Keys, DEFAULT_MAX_SIZE};
+use image::DynamicImage;
+use std::{
+    io::{self, Cursor, Write},
+    path::{Component, Path, PathBuf},
+};
+
+/// An encoder for sequences of _PNG_ files.
+///
+/// The output of `PngSequence::write` is a `.tar` archive
+/// containing one _PNG_ file per entry, stored under the
+/// path of its associated key.
+#[derive(Clone, Debug)]
+pub struct PngSequence {
+    sequence: Sequence,
+}
+
+/// An encoder for sequences of _PNG_ files.
+///
+/// The output of `PngZip::write` is a `.zip` archive
+/// containing one _PNG_ file per entry, stored under the
+/// path of its associated key.
+#[derive(Clone, Debug)]
+pub struct PngZip {
+    sequence: Sequence,
+    compression: Compression,
+}
+
+#[cfg(feature = "avif")]
+/// An encoder for sequences of _AVIF_ files.
+///
+/// The output of `AvifSequence::write` is a `.tar` archive
+/// containing one _AVIF_ file per entry, stored under the
+/// path of its associated key.
+///
+/// This encoder is only available with the `avif` feature.
+#[derive(Clone, Debug)]
+pub struct AvifSequence {
+    sequence: Sequence,
+    quality: u8,
+}
+
+#[cfg(feature = "webp")]
+/// An encoder for sequences of lossy _WebP_ files.
+///
+/// The output of `WebpSequence::write` is a `.tar` archive
+/// containing one _WebP_ file per entry, stored under the
+/// path of its associated key.
+///
+/// This encoder is only available with the `webp` feature.
+#[derive(Clone, Debug)]
+pub struct WebpSequence {
+    sequence: Sequence,
+    quality: u8,
+}
+
+/// The _key type_ for `PngSequence`, `PngZip`, `AvifSequence` and `WebpSequence`. A `Key`
+/// associates the size of an entry with its path in the archive.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    size: u32,
+    path: PathBuf,
+}
+
+/// The compression method of the entries of a `PngZip`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Compression {
+    /// Entries are stored uncompressed. Since _PNG_ files are
+    /// already compressed, this is often about as compact as
+    /// `Compression::Deflated`.
+    Stored,
+    /// Entries are compressed using the _deflate_ algorithm,
+    /// at its default compression level (`6`).
+    Deflated,
+}
+
+#[derive(Clone, Debug)]
+/// The shared core of the _png sequence_ encoders: a
+/// list of encoded _PNG_ files, in insertion order.
+struct Sequence {
+    entries: Vec<(Key, Vec<u8>)>,
+    template: Option<String>,
+    max_size: u32,
+}
+
+impl PngSequence {
+    /// Creates a new sequence whose entries are stored under a path
+    /// formatted from `template`, instead of the path of their key.
+    ///
+    /// The `{size}`, `{width}` and `{height}` placeholders of `template`
+    /// are replaced by the corresponding dimensions of each entry.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if
+    ///   `template` contains none of the placeholders, in which case all
+    ///   entries would share the same path, or if it does not describe
+    ///   a valid path for a `Key`.
+    /// * Otherwise returns `Ok(sequence)`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let mut icon = PngSequence::with_template("icons/icon-{size}.png")?;
+    ///
+    /// // Stored as `icons/icon-32.png`
+    /// icon.add_entry(resample::linear, &src, Key::from(32, "32/icon.png")?)?;
+    /// ```
+    pub fn with_template(template: &str) -> io::Result<Self> {
+        let has_placeholder = ["{size}", "{width}", "{height}"]
+            .iter()
+            .any(|placeholder| template.contains(placeholder));
+
+        if !has_placeholder {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        Key::from(1, render(template, 1))?;
+
+        let mut icon = PngSequence::new();
+        icon.sequence.template = Some(template.to_owned());
+
+        Ok(icon)
+    }
+
+    #[inline]
+    /// Sets the size limit, in pixels, of the entries of the sequence.
+    ///
+    /// Adding a larger entry fails with `IconError::InvalidSize`.
+    /// This option defaults to `DEFAULT_MAX_SIZE` (`4096`).
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let png = PngSequence::new().max_size(8192);
+    /// ```
+    pub fn max_size(&mut self, size: u32) -> &mut Self {
+        self.sequence.max_size = size;
+        self
+    }
+}
+
+impl PngZip {
+    #[inline]
+    /// Sets the compression method of the entries of the archive.
+    ///
+    /// This option defaults to `Compression::Deflated`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let zip = PngZip::new().compression(Compression::Stored);
+    /// ```
+    pub fn compression(&mut self, compression: Compression) -> &mut Self {
+        self.compression = compression;
+        self
+    }
+
+    #[inline]
+    /// Sets the size limit, in pixels, of the entries of the archive.
+    /// See `PngSequence::max_size`.
+    pub fn max_size(&mut self, size: u32) -> &mut Self {
+        self.sequence.max_size = size;
+        self
+    }
+}
+
+#[cfg(feature = "avif")]
+impl AvifSequence {
+    #[inline]
+    /// Sets the _AVIF_ quality of the entries added after this call,
+    /// ranging from `1` (smallest output) to `100` (best quality).
+    ///
+    /// This option defaults to `80`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let avif = AvifSequence::new().quality(60);
+    /// ```
+    pub fn quality(&mut self, quality: u8) -> &mut Self {
+        self.quality = quality;
+        self
+    }
+
+    #[inline]
+    /// Sets the size limit, in pixels, of the entries of the sequence.
+    /// See `PngSequence::max_size`.
+    pub fn max_size(&mut self, size: u32) -> &mut Self {
+        self.sequence.max_size = size;
+        self
+    }
+}
+
+#[cfg(feature = "webp")]
+impl WebpSequence {
+    #[inline]
+    /// Sets the _WebP_ quality of the entries added after this call,
+    /// ranging from `0` (smallest output) to `100` (best quality).
+    ///
+    /// This option defaults to `80`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let webp = WebpSequence::new().quality(60);
+    /// ```
+    pub fn quality(&mut self, quality: u8) -> &mut Self {
+        self.quality = quality;
+        self
+    }
+
+    #[inline]
+    /// Sets the size limit, in pixels, of the entries of the sequence.
+    /// See `PngSequence::max_size`.
+    pub fn max_size(&mut self, size: u32) -> &mut Self {
+        self.sequence.max_size = size;
+        self
+    }
+}
+
+impl Icon for PngSequence {
+    type Key = Key;
+
+    fn with_capacity(capacity: usize) -> Self {
+        PngSequence {
+            sequence: Sequence::with_capacity(capacity),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.sequence.entries.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.sequence.entries.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.sequence.entries.reserve(additional);
+    }
+
+    fn keys(&self) -> Keys<Self::Key> {
+        self.sequence.keys()
+    }
+
+    fn contains(&self, key: Self::Key) -> bool {
+        self.sequence.position(&key).is_some()
+    }
+
+    fn add_entry<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        filter: F,
+        source: &Image,
+        key: Self::Key,
+    ) -> Result<(), IconError<Self::Key>> {
+        self.sequence.add_entry(filter, source, key, encode::png)
+    }
+
+    fn get_entry(&self, key: Self::Key) -> Option<DynamicImage> {
+        self.sequence.get_entry(&key)
+    }
+
+    fn remove_entry(&mut self, key: Self::Key) -> bool {
+        self.sequence.remove_entry(&key)
+    }
+
+    fn clear(&mut self) {
+        self.sequence.entries.clear();
+    }
+
+    fn write<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        self.sequence.write_tar(w)
+    }
+}
+
+impl Icon for PngZip {
+    type Key = Key;
+
+    fn with_capacity(capacity: usize) -> Self {
+        PngZip {
+            sequence: Sequence::with_capacity(capacity),
+            compression: Compression::Deflated,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.sequence.entries.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.sequence.entries.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.sequence.entries.reserve(additional);
+    }
+
+    fn keys(&self) -> Keys<Self::Key> {
+        self.sequence.keys()
+    }
+
+    fn contains(&self, key: Self::Key) -> bool {
+        self.sequence.position(&key).is_some()
+    }
+
+    fn add_entry<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        filter: F,
+        source: &Image,
+        key: Self::Key,
+    ) -> Result<(), IconError<Self::Key>> {
+        self.sequence.add_entry(filter, source, key, encode::png)
+    }
+
+    fn get_entry(&self, key: Self::Key) -> Option<DynamicImage> {
+        self.sequence.get_entry(&key)
+    }
+
+    fn remove_entry(&mut self, key: Self::Key) -> bool {
+        self.sequence.remove_entry(&key)
+    }
+
+    fn clear(&mut self) {
+        self.sequence.entries.clear();
+    }
+
+    fn write<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        // zip::ZipWriter requires the writer to be seekable
+        let mut zip_writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default()
+            .compression_method(self.compression.into())
+            .unix_permissions(0o644);
+
+        for (key, buf) in &self.sequence.entries {
+            zip_writer.start_file(zip_name(&key.path), options)?;
+            zip_writer.write_all(buf)?;
+        }
+
+        let output = zip_writer.finish()?;
+        w.write_all(output.get_ref())
+    }
+}
+
+impl IntoIterator for &PngSequence {
+    type Item = Key;
+    type IntoIter = Keys<Key>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys()
+    }
+}
+
+impl IntoIterator for &PngZip {
+    type Item = Key;
+    type IntoIter = Keys<Key>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys()
+    }
+}
+
+#[cfg(feature = "avif")]
+impl Icon for AvifSequence {
+    type Key = Key;
+
+    fn with_capacity(capacity: usize) -> Self {
+        AvifSequence {
+            sequence: Sequence::with_capacity(capacity),
+            quality: 80,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.sequence.entries.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.sequence.entries.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.sequence.entries.reserve(additional);
+    }
+
+    fn keys(&self) -> Keys<Self::Key> {
+        self.sequence.keys()
+    }
+
+    fn contains(&self, key: Self::Key) -> bool {
+        self.sequence.position(&key).is_some()
+    }
+
+    fn add_entry<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        filter: F,
+        source: &Image,
+        key: Self::Key,
+    ) -> Result<(), IconError<Self::Key>> {
+        let quality = self.quality;
+        self.sequence
+            .add_entry(filter, source, key, |icon| encode::avif(icon, quality))
+    }
+
+    fn remove_entry(&mut self, key: Self::Key) -> bool {
+        self.sequence.remove_entry(&key)
+    }
+
+    fn clear(&mut self) {
+        self.sequence.entries.clear();
+    }
+
+    fn write<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        self.sequence.write_tar(w)
+    }
+}
+
+#[cfg(feature = "avif")]
+impl IntoIterator for &AvifSequence {
+    type Item = Key;
+    type IntoIter = Keys<Key>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys()
+    }
+}
+
+#[cfg(feature = "webp")]
+impl Icon for WebpSequence {
+    type Key = Key;
+
+    fn with_capacity(capacity: usize) -> Self {
+        WebpSequence {
+            sequence: Sequence::with_capacity(capacity),
+            quality: 80,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.sequence.entries.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.sequence.entries.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.sequence.entries.reserve(additional);
+    }
+
+    fn keys(&self) -> Keys<Self::Key> {
+        self.sequence.keys()
+    }
+
+    fn contains(&self, key: Self::Key) -> bool {
+        self.sequence.position(&key).is_some()
+    }
+
+    fn add_entry<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+        &mut self,
+        filter: F,
+        source: &Image,
+        key: Self::Key,
+    ) -> Result<(), IconError<Self::Key>> {
+        let quality = self.quality;
+        self.sequence
+            .add_entry(filter, source, key, |icon| encode::webp(icon, quality))
+    }
+
+    fn remove_entry(&mut self, key: Self::Key) -> bool {
+        self.sequence.remove_entry(&key)
+    }
+
+    fn clear(&mut self) {
+        self.sequence.entries.clear();
+    }
+
+    fn write<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        self.sequence.write_tar(w)
+    }
+}
+
+#[cfg(feature = "webp")]
+impl IntoIterator for &WebpSequence {
+    type Item = Key;
+    type IntoIter = Keys<Key>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys()
+    }
+}
+
+impl Sequence {
+    fn with_capacity(capacity: usize) -> Self {
+        Sequence {
+            entries: Vec::with_capacity(capacity),
+            template: None,
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    fn keys(&self) -> Keys<Key> {
+        let keys: Vec<Key> = self.entries.iter().map(|(key, _)| key.clone()).collect();
+        Keys::from(keys)
+    }
+
+    /// Returns the index of the entry stored under the path of `key`.
+    fn position(&self, key: &Key) -> Option<usize> {
+        let key = self.resolve(key.clone()).ok()?;
+        self.entries.iter().position(|(k, _)| k.path == key.path)
+    }
+
+    /// Returns `key`, stored under the path formatted from
+    /// the template of the sequence if it has one.
+    fn resolve(&self, key: Key) -> io::Result<Key> {
+        match &self.template {
+            Some(template) => Key::from(key.size, render(template, key.size)),
+            None => Ok(key),
+        }
+    }
+
+    /// Rasterizes `source` to the dimensions of `key` and stores the
+    /// output of `encoder` under the path of `key`.
+    fn add_entry<F, E>(
+        &mut self,
+        filter: F,
+        source: &Image,
+        key: Key,
+        encoder: E,
+    ) -> Result<(), IconError<Key>>
+    where
+        F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>,
+        E: FnOnce(&DynamicImage) -> io::Result<Vec<u8>>,
+    {
+        if key.size > self.max_size {
+            return Err(IconError::InvalidSize { requested: key.size, max: self.max_size });
+        }
+
+        let key = self.resolve(key)?;
+
+        if self.position(&key).is_some() {
+            return Err(IconError::AlreadyIncluded(key));
+        }
+
+        let icon = source.rasterize_to(filter, key.as_dimensions())?;
+        let buf = encoder(&icon)?;
+
+        self.entries.push((key, buf));
+        Ok(())
+    }
+
+    fn get_entry(&self, key: &Key) -> Option<DynamicImage> {
+        let (_, buf) = &self.entries[self.position(key)?];
+        image::load_from_memory(buf).ok()
+    }
+
+    fn remove_entry(&mut self, key: &Key) -> bool {
+        if let Some(index) = self.position(key) {
+            self.entries.remove(index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Writes the entries to a `.tar` archive.
+    fn write_tar<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut tar_builder = tar::Builder::new(w);
+
+        for (key, buf) in &self.entries {
+            write_data(&mut tar_builder, buf.as_ref(), &key.path)?;
+        }
+
+        tar_builder.finish()
+    }
+}
+
+impl Key {
+    /// Creates a `Key` for a _`size`x`size`_ entry stored under `path`.
+    ///
+    /// # Return Value
+    ///
+    /// * Returns `Err(io::Error::from(io::ErrorKind::InvalidInput))` if
+    ///   `size` is zero or if `path` is empty, absolute or refers to
+    ///   a parent directory.
+    /// * Otherwise returns `Ok(key)`.
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let key = Key::from(32, "32/icon.png")?;
+    /// ```
+    pub fn from<P: AsRef<Path>>(size: u32, path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        let is_valid = size != 0
+            && path.file_name().is_some()
+            && path.is_relative()
+            && !path.components().any(|c| c == Component::ParentDir);
+
+        if is_valid {
+            Ok(Key { size, path: path.to_path_buf() })
+        } else {
+            Err(io::Error::from(io::ErrorKind::InvalidInput))
+        }
+    }
+
+    /// Returns the path of the entry associated with this key.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsSize for Key {
+    fn as_size(&self) -> u32 {
+        self.size
+    }
+}
+
+impl From<Compression> for zip::CompressionMethod {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::Stored => zip::CompressionMethod::Stored,
+            Compression::Deflated => zip::CompressionMethod::Deflated,
+        }
+    }
+}
+
+/// Helper function to format the path of a _`size`x`size`_
+/// entry from a `PngSequence` template.
+fn render(template: &str, size: u32) -> String {
+    let size = size.to_string();
+
+    template
+        .replace("{size}", &size)
+        .replace("{width}", &size)
+        .replace("{height}", &size)
+}
+
+/// Helper function to convert a path to the name of a `.zip`
+/// file entry, which always uses `/` as a separator.
+fn zip_name(path: &Path) -> String {
+    let components: Vec<_> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+
+    components.join("/")
+}
+
+#[inline]
+/// Helper function to append a buffer to a `.tar` file
+fn write_data<W: Write>(builder: &mut tar::Builder<W>, data: &[u8], path: &Path) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+
+    builder.append_data(&mut header, path, data)
+}
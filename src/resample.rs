@@ -2,19 +2,111 @@
 
 use crate::ResReResampleError;
 use std::io;
-use image::{imageops, DynamicImage, ImageBuffer, GenericImageView, FilterType, Bgra};
+use image::{imageops, DynamicImage, ImageBuffer, GenericImageView, FilterType, Bgra, Pixel, Rgba, RgbaImage};
 use resvg::{usvg::{self, Tree}, raqote::DrawTarget , FitTo};
 
+/// A trade-off between the speed and the quality of a resampling filter.
+///
+/// See `resample::preset`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Quality {
+    /// Favors speed over quality.
+    Fast,
+    /// A reasonable compromise between speed and quality.
+    Balanced,
+    /// Favors quality over speed.
+    Best,
+}
+
 /// [Linear resampling filter](https://en.wikipedia.org/wiki/Linear_interpolation).
 pub fn linear(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
     overfit(&scale(source, size, FilterType::Triangle)?, size)
 }
 
 /// [Lanczos resampling filter](https://en.wikipedia.org/wiki/Lanczos_resampling).
+///
+/// Despite its name, this filter has always been backed by the Lanczos
+/// filter of the `image` crate, and is an alias of `resample::lanczos3`.
+/// Use `resample::catmull_rom` or `resample::mitchell` for cubic filtering.
+#[deprecated(note = "use resample::lanczos3")]
 pub fn cubic(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
+    lanczos3(source, size)
+}
+
+/// [Lanczos resampling filter](https://en.wikipedia.org/wiki/Lanczos_resampling)
+/// with a window of 3 lobes.
+pub fn lanczos3(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
     overfit(&scale(source, size, FilterType::Lanczos3)?, size)
 }
 
+/// [Mitchell–Netravali resampling filter](https://en.wikipedia.org/wiki/Mitchell%E2%80%93Netravali_filters)
+/// with `B = C = 1/3`.
+pub fn mitchell(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
+    let (nw, nh) = fit_dimensions(source.dimensions(), size);
+    let scaled = convolve(source, nw, nh, mitchell_kernel, 2.0);
+
+    overfit(&DynamicImage::ImageRgba8(scaled), size)
+}
+
+/// Area-averaging (box) resampling filter.
+///
+/// Each pixel of the output is the average of the pixels of the source
+/// image it covers, weighted by their coverage. This makes it well suited
+/// for downscaling _pixel-art_ by integer factors, where `nearest` aliases
+/// and `linear` blurs.
+pub fn area(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
+    let (w, h) = source.dimensions();
+    let (nw, nh) = fit_dimensions((w, h), size);
+    let scaled = separable(source, &area_weights(w, nw), &area_weights(h, nh));
+
+    overfit(&DynamicImage::ImageRgba8(scaled), size)
+}
+
+/// [Catmull-Rom resampling filter](https://en.wikipedia.org/wiki/Cubic_Hermite_spline#Catmull%E2%80%93Rom_spline),
+/// the cubic filter with `B = 0` and `C = 1/2`.
+///
+/// Unlike the deprecated `cubic`, which is backed by the Lanczos filter
+/// of the `image` crate, this filter is an interpolating cubic spline: it
+/// is sharper than `mitchell` and has less ringing than `lanczos3`.
+pub fn catmull_rom(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
+    let (nw, nh) = fit_dimensions(source.dimensions(), size);
+    let scaled = convolve(source, nw, nh, catmull_rom_kernel, 2.0);
+
+    overfit(&DynamicImage::ImageRgba8(scaled), size)
+}
+
+/// Gamma-correct [Lanczos resampling filter](https://en.wikipedia.org/wiki/Lanczos_resampling)
+/// with a window of 3 lobes.
+///
+/// The source image is converted from _sRGB_ to linear light and its
+/// alpha channel is premultiplied before resampling, which prevents
+/// antialiased edges from getting darkened by the downscaling.
+pub fn lanczos3_srgb(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
+    let (nw, nh) = fit_dimensions(source.dimensions(), size);
+    let source = source.to_rgba();
+
+    let pixels: Vec<[f32; 4]> = source
+        .pixels()
+        .map(|p| {
+            let a = p[3] as f32 / 255.0;
+            [srgb_to_linear(p[0]) * a, srgb_to_linear(p[1]) * a, srgb_to_linear(p[2]) * a, a]
+        })
+        .collect();
+
+    let scaled = convolve_f32(&pixels, source.dimensions(), (nw, nh), lanczos3_kernel, 3.0);
+    let mut output = RgbaImage::new(nw, nh);
+
+    for (px, [r, g, b, a]) in output.pixels_mut().zip(scaled) {
+        *px = if a > 0.0 {
+            Rgba([linear_to_srgb(r / a), linear_to_srgb(g / a), linear_to_srgb(b / a), to_u8(a * 255.0)])
+        } else {
+            Rgba([0, 0, 0, 0])
+        };
+    }
+
+    overfit(&DynamicImage::ImageRgba8(output), size)
+}
+
 /// [Nearest-Neighbor resampling filter](https://en.wikipedia.org/wiki/Nearest-neighbor_interpolation).
 pub fn nearest(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
     let scaled = if source.width() < size && source.height() < size {
@@ -26,6 +118,371 @@ pub fn nearest(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
     overfit(&scaled, size)
 }
 
+/// [Nearest-Neighbor resampling filter](https://en.wikipedia.org/wiki/Nearest-neighbor_interpolation)
+/// snapping to the pixel grid of `source`.
+///
+/// If `size` is an exact integer multiple of the largest dimension of `source`,
+/// each source pixel is replicated into a block of _`scale`x`scale`_ pixels,
+/// with no rounding artifacts. This keeps upscaled pixel art crisp. Otherwise
+/// this falls back to `resample::nearest`.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// // An 8x8 sprite becomes 4x4 blocks of a 32x32 entry
+/// icon.add_entry(resample::nearest_integer, &sprite, Key(32))?;
+/// ```
+pub fn nearest_integer(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
+    let (w, h) = source.dimensions();
+    let max = w.max(h);
+    let scale = size.checked_div(max).unwrap_or(0);
+
+    if scale == 0 || scale * max != size {
+        return nearest(source, size);
+    }
+
+    let source = source.to_rgba();
+    let output = RgbaImage::from_fn(w * scale, h * scale, |x, y| *source.get_pixel(x / scale, y / scale));
+
+    overfit(&DynamicImage::ImageRgba8(output), size)
+}
+
+/// Returns the built-in resampling filter named `name`, such as `"lanczos3"`.
+///
+/// The names match the ones of the filter functions of this module.
+///
+/// # Return Value
+///
+/// * Returns `Some(filter)` if `name` is the name of a built-in filter.
+/// * Otherwise returns `None`.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let filter = resample::by_name(&config.filter).unwrap_or(resample::linear);
+/// icon.add_entry(filter, &src, Key(32))?;
+/// ```
+pub fn by_name(name: &str) -> Option<fn(&DynamicImage, u32) -> io::Result<DynamicImage>> {
+    match name {
+        "nearest" => Some(nearest),
+        "nearest_integer" => Some(nearest_integer),
+        "linear" => Some(linear),
+        // Kept for compatibility with the deprecated `resample::cubic`
+        "cubic" => Some(lanczos3),
+        "lanczos3" => Some(lanczos3),
+        "lanczos3_srgb" => Some(lanczos3_srgb),
+        "mitchell" => Some(mitchell),
+        "catmull_rom" => Some(catmull_rom),
+        "area" => Some(area),
+        _ => None,
+    }
+}
+
+/// Returns the built-in resampling filter corresponding to `quality`:
+///
+/// * `Quality::Fast` maps to `resample::nearest`.
+/// * `Quality::Balanced` maps to `resample::linear`.
+/// * `Quality::Best` maps to `resample::lanczos3`.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// icon.add_entry(resample::preset(Quality::Best), &src, Key(32))?;
+/// ```
+pub fn preset(quality: Quality) -> fn(&DynamicImage, u32) -> io::Result<DynamicImage> {
+    match quality {
+        Quality::Fast => nearest,
+        Quality::Balanced => linear,
+        Quality::Best => lanczos3,
+    }
+}
+
+/// Adapts a resampling filter to operate on images with premultiplied alpha.
+///
+/// The source image is premultiplied before being passed to `filter`,
+/// and the output of `filter` is unpremultiplied afterwards. This
+/// prevents the color of fully transparent pixels from bleeding into
+/// the semi-transparent edges of the re-scaled image.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// icon.add_entry(resample::premultiplied(resample::linear), &src, Key(32))?;
+/// ```
+pub fn premultiplied<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+    mut filter: F,
+) -> impl FnMut(&DynamicImage, u32) -> io::Result<DynamicImage> {
+    move |source, size| {
+        let mut buf = source.to_rgba();
+
+        for pixel in buf.pixels_mut() {
+            let a = pixel[3] as u32;
+
+            for c in 0..3 {
+                pixel[c] = ((pixel[c] as u32 * a + 127) / 255) as u8;
+            }
+        }
+
+        let mut output = filter(&DynamicImage::ImageRgba8(buf), size)?.to_rgba();
+
+        for pixel in output.pixels_mut() {
+            match pixel[3] as u32 {
+                0 => *pixel = Rgba([0, 0, 0, 0]),
+                a => {
+                    for c in 0..3 {
+                        pixel[c] = ((pixel[c] as u32 * 255 + a / 2) / a).min(255) as u8;
+                    }
+                }
+            }
+        }
+
+        Ok(DynamicImage::ImageRgba8(output))
+    }
+}
+
+/// Creates a resampling filter that applies a
+/// [Gaussian blur](https://en.wikipedia.org/wiki/Gaussian_blur) to the source
+/// image before re-scaling it with the `linear` filter, which suppresses
+/// moiré patterns in heavily downscaled artwork.
+///
+/// The standard deviation of the blur is `sigma` scaled by how much the source
+/// image is shrunk, so that no blur is applied when the source image is not
+/// downscaled and the blur grows with the downscale ratio.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// icon.add_entry(resample::gaussian(0.5), &src, Key(16))?;
+/// ```
+pub fn gaussian(sigma: f32) -> impl FnMut(&DynamicImage, u32) -> io::Result<DynamicImage> {
+    move |source, size| {
+        let (w, h) = source.dimensions();
+        let ratio = w.max(h) as f32 / size as f32;
+        let sigma = sigma * (ratio - 1.0);
+
+        // Blurs below a tenth of a pixel are imperceptible
+        if sigma < 0.1 {
+            linear(source, size)
+        } else {
+            linear(&DynamicImage::ImageRgba8(imageops::blur(source, sigma)), size)
+        }
+    }
+}
+
+/// Adapts a resampling filter to render images at `factor` times the
+/// requested size, and then downscale them with the `area` filter.
+///
+/// This reduces the aliasing of filters that are prone to it, such as
+/// `nearest`, at the cost of additional processing. If `factor` is `1`,
+/// the output of `filter` is returned unchanged.
+///
+/// Resampling filters are not applied to _SVG_ sources, which are always
+/// rendered directly at the requested size with anti-aliasing, so this
+/// adaptor has no effect on them. To supersample an _SVG_ source, rasterize
+/// it at the larger size beforehand and downscale the result instead.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// icon.add_entry(resample::supersample(4, resample::nearest), &src, Key(16))?;
+///
+/// let large = Image::from(svg.rasterize(resample::nearest, 4 * 16)?);
+/// icon.add_entry(resample::area, &large, Key(16))?;
+/// ```
+pub fn supersample<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+    factor: u32,
+    mut filter: F,
+) -> impl FnMut(&DynamicImage, u32) -> io::Result<DynamicImage> {
+    move |source, size| {
+        if factor <= 1 {
+            return filter(source, size);
+        }
+
+        let large = filter(source, size * factor)?;
+        area(&large, size)
+    }
+}
+
+/// Adapts a resampling filter to downscale large images progressively.
+///
+/// The source image is repeatedly halved by averaging blocks of _2x2_
+/// pixels, until it is within twice the requested size, and `filter` is
+/// only applied for the final step. This _mip-chain_ downscaling preserves
+/// thin details that single-step filters such as `nearest` skip over when
+/// the ratio between the source and the requested size is large.
+///
+/// Note that resampling filters are not applied to _SVG_ sources, which
+/// are always rendered directly at the requested size.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// icon.add_entry(resample::progressive(resample::linear), &src, Key(16))?;
+/// ```
+pub fn progressive<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+    mut filter: F,
+) -> impl FnMut(&DynamicImage, u32) -> io::Result<DynamicImage> {
+    move |source, size| {
+        let (w, h) = source.dimensions();
+
+        if w.max(h) / 2 <= size {
+            return filter(source, size);
+        }
+
+        let mut image = halve(&source.to_rgba());
+
+        while image.width().max(image.height()) / 2 > size {
+            image = halve(&image);
+        }
+
+        filter(&DynamicImage::ImageRgba8(image), size)
+    }
+}
+
+/// Adapts a resampling filter to convert its output to grayscale,
+/// preserving its alpha channel.
+///
+/// The luma of each pixel is computed with the _Rec. 709_ coefficients,
+/// as for _sRGB_ images. This is useful for tinted icons, such as _macOS_
+/// template images.
+///
+/// Note that resampling filters are not applied to _SVG_ sources, which
+/// are always rendered directly at the requested size.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// icon.add_entry(resample::grayscale(resample::linear), &src, Key(32))?;
+/// ```
+pub fn grayscale<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+    mut filter: F,
+) -> impl FnMut(&DynamicImage, u32) -> io::Result<DynamicImage> {
+    move |source, size| {
+        let mut output = filter(source, size)?.to_rgba();
+
+        for pixel in output.pixels_mut() {
+            let l = luma(pixel);
+            *pixel = Rgba([l, l, l, pixel[3]]);
+        }
+
+        Ok(DynamicImage::ImageRgba8(output))
+    }
+}
+
+/// Adapts a resampling filter to convert its output to black and white,
+/// preserving its alpha channel.
+///
+/// Pixels whose luma is at least `threshold` become white, and the
+/// remaining ones become black. This is useful for monochrome icons,
+/// such as _Windows_ tray icons.
+///
+/// Note that resampling filters are not applied to _SVG_ sources, which
+/// are always rendered directly at the requested size.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// icon.add_entry(resample::monochrome(resample::linear, 128), &src, Key(16))?;
+/// ```
+pub fn monochrome<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+    mut filter: F,
+    threshold: u8,
+) -> impl FnMut(&DynamicImage, u32) -> io::Result<DynamicImage> {
+    move |source, size| {
+        let mut output = filter(source, size)?.to_rgba();
+
+        for pixel in output.pixels_mut() {
+            let l = if luma(pixel) >= threshold { 255 } else { 0 };
+            *pixel = Rgba([l, l, l, pixel[3]]);
+        }
+
+        Ok(DynamicImage::ImageRgba8(output))
+    }
+}
+
+/// Adapts a resampling filter to sharpen its output with an
+/// _unsharp mask_ of strength `amount`, preserving its alpha channel.
+///
+/// Each color channel is pushed away from a _3x3_ blur of its
+/// neighbourhood by `amount` times their difference, so an `amount`
+/// of `0.0` leaves the output of `filter` untouched. Amounts between
+/// `0.3` and `0.5` work well for _16x16_ entries, while _32x32_ entries
+/// usually call for a subtler `0.2` to `0.3`.
+///
+/// Note that resampling filters are not applied to _SVG_ sources, which
+/// are always rendered directly at the requested size.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// icon.add_entry(resample::sharpen(resample::lanczos3, 0.4), &src, Key(16))?;
+/// ```
+pub fn sharpen<F: FnMut(&DynamicImage, u32) -> io::Result<DynamicImage>>(
+    mut filter: F,
+    amount: f32,
+) -> impl FnMut(&DynamicImage, u32) -> io::Result<DynamicImage> {
+    move |source, size| {
+        let input = filter(source, size)?.to_rgba();
+        let (w, h) = input.dimensions();
+        let mut output = input.clone();
+
+        for (x, y, pixel) in output.enumerate_pixels_mut() {
+            for c in 0..3 {
+                let blur = blur_channel(&input, (x, y), (w, h), c);
+                let value = f32::from(pixel[c]);
+
+                pixel[c] = to_u8(value + amount * (value - blur));
+            }
+        }
+
+        Ok(DynamicImage::ImageRgba8(output))
+    }
+}
+
+/// Returns the value of channel `c` of the pixel at `(x, y)`
+/// blurred by a _3x3_ binomial kernel, clamping at the edges.
+fn blur_channel(image: &RgbaImage, (x, y): (u32, u32), (w, h): (u32, u32), c: usize) -> f32 {
+    const WEIGHTS: [f32; 3] = [1.0, 2.0, 1.0];
+    let mut sum = 0.0;
+
+    for (j, wy) in WEIGHTS.iter().enumerate() {
+        let sy = (y + j as u32).max(1).min(h) - 1;
+
+        for (i, wx) in WEIGHTS.iter().enumerate() {
+            let sx = (x + i as u32).max(1).min(w) - 1;
+            sum += wx * wy * f32::from(image.get_pixel(sx, sy)[c]);
+        }
+    }
+
+    sum / 16.0
+}
+
+/// Halves the dimensions of `image`, averaging each block of _2x2_ pixels.
+/// Blocks in the last row or column of an odd-sized image are averaged
+/// over the pixels they cover.
+fn halve(image: &RgbaImage) -> RgbaImage {
+    let (w, h) = image.dimensions();
+
+    RgbaImage::from_fn(w / 2 + w % 2, h / 2 + h % 2, |x, y| {
+        let (x0, y0) = (2 * x, 2 * y);
+        let (x1, y1) = ((x0 + 2).min(w), (y0 + 2).min(h));
+        let n = ((x1 - x0) * (y1 - y0)) as f32;
+
+        let mut acc = [0.0f32; 4];
+        for sy in y0..y1 {
+            for sx in x0..x1 {
+                let p = image.get_pixel(sx, sy);
+
+                for c in 0..4 {
+                    acc[c] += f32::from(p[c]);
+                }
+            }
+        }
+
+        Rgba([to_u8(acc[0] / n), to_u8(acc[1] / n), to_u8(acc[2] / n), to_u8(acc[3] / n)])
+    })
+}
+
 fn nearest_upscale_integer(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
     let (w ,  h) = source.dimensions();
 
@@ -37,13 +494,277 @@ fn nearest_upscale_integer(source: &DynamicImage, size: u32) -> io::Result<Dynam
 
 /// Rescales `source` to fit in _`size`x`size`_ image.
 fn scale(source: &DynamicImage, size: u32, filter: FilterType) -> io::Result<DynamicImage> {
-    let (w ,  h) = source.dimensions();
-
-    let (nw, nh) = if w > h { (size, (size * h) / w) } else { ((size * w) / h, size) };
+    let (nw, nh) = fit_dimensions(source.dimensions(), size);
 
     Ok(DynamicImage::ImageRgba8(imageops::resize(source, nw, nh, filter)))
 }
 
+/// Computes the luma of `pixel` with the _Rec. 709_ coefficients.
+fn luma(pixel: &Rgba<u8>) -> u8 {
+    let l = 2126 * u32::from(pixel[0]) + 7152 * u32::from(pixel[1]) + 722 * u32::from(pixel[2]);
+    ((l + 5000) / 10000) as u8
+}
+
+/// Computes the dimensions of an image of dimensions `(w, h)`
+/// rescaled to fit in a _`size`x`size`_ image.
+fn fit_dimensions((w, h): (u32, u32), size: u32) -> (u32, u32) {
+    if w > h { (size, (size * h) / w) } else { ((size * w) / h, size) }
+}
+
+/// The Mitchell–Netravali cubic kernel, with `B = C = 1/3`.
+fn mitchell_kernel(x: f32) -> f32 {
+    bicubic_kernel(1.0 / 3.0, 1.0 / 3.0, x)
+}
+
+/// The Catmull-Rom cubic kernel, with `B = 0` and `C = 1/2`.
+fn catmull_rom_kernel(x: f32) -> f32 {
+    bicubic_kernel(0.0, 0.5, x)
+}
+
+/// The family of cubic kernels described by Mitchell and Netravali.
+fn bicubic_kernel(b: f32, c: f32, x: f32) -> f32 {
+    let x = x.abs();
+
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+            + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2)
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x < 2.0 {
+        ((-b - 6.0 * c) * x.powi(3)
+            + (6.0 * b + 30.0 * c) * x.powi(2)
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+/// The Lanczos kernel, with a window of 3 lobes.
+fn lanczos3_kernel(x: f32) -> f32 {
+    use std::f32::consts::PI;
+
+    let x = x.abs();
+
+    if x < std::f32::EPSILON {
+        1.0
+    } else if x < 3.0 {
+        3.0 * (PI * x).sin() * (PI * x / 3.0).sin() / (PI * PI * x * x)
+    } else {
+        0.0
+    }
+}
+
+/// Converts an _sRGB_ sample to linear light, in the `[0, 1]` range.
+fn srgb_to_linear(v: u8) -> f32 {
+    let v = v as f32 / 255.0;
+
+    if v <= 0.040_45 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear light sample, in the `[0, 1]` range, to _sRGB_.
+fn linear_to_srgb(v: f32) -> u8 {
+    let v = if v <= 0.0 {
+        0.0
+    } else if v >= 1.0 {
+        1.0
+    } else if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+
+    to_u8(v * 255.0)
+}
+
+/// Resizes `source` to _`nw`x`nh`_ by applying the separable
+/// convolution `kernel`, of radius `support`, along each axis.
+fn convolve<K: Fn(f32) -> f32>(
+    source: &DynamicImage,
+    nw: u32,
+    nh: u32,
+    kernel: K,
+    support: f32,
+) -> RgbaImage {
+    let (w, h) = source.dimensions();
+    let horizontal = kernel_weights(w, nw, &kernel, support);
+    let vertical = kernel_weights(h, nh, &kernel, support);
+
+    separable(source, &horizontal, &vertical)
+}
+
+/// Resizes `source` by applying the `horizontal` and `vertical`
+/// weights, as returned by `kernel_weights`, along each axis.
+fn separable(
+    source: &DynamicImage,
+    horizontal: &[(usize, Vec<f32>)],
+    vertical: &[(usize, Vec<f32>)],
+) -> RgbaImage {
+    let source = source.to_rgba();
+    let (nw, nh) = (horizontal.len() as u32, vertical.len() as u32);
+
+    let pixels: Vec<[f32; 4]> = source
+        .pixels()
+        .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32, p[3] as f32])
+        .collect();
+
+    let scaled = weighted_sum(&pixels, source.dimensions(), horizontal, vertical);
+    let mut output = RgbaImage::new(nw, nh);
+
+    for (px, acc) in output.pixels_mut().zip(scaled) {
+        *px = Rgba([to_u8(acc[0]), to_u8(acc[1]), to_u8(acc[2]), to_u8(acc[3])]);
+    }
+
+    output
+}
+
+/// Resizes a _`w`x`h`_ buffer of floating-point _RGBA_ pixels to
+/// _`nw`x`nh`_ by applying the separable convolution `kernel`, of
+/// radius `support`, along each axis.
+fn convolve_f32<K: Fn(f32) -> f32>(
+    pixels: &[[f32; 4]],
+    (w, h): (u32, u32),
+    (nw, nh): (u32, u32),
+    kernel: K,
+    support: f32,
+) -> Vec<[f32; 4]> {
+    let horizontal = kernel_weights(w, nw, &kernel, support);
+    let vertical = kernel_weights(h, nh, &kernel, support);
+
+    weighted_sum(pixels, (w, h), &horizontal, &vertical)
+}
+
+/// Resizes a _`w`x`h`_ buffer of floating-point _RGBA_ pixels by
+/// applying the `horizontal` and `vertical` weights along each axis.
+fn weighted_sum(
+    pixels: &[[f32; 4]],
+    (w, h): (u32, u32),
+    horizontal: &[(usize, Vec<f32>)],
+    vertical: &[(usize, Vec<f32>)],
+) -> Vec<[f32; 4]> {
+    let (nw, nh) = (horizontal.len() as u32, vertical.len() as u32);
+
+    // Horizontal pass: w x h -> nw x h
+    let mut buf = vec![[0.0; 4]; (nw * h) as usize];
+
+    for y in 0..h as usize {
+        for (x, (start, ws)) in horizontal.iter().enumerate() {
+            let mut acc = [0.0; 4];
+
+            for (i, weight) in ws.iter().enumerate() {
+                let p = pixels[y * w as usize + start + i];
+
+                for c in 0..4 {
+                    acc[c] += p[c] * weight;
+                }
+            }
+
+            buf[y * nw as usize + x] = acc;
+        }
+    }
+
+    // Vertical pass: nw x h -> nw x nh
+    let mut output = vec![[0.0; 4]; (nw * nh) as usize];
+
+    for (y, (start, ws)) in vertical.iter().enumerate() {
+        for x in 0..nw as usize {
+            let mut acc = [0.0; 4];
+
+            for (i, weight) in ws.iter().enumerate() {
+                let p = buf[(start + i) * nw as usize + x];
+
+                for c in 0..4 {
+                    acc[c] += p[c] * weight;
+                }
+            }
+
+            output[y * nw as usize + x] = acc;
+        }
+    }
+
+    output
+}
+
+#[inline]
+/// Rounds a sample to the nearest `u8`, saturating
+/// values outside of the `[0, 255]` range.
+fn to_u8(v: f32) -> u8 {
+    if v <= 0.0 {
+        0
+    } else if v >= 255.0 {
+        255
+    } else {
+        v.round() as u8
+    }
+}
+
+/// Computes the normalized kernel weights used to resample a
+/// row of `len` samples into a row of `new_len` samples.
+///
+/// For each output sample, returns the index of the first input
+/// sample it depends on, along with the weights of each input sample.
+fn kernel_weights<K: Fn(f32) -> f32>(
+    len: u32,
+    new_len: u32,
+    kernel: &K,
+    support: f32,
+) -> Vec<(usize, Vec<f32>)> {
+    let ratio = len as f32 / new_len as f32;
+
+    // Widen the kernel when downscaling to avoid aliasing
+    let scale = ratio.max(1.0);
+    let radius = support * scale;
+
+    (0..new_len)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * ratio;
+            let start = (center - radius).floor().max(0.0) as usize;
+            let end = ((center + radius).ceil() as usize).min(len as usize);
+
+            let mut weights: Vec<f32> = (start..end)
+                .map(|j| kernel((j as f32 + 0.5 - center) / scale))
+                .collect();
+
+            let sum: f32 = weights.iter().sum();
+
+            if sum != 0.0 {
+                weights.iter_mut().for_each(|w| *w /= sum);
+            }
+
+            (start, weights)
+        })
+        .collect()
+}
+
+/// Computes the weights used to resample a row of `len` samples into a
+/// row of `new_len` samples by averaging the input samples covered by
+/// each output sample, in the same format as `kernel_weights`.
+///
+/// Input samples only partially covered by an output sample are weighted
+/// by the fraction of their area that lies within it.
+fn area_weights(len: u32, new_len: u32) -> Vec<(usize, Vec<f32>)> {
+    let ratio = len as f64 / new_len as f64;
+
+    (0..new_len)
+        .map(|i| {
+            let (lo, hi) = (i as f64 * ratio, (i + 1) as f64 * ratio);
+            let start = lo.floor() as usize;
+            let end = (hi.ceil() as usize).min(len as usize);
+
+            let weights: Vec<f32> = (start..end)
+                .map(|j| ((hi.min(j as f64 + 1.0) - lo.max(j as f64)) / ratio) as f32)
+                .collect();
+
+            (start, weights)
+        })
+        .collect()
+}
+
 /// Adds transparent borders to an image so that the output is square.
 fn overfit(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
     let mut output = DynamicImage::new_rgba8(size, size);
@@ -55,23 +776,78 @@ fn overfit(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
     Ok(output)
 }
 
+/// Pads or crops an image so that the output has dimensions
+/// `width`x`height`, keeping the image centered.
+pub(crate) fn fit(source: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+    let (w, h) = source.dimensions();
+    let (cw, ch) = (w.min(width), h.min(height));
+
+    let cropped = source.clone().crop((w - cw) / 2, (h - ch) / 2, cw, ch);
+    let mut output = DynamicImage::new_rgba8(width, height);
+
+    imageops::overlay(&mut output, &cropped, (width - cw) / 2, (height - ch) / 2);
+    output
+}
+
+/// Renders `source` directly at _`size`x`size`_ device pixels, so that
+/// no resampling of the rendered image is required.
 pub(crate) fn svg(source: &Tree, size: u32) -> Result<DynamicImage, ResReResampleError> {
-    let rect = source.svg_node().view_box.rect;
-    let (w, h) = (rect.width(), rect.height());
+    svg_with_background(source, size, None)
+}
+
+/// Renders `source` as `resample::svg` does, onto a canvas filled
+/// with `background`. A `background` of `None` is transparent.
+pub(crate) fn svg_with_background(
+    source: &Tree,
+    size: u32,
+    background: Option<usvg::Color>,
+) -> Result<DynamicImage, ResReResampleError> {
+    Ok(draw_target_to_rgba(render_svg(source, size, background), size)?)
+}
+
+/// Renders `source` as `resample::svg` does, appending the _RGBA_
+/// pixels of the output to `buf`.
+///
+/// Square documents are converted directly from the rendered surface,
+/// without allocating any intermediate image.
+pub(crate) fn svg_into(source: &Tree, size: u32, buf: &mut Vec<u8>) -> Result<(), ResReResampleError> {
+    let mut draw_target = render_svg(source, size, None);
+    let (w, h) = (draw_target.width() as u32, draw_target.height() as u32);
+
+    if (w, h) != (size, size) {
+        buf.extend_from_slice(&draw_target_to_rgba(draw_target, size)?.to_rgba());
+        return Ok(());
+    }
+
+    // Matches the output of `fit`, which blends the
+    // rendered image onto a transparent canvas
+    for p in draw_target.get_data_u8_mut().chunks_exact(4) {
+        let mut pixel = Rgba([0, 0, 0, 0]);
+        pixel.blend(&Rgba([p[2], p[1], p[0], p[3]]));
+
+        buf.extend_from_slice(&pixel.0);
+    }
+
+    Ok(())
+}
+
+/// Renders `source` to a surface whose longest edge is `size` pixels long.
+fn render_svg(source: &Tree, size: u32, background: Option<usvg::Color>) -> DrawTarget {
+    // resvg scales the document based on its size, not on its view box
+    let doc_size = source.svg_node().size;
+    let (w, h) = (doc_size.width(), doc_size.height());
     let fit_to = if w > h { FitTo::Width(size) } else { FitTo::Height(size) };
 
     let opts = resvg::Options {
         usvg: usvg::Options::default(),
         fit_to,
-        background: None
+        background,
     };
 
     // In this context it's safe to assume render_to_image will return Some(_)
     // https://github.com/RazrFalcon/resvg/issues/175#issuecomment-531477376
-    let draw_target = resvg::backend_raqote::render_to_image(source, &opts)
-        .expect("Could not render svg tree to image buffer");
-
-    Ok(draw_target_to_rgba(draw_target, size)?)
+    resvg::backend_raqote::render_to_image(source, &opts)
+        .expect("Could not render svg tree to image buffer")
 }
 
 #[inline]
@@ -83,7 +859,7 @@ fn draw_target_to_rgba(mut surface: DrawTarget, size: u32) -> io::Result<Dynamic
     // If ImageBuffer::from_vec returns None then there's a bug in
     // resvg
     match ImageBuffer::<Bgra<u8>, Vec<u8>>::from_vec(w, h, data) {
-        Some(buf) => overfit(&DynamicImage::ImageBgra8(buf), size),
+        Some(buf) => Ok(fit(&DynamicImage::ImageBgra8(buf), size, size)),
         None      => panic!("Buffer in not big enought")
     }
 }
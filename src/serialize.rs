@@ -0,0 +1,199 @@
+//! Implementations of the `serde` traits for the key and configuration
+//! types of the crate.
+//!
+//! Keys are (de)serialized as their size in pixels, so that a list of
+//! sizes in a configuration file can be deserialized to typed keys. The
+//! `icns::Key` variants at 2x density are represented by their logical
+//! size followed by `@2x`, such as `"16@2x"`, which is only supported by
+//! self-describing formats.
+
+use crate::{favicon::{self, ManifestConfig}, icns, ico, AsSize, FormatKind};
+use serde_core::{
+    de::{self, Deserializer, IgnoredAny, MapAccess, SeqAccess, Unexpected, Visitor},
+    ser::{SerializeStruct, Serializer},
+    Deserialize, Serialize,
+};
+use std::{convert::TryFrom, fmt};
+
+const FORMAT_NAMES: [&str; 5] = ["ico", "icns", "favicon", "png_sequence", "png_zip"];
+const MANIFEST_FIELDS: [&str; 2] = ["name", "theme_color"];
+
+macro_rules! size_key {
+    ($key: ty) => {
+        impl Serialize for $key {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_u32(self.as_size())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $key {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let size = u32::deserialize(deserializer)?;
+
+                <$key>::try_from(size).map_err(|_| {
+                    de::Error::invalid_value(Unexpected::Unsigned(u64::from(size)), &"a supported icon size")
+                })
+            }
+        }
+    };
+}
+
+size_key!(ico::Key);
+size_key!(favicon::Key);
+
+impl Serialize for icns::Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match retina_size(*self) {
+            Some(size) => serializer.serialize_str(&format!("{}@2x", size)),
+            None => serializer.serialize_u32(self.as_size()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for icns::Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(IcnsKeyVisitor)
+    }
+}
+
+struct IcnsKeyVisitor;
+
+impl<'de> Visitor<'de> for IcnsKeyVisitor {
+    type Value = icns::Key;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a supported icon size, such as `32` or `\"16@2x\"`")
+    }
+
+    fn visit_u64<E: de::Error>(self, size: u64) -> Result<icns::Key, E> {
+        u32::try_from(size)
+            .ok()
+            .and_then(|size| icns::Key::try_from(size).ok())
+            .ok_or_else(|| de::Error::invalid_value(Unexpected::Unsigned(size), &self))
+    }
+
+    fn visit_i64<E: de::Error>(self, size: i64) -> Result<icns::Key, E> {
+        match u64::try_from(size) {
+            Ok(size) => self.visit_u64(size),
+            Err(_) => Err(de::Error::invalid_value(Unexpected::Signed(size), &self)),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<icns::Key, E> {
+        let key = match s.find("@2x") {
+            Some(i) if i + 3 == s.len() => match &s[..i] {
+                "16" => Some(icns::Key::Rgba16At2x),
+                "32" => Some(icns::Key::Rgba32At2x),
+                "128" => Some(icns::Key::Rgba128At2x),
+                "256" => Some(icns::Key::Rgba256At2x),
+                _ => None,
+            },
+            Some(_) => None,
+            None => s.parse().ok(),
+        };
+
+        key.ok_or_else(|| de::Error::invalid_value(Unexpected::Str(s), &self))
+    }
+}
+
+/// Returns the logical size of the keys at 2x density.
+fn retina_size(key: icns::Key) -> Option<u32> {
+    match key {
+        icns::Key::Rgba16At2x => Some(16),
+        icns::Key::Rgba32At2x => Some(32),
+        icns::Key::Rgba128At2x => Some(128),
+        icns::Key::Rgba256At2x => Some(256),
+        _ => None,
+    }
+}
+
+impl Serialize for FormatKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let index = match self {
+            FormatKind::Ico => 0,
+            FormatKind::Icns => 1,
+            FormatKind::Favicon => 2,
+            FormatKind::PngSequence => 3,
+            FormatKind::PngZip => 4,
+        };
+
+        serializer.serialize_unit_variant("FormatKind", index, FORMAT_NAMES[index as usize])
+    }
+}
+
+impl<'de> Deserialize<'de> for FormatKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(FormatKindVisitor)
+    }
+}
+
+struct FormatKindVisitor;
+
+impl<'de> Visitor<'de> for FormatKindVisitor {
+    type Value = FormatKind;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the name of an icon format")
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> Result<FormatKind, E> {
+        match name {
+            "ico" => Ok(FormatKind::Ico),
+            "icns" => Ok(FormatKind::Icns),
+            "favicon" => Ok(FormatKind::Favicon),
+            "png_sequence" => Ok(FormatKind::PngSequence),
+            "png_zip" => Ok(FormatKind::PngZip),
+            _ => Err(de::Error::unknown_variant(name, &FORMAT_NAMES)),
+        }
+    }
+}
+
+impl Serialize for ManifestConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ManifestConfig", MANIFEST_FIELDS.len())?;
+        state.serialize_field("name", &self.name)?;
+        state.serialize_field("theme_color", &self.theme_color)?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for ManifestConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("ManifestConfig", &MANIFEST_FIELDS, ManifestConfigVisitor)
+    }
+}
+
+struct ManifestConfigVisitor;
+
+impl<'de> Visitor<'de> for ManifestConfigVisitor {
+    type Value = ManifestConfig;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a web app manifest configuration")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<ManifestConfig, A::Error> {
+        let mut config = ManifestConfig::default();
+
+        // Missing fields default to `None` and unknown ones are ignored
+        while let Some(field) = map.next_key::<String>()? {
+            match field.as_str() {
+                "name" => config.name = map.next_value()?,
+                "theme_color" => config.theme_color = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    // Non-self-describing formats encode the fields in order
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ManifestConfig, A::Error> {
+        let name = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let theme_color = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+
+        Ok(ManifestConfig { name, theme_color })
+    }
+}
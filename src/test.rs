@@ -1,16 +1,33 @@
+// The crate must also build without the `std-fs` feature, so that it can
+// target platforms with no filesystem such as `wasm32-unknown-unknown`. Tests
+// relying on the filesystem APIs of the crate are gated accordingly, and CI
+// checks both configurations with:
+//
+//     cargo test --no-default-features
+//     cargo check --no-default-features --target wasm32-unknown-unknown
+
 use crate::{
+    ani::Ani,
+    cache::{CachedSource, RasterCache},
+    convert,
     encode,
     favicon::{self, Favicon},
     icns::{self, Icns},
     ico::{self, Ico},
+    png_sequence::{self, PngSequence, PngZip},
     resample, Icon, Image,
 };
+use image::{DynamicImage, GenericImageView};
 use std::{
-    fs::File,
-    io::{self, BufWriter, Write},
+    io::{self, Write},
     path::Path,
 };
+#[cfg(feature = "std-fs")]
+use crate::bundle::Bundle;
+#[cfg(feature = "std-fs")]
+use std::{fs::File, io::BufWriter};
 
+#[cfg(feature = "std-fs")]
 #[test]
 fn test_resample() -> io::Result<()> {
     let mut file_near = File::create("tests/test_near.png").expect("Couldn't create file");
@@ -27,7 +44,7 @@ fn test_resample() -> io::Result<()> {
     let buf = encode::png(&hydra.rasterize(resample::linear, 32).expect("Failed"))?;
     file_linear.write_all(buf.as_ref())?;
 
-    let buf = encode::png(&hydra.rasterize(resample::cubic, 32).expect("Failed"))?;
+    let buf = encode::png(&hydra.rasterize(resample::lanczos3, 32).expect("Failed"))?;
     file_cubic.write_all(buf.as_ref())?;
 
     let buf = encode::png(&box_svg.rasterize(resample::nearest, 32).expect("Failed"))?;
@@ -36,6 +53,40 @@ fn test_resample() -> io::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_lanczos() {
+    // The left half is black and the right half is white
+    let source = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, _| {
+        if x < 32 { image::Rgba([0, 0, 0, 255]) } else { image::Rgba([255, 255, 255, 255]) }
+    }));
+
+    let icon = Image::from(source).rasterize(resample::lanczos3, 32).expect("Failed").to_rgba();
+    assert_eq!(icon.dimensions(), (32, 32));
+
+    // The `image` crate truncates the filtered values, so flat
+    // regions may lose a few units of each channel
+    let close = |p: &image::Rgba<u8>, q: [u8; 4]| (0..4).all(|c| (i32::from(p[c]) - i32::from(q[c])).abs() <= 3);
+
+    for y in 0..32 {
+        // Flat regions are preserved, away from the ringing of the edge
+        assert!(close(icon.get_pixel(4, y), [0, 0, 0, 255]));
+        assert!(close(icon.get_pixel(27, y), [255, 255, 255, 255]));
+
+        // The edge is interpolated between both sides
+        let (left, right) = (icon.get_pixel(15, y)[0], icon.get_pixel(16, y)[0]);
+        assert!(left < 128 && right > 128, "{} {}", left, right);
+    }
+
+    // Non-square sources are fit into transparent bars
+    let banner = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(64, 32, image::Rgba([255, 0, 0, 255])));
+    let icon = resample::lanczos3(&banner, 32).expect("Failed").to_rgba();
+
+    assert_eq!(icon.dimensions(), (32, 32));
+    assert_eq!(icon.get_pixel(16, 0)[3], 0);
+    assert!(close(icon.get_pixel(16, 16), [255, 0, 0, 255]));
+}
+
+#[cfg(feature = "std-fs")]
 #[test]
 fn test_ico() {
     let mut file = BufWriter::new(File::create("tests/test.ico").expect("Couldn't create file"));
@@ -64,6 +115,7 @@ fn test_ico() {
     }
 }
 
+#[cfg(feature = "std-fs")]
 #[test]
 fn test_icns() {
     let mut file = BufWriter::new(File::create("tests/test.icns").expect("Couldn't create file"));
@@ -85,6 +137,7 @@ fn test_icns() {
     }
 }
 
+#[cfg(feature = "std-fs")]
 #[test]
 fn test_favicon() {
     let path = Path::new("tests/favicon/");
@@ -99,7 +152,7 @@ fn test_favicon() {
         panic!("{:?}", err);
     }
 
-    if let Err(err) = icon.add_entries(resample::cubic, &bbox, entries) {
+    if let Err(err) = icon.add_entries(resample::lanczos3, &bbox, entries) {
         panic!("{:?}", err);
     }
 
@@ -108,18 +161,3188 @@ fn test_favicon() {
     }
 }
 
-/*fn test_png() {
-    let mut file = File::create("tests/test.tar").expect("Couldn't create file");
+#[test]
+fn test_remove_entry() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+
+    let mut ico = Ico::new();
+    ico.add_entry(resample::nearest, &img, ico::Key(32)).expect("Failed");
+    ico.add_entry(resample::nearest, &img, ico::Key(0)).expect("Failed");
+
+    assert!(ico.remove_entry(ico::Key(32)));
+    assert!(!ico.remove_entry(ico::Key(32)));
+    assert_eq!(ico.len(), 1);
+
+    // Re-adding a removed key should not trigger `AlreadyIncluded`
+    ico.add_entry(resample::nearest, &img, ico::Key(32)).expect("Failed");
+    assert_eq!(ico.len(), 2);
+
+    let mut icns = Icns::new();
+    icns.add_entry(resample::nearest, &img, icns::Key::Rgba32).expect("Failed");
+
+    assert!(icns.remove_entry(icns::Key::Rgba32));
+    assert!(!icns.remove_entry(icns::Key::Rgba32));
+    assert_eq!(icns.len(), 0);
+
+    icns.add_entry(resample::nearest, &img, icns::Key::Rgba32).expect("Failed");
+    assert_eq!(icns.len(), 1);
+}
+
+#[test]
+fn test_set_entry() {
+    let red = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+        32,
+        32,
+        image::Rgba([255, 0, 0, 255]),
+    )));
+    let blue = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+        32,
+        32,
+        image::Rgba([0, 0, 255, 255]),
+    )));
+
+    let mut ico = Ico::new();
+    ico.set_entry(resample::nearest, &red, ico::Key(32)).expect("Failed");
+    ico.set_entry(resample::nearest, &blue, ico::Key(32)).expect("Failed");
+    assert_eq!(ico.len(), 1);
+
+    let entry = ico.get_entry(ico::Key(32)).expect("Missing entry").to_rgba();
+    assert_eq!(entry.get_pixel(0, 0), &image::Rgba([0, 0, 255, 255]));
+
+    let mut icns = Icns::new();
+    icns.set_entry(resample::nearest, &red, icns::Key::Rgba32).expect("Failed");
+    icns.set_entry(resample::nearest, &blue, icns::Key::Rgba32).expect("Failed");
+    assert_eq!(icns.len(), 1);
+
+    let entry = icns.get_entry(icns::Key::Rgba32).expect("Missing entry").to_rgba();
+    assert_eq!(entry.get_pixel(0, 0), &image::Rgba([0, 0, 255, 255]));
+}
+
+#[test]
+fn test_rasterize_to() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+
+    let tile = img.rasterize_to(resample::linear, (16, 32)).expect("Failed");
+    assert_eq!(tile.dimensions(), (16, 32));
+
+    let tile = img.rasterize_to(resample::linear, (48, 24)).expect("Failed");
+    assert_eq!(tile.dimensions(), (48, 24));
+}
+
+#[test]
+fn test_from_bytes() -> io::Result<()> {
+    let png = encode::png(&DynamicImage::new_rgba8(24, 12))?;
+    let img = Image::from_bytes(&png)?;
+
+    if let Image::Svg(_) = img {
+        panic!("Expected a raster image");
+    }
+
+    assert_eq!(img.dimensions(), (24.0, 12.0));
+
+    let svg = b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"32\" height=\"16\">\
+        <rect width=\"32\" height=\"16\" fill=\"red\"/></svg>";
+    let img = Image::from_bytes(svg)?;
+
+    if let Image::Raster(_) = img {
+        panic!("Expected a vector image");
+    }
+
+    assert_eq!(img.dimensions(), (32.0, 16.0));
+
+    assert!(Image::from_bytes(b"not an image").is_err());
+
+    Ok(())
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_par_add_entries() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+
+    let mut icon = Icns::new();
+    let entries = vec![icns::Key::Rgba16, icns::Key::Rgba32, icns::Key::Rgba64];
+
+    icon.par_add_entries(resample::linear, &img, entries).expect("Failed");
+    assert_eq!(icon.len(), 3);
+
+    // Should fail before rasterizing any of the entries
+    let calls = std::sync::atomic::AtomicUsize::new(0);
+    let filter = |source: &DynamicImage, size: u32| {
+        calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        resample::linear(source, size)
+    };
+
+    match icon.par_add_entries(filter, &img, vec![icns::Key::Rgba128, icns::Key::Rgba32]) {
+        Err(crate::IconError::AlreadyIncluded(icns::Key::Rgba32)) => (),
+        _ => panic!("Expected `IconError::AlreadyIncluded`"),
+    }
+
+    assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 0);
+    assert_eq!(icon.len(), 3);
+}
+
+#[test]
+fn test_raster_cache() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut cache = RasterCache::new();
+    let mut calls = 0;
+
+    let mut ico = Ico::new();
+    let mut icns = Icns::new();
+
+    let mut filter = |src: &DynamicImage, size: u32| {
+        calls += 1;
+        resample::linear(src, size)
+    };
+
+    ico.add_entry_cached(&mut filter, "linear", &img, ico::Key(32), &mut cache)
+        .expect("Failed");
+
+    // The 32x32 image should be retrieved from the cache
+    icns.add_entry_cached(&mut filter, "linear", &img, icns::Key::Rgba32, &mut cache)
+        .expect("Failed");
+
+    assert_eq!(calls, 1);
+    assert_eq!(cache.len(), 1);
+    assert!(cache.contains("linear", (32, 32)));
+    assert_eq!(ico.len(), 1);
+    assert_eq!(icns.len(), 1);
+}
+
+#[test]
+fn test_cached_source() {
+    let src = CachedSource::with_capacity(Image::from(DynamicImage::new_rgba8(64, 64)), 2);
+    let mut calls = 0;
+
+    let mut ico = Ico::new();
+    let mut icns = Icns::new();
+
+    let mut filter = |src: &DynamicImage, size: u32| {
+        calls += 1;
+        resample::linear(src, size)
+    };
+
+    src.add_entry(&mut ico, &mut filter, ico::Key(32)).expect("Failed");
+
+    // The 32x32 image should be served from the cache
+    src.add_entry(&mut icns, &mut filter, icns::Key::Rgba32).expect("Failed");
+    assert_eq!(src.len(), 1);
+
+    // The least recently used image is evicted
+    src.rasterize(&mut filter, 16).expect("Failed");
+    src.rasterize(&mut filter, 64).expect("Failed");
+    src.rasterize(&mut filter, 32).expect("Failed");
+    assert_eq!(src.len(), 2);
+
+    src.rasterize(&mut filter, 32).expect("Failed");
+    src.rasterize(&mut filter, 64).expect("Failed");
+
+    assert_eq!(calls, 4);
+    assert_eq!(ico.len(), 1);
+    assert_eq!(icns.len(), 1);
+
+    let svg = b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"32\" height=\"32\">\
+        <rect width=\"32\" height=\"32\" fill=\"red\"/></svg>";
+    let src = CachedSource::from(Image::from_bytes(svg).expect("Failed"));
+
+    let a = src.rasterize(resample::nearest, 48).expect("Failed");
+    let b = src.rasterize(resample::linear, 48).expect("Failed");
+    assert_eq!(a.raw_pixels(), b.raw_pixels());
+    assert_eq!(src.len(), 1);
+}
+
+#[test]
+fn test_ico_from_reader() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut buf = Vec::new();
+
+    let mut icon = Ico::new();
+    icon.add_entries(resample::nearest, &img, vec![ico::Key(16), ico::Key(0)])
+        .expect("Failed");
+    icon.write(&mut buf).expect("Failed");
+
+    let mut icon = Ico::from_reader(buf.as_slice()).expect("Failed");
+    assert_eq!(icon.len(), 2);
+
+    // Should fail
+    assert!(icon.add_entry(resample::nearest, &img, ico::Key(16)).is_err());
+    assert!(icon.add_entry(resample::nearest, &img, ico::Key(0)).is_err());
+
+    // Should pass
+    icon.add_entry(resample::nearest, &img, ico::Key(32)).expect("Failed");
+    assert_eq!(icon.len(), 3);
+}
+
+/// Returns an `.ico` file containing both a _4-bit_ and a _24-bit_
+/// _32x32_ entry, as well as a _16x16_ entry.
+fn fixture_multi_depth_ico() -> Vec<u8> {
+    let img = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, y| {
+        image::Rgba([(x * 4) as u8, (y * 4) as u8, 0, 255])
+    })));
+
+    let mut low = Ico::with_encoding(ico::IcoEncoding::AllBmp);
+    low.set_bit_depth(ico::BitDepth::Four);
+    low.add_entry(resample::nearest, &img, ico::Key(32)).expect("Failed");
+
+    let mut high = Ico::with_encoding(ico::IcoEncoding::AllBmp);
+    high.add_entries(resample::nearest, &img, vec![ico::Key(32), ico::Key(16)]).expect("Failed");
+
+    let mut icon_dir = ::ico::IconDir::new(::ico::ResourceType::Icon);
+    for icon in &[low, high] {
+        let buf = icon.clone().write_to_vec().expect("Failed");
+        for entry in ::ico::IconDir::read(io::Cursor::new(buf)).expect("Failed").entries() {
+            icon_dir.add_entry(entry.clone());
+        }
+    }
+
+    let mut buf = Vec::new();
+    icon_dir.write(&mut buf).expect("Failed");
+    buf
+}
+
+#[test]
+fn test_ico_from_reader_multi_depth() {
+    let depths = |buf: Vec<u8>| -> Vec<(u32, u16)> {
+        let icon_dir = ::ico::IconDir::read(io::Cursor::new(buf)).expect("Failed");
+        icon_dir.entries().iter().map(|entry| (entry.width(), entry.bits_per_pixel())).collect()
+    };
+
+    let buf = fixture_multi_depth_ico();
+    assert_eq!(depths(buf.clone()), vec![(32, 4), (16, 24), (32, 24)]);
+
+    // Only the deepest entry of each size is kept
+    let mut icon = Ico::from_reader(buf.as_slice()).expect("Failed");
+    assert_eq!(icon.len(), 2);
+    assert_eq!(icon.keys().collect::<Vec<_>>(), vec![ico::Key(32), ico::Key(16)]);
+    assert_eq!(depths(icon.clone().write_to_vec().expect("Failed")), vec![(16, 24), (32, 24)]);
+
+    assert!(icon.remove_entry(ico::Key(32)));
+    assert_eq!(icon.len(), 1);
+    assert!(!icon.contains(ico::Key(32)));
+    assert_eq!(icon.keys().collect::<Vec<_>>(), vec![ico::Key(16)]);
+    assert_eq!(icon.entries().map(|(key, _)| key).collect::<Vec<_>>(), vec![ico::Key(16)]);
+
+    // Non-square entries are rejected
+    let mut icon_dir = ::ico::IconDir::new(::ico::ResourceType::Icon);
+    let image = ::ico::IconImage::from_rgba_data(32, 16, vec![0; 32 * 16 * 4]);
+    icon_dir.add_entry(::ico::IconDirEntry::encode(&image).expect("Failed"));
+
+    let mut buf = Vec::new();
+    icon_dir.write(&mut buf).expect("Failed");
+
+    match Ico::from_reader(buf.as_slice()) {
+        Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput),
+        Ok(_) => panic!("Expected an error for a non-square entry"),
+    }
+}
+
+#[test]
+fn test_contains() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+
+    let mut ico = Ico::new();
+    assert!(ico.is_empty());
+    assert!(!ico.contains(ico::Key(32)));
+
+    ico.add_entry(resample::nearest, &img, ico::Key(32)).expect("Failed");
+    assert!(!ico.is_empty());
+    assert!(ico.contains(ico::Key(32)));
+    assert!(!ico.contains(ico::Key(64)));
+
+    let mut icns = Icns::new();
+    assert!(!icns.contains(icns::Key::Rgba32));
+
+    icns.add_entry(resample::nearest, &img, icns::Key::Rgba32).expect("Failed");
+    assert!(icns.contains(icns::Key::Rgba32));
+
+    // A failed `add_entry` should not register the key
+    let mut filter = |_: &DynamicImage, _: u32| Ok(DynamicImage::new_rgba8(1, 1));
+    assert!(icns.add_entry(&mut filter, &img, icns::Key::Rgba64).is_err());
+    assert!(!icns.contains(icns::Key::Rgba64));
+
+    let mut fav = Favicon::new();
+    assert!(!fav.contains(favicon::Key(16)));
+
+    fav.add_entry(resample::nearest, &img, favicon::Key(16)).expect("Failed");
+    assert!(fav.contains(favicon::Key(16)));
+}
+
+#[cfg(feature = "std-fs")]
+#[test]
+fn test_iconset() {
+    let dir = std::env::temp_dir().join(format!("iconwriter_test_{}.iconset", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+
+    let mut icon = Icns::new();
+    let entries = vec![icns::Key::Rgba16, icns::Key::Rgba32, icns::Key::Rgba64];
+
+    icon.add_entries(resample::nearest, &img, entries).expect("Failed");
+    icon.save_iconset(&dir).expect("Failed");
+
+    for name in &["icon_16x16.png", "icon_16x16@2x.png", "icon_32x32.png", "icon_32x32@2x.png"] {
+        assert!(dir.join(name).is_file(), "Missing {}", name);
+    }
+
+    assert!(!dir.join("icon_128x128.png").exists());
+
+    std::fs::remove_dir_all(&dir).expect("Failed");
+}
+
+#[test]
+fn test_icns_retina() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut buf = Vec::new();
+
+    let mut icon = Icns::new();
+    icon.add_entry(resample::nearest, &img, icns::Key::Rgba256).expect("Failed");
+    icon.add_entry(resample::nearest, &img, icns::Key::Rgba128At2x).expect("Failed");
+    assert_eq!(icon.len(), 2);
+
+    icon.write(&mut buf).expect("Failed");
+
+    let family = ::icns::IconFamily::read(buf.as_slice()).expect("Failed");
+    let ostypes: Vec<String> = family.elements.iter().map(|e| e.ostype.to_string()).collect();
+
+    assert_eq!(ostypes, vec!["TOC ", "ic08", "ic13"]);
+
+    assert!(icon.remove_entry(icns::Key::Rgba128At2x));
+    assert!(icon.contains(icns::Key::Rgba256));
+}
+
+#[test]
+fn test_mitchell() {
+    let mut source = image::RgbaImage::new(48, 24);
+
+    for (x, y, pixel) in source.enumerate_pixels_mut() {
+        *pixel = image::Rgba([(x * 5) as u8, (y * 10) as u8, ((x + y) * 3) as u8, 255]);
+    }
+
+    let icon = resample::mitchell(&DynamicImage::ImageRgba8(source), 16).expect("Failed");
+    assert_eq!(icon.dimensions(), (16, 16));
+
+    let checksum: u64 = icon.to_rgba().into_raw().iter().map(|&b| b as u64).sum();
+    assert_eq!(checksum, 75840);
+
+    // Uniform images should remain uniform
+    let source = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(40, 40, image::Rgba([200, 100, 50, 255])));
+    let icon = resample::mitchell(&source, 16).expect("Failed").to_rgba();
+
+    assert!(icon.pixels().all(|p| *p == image::Rgba([200, 100, 50, 255])));
+}
+
+#[test]
+fn test_trimmed() -> io::Result<()> {
+    let mut source = image::RgbaImage::new(10, 10);
+
+    for x in 4..7 {
+        for y in 5..7 {
+            source.put_pixel(x, y, image::Rgba([255, 0, 0, 255]));
+        }
+    }
+
+    let img = Image::from(DynamicImage::ImageRgba8(source)).trimmed();
+    assert_eq!(img.dimensions(), (3.0, 2.0));
+
+    // Fully transparent images should be left unchanged
+    let img = Image::from(DynamicImage::new_rgba8(10, 10)).trimmed();
+    assert_eq!(img.dimensions(), (10.0, 10.0));
+
+    let svg = b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"100\" height=\"100\">\
+        <rect x=\"10\" y=\"10\" width=\"20\" height=\"5\" fill=\"red\"/></svg>";
+    let img = Image::from_bytes(svg)?;
+    let trimmed = img.trimmed();
+
+    assert_eq!(trimmed.dimensions(), (20.0, 5.0));
+    assert_eq!(img.dimensions(), (100.0, 100.0));
+
+    // The contents of the image should fill the rasterized icon
+    let icon = trimmed.rasterize(resample::nearest, 20).expect("Failed").to_rgba();
+    assert_eq!(icon.get_pixel(1, 10)[3], 255);
+    assert_eq!(icon.get_pixel(1, 1)[3], 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_background() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let background = image::Rgba([0, 0, 255, 255]);
+
+    let filter = |_: &DynamicImage, size: u32| {
+        let buf = image::RgbaImage::from_pixel(size, size, image::Rgba([255, 0, 0, 128]));
+        Ok(DynamicImage::ImageRgba8(buf))
+    };
+
+    let icon = img.rasterize_on_background(filter, 16, background).expect("Failed");
+
+    // Half-transparent pixels should be blended with the background
+    assert!(icon.to_rgba().pixels().all(|p| *p == image::Rgba([128, 0, 127, 255])));
+
+    let mut ico = Ico::new();
+    ico.add_entry_on_background(filter, &img, ico::Key(16), background).expect("Failed");
+    assert!(ico.contains(ico::Key(16)));
+}
+
+#[test]
+fn test_png_sequence() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut buf = Vec::new();
+
     let mut icon = PngSequence::new();
-    let img = Image::open("tests/hydra.png").expect("File not found");
     let entries = vec![
-        PngKey::from(32, "32/icon.png").unwrap(),
-        PngKey::from(64, "64/icon.png").unwrap(),
+        png_sequence::Key::from(32, "32/icon.png").expect("Invalid key"),
+        png_sequence::Key::from(64, "64/icon.png").expect("Invalid key"),
     ];
-    if let Err(err) = icon.add_entries(resample::linear, &img, entries) {
-        panic!("{:?}", err);
+
+    icon.add_entries(resample::linear, &img, entries).expect("Failed");
+    icon.write(&mut buf).expect("Failed");
+
+    let mut archive = tar::Archive::new(buf.as_slice());
+    let mut paths = Vec::new();
+
+    for entry in archive.entries().expect("Failed") {
+        let mut entry = entry.expect("Failed");
+        let mut data = Vec::new();
+        io::Read::read_to_end(&mut entry, &mut data).expect("Failed");
+
+        let png = image::load_from_memory(&data).expect("Invalid PNG");
+        let size = png.width();
+
+        assert_eq!(png.dimensions(), (size, size));
+        paths.push(entry.path().expect("Failed").into_owned());
     }
-    if let Err(err) = icon.write(&mut file) {
-        panic!("{:?}", err);
+
+    assert_eq!(paths, vec![Path::new("32/icon.png"), Path::new("64/icon.png")]);
+
+    assert!(png_sequence::Key::from(32, "").is_err());
+    assert!(png_sequence::Key::from(32, "/32/icon.png").is_err());
+    assert!(png_sequence::Key::from(32, "../icon.png").is_err());
+}
+
+#[test]
+fn test_png_sequence_template() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut icon = PngSequence::with_template("icon-{size}.png").expect("Invalid template");
+
+    let key = |size| png_sequence::Key::from(size, format!("{}/icon.png", size)).expect("Invalid key");
+    icon.add_entries(resample::linear, &img, vec![key(16), key(32)]).expect("Failed");
+
+    let paths: Vec<_> = icon.keys().map(|key| key.path().to_path_buf()).collect();
+    assert_eq!(paths, vec![Path::new("icon-16.png"), Path::new("icon-32.png")]);
+
+    // Keys are looked up by their formatted path
+    assert!(icon.contains(key(32)));
+    assert!(icon.add_entry(resample::linear, &img, key(32)).is_err());
+
+    let mut buf = Vec::new();
+    icon.write(&mut buf).expect("Failed");
+
+    let mut archive = tar::Archive::new(buf.as_slice());
+    let paths: Vec<_> = archive
+        .entries()
+        .expect("Failed")
+        .map(|entry| entry.expect("Failed").path().expect("Failed").into_owned())
+        .collect();
+    assert_eq!(paths, vec![Path::new("icon-16.png"), Path::new("icon-32.png")]);
+
+    assert!(icon.remove_entry(key(16)));
+
+    let mut icon = PngSequence::with_template("{width}x{height}/icon.png").expect("Invalid template");
+    icon.add_entry(resample::linear, &img, key(32)).expect("Failed");
+    assert_eq!(icon.keys().next().expect("Missing entry").path(), Path::new("32x32/icon.png"));
+
+    assert!(PngSequence::with_template("icon.png").is_err());
+    assert!(PngSequence::with_template("/icon-{size}.png").is_err());
+    assert!(PngSequence::with_template("../icon-{size}.png").is_err());
+}
+
+#[test]
+fn test_png_zip() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+
+    for &compression in &[png_sequence::Compression::Stored, png_sequence::Compression::Deflated] {
+        let mut buf = Vec::new();
+
+        let mut icon = PngZip::new();
+        let entries = vec![
+            png_sequence::Key::from(32, "32/icon.png").expect("Invalid key"),
+            png_sequence::Key::from(64, "64/icon.png").expect("Invalid key"),
+        ];
+
+        icon.compression(compression);
+        icon.add_entries(resample::linear, &img, entries).expect("Failed");
+
+        // Should fail
+        let key = png_sequence::Key::from(16, "32/icon.png").expect("Invalid key");
+        assert!(icon.add_entry(resample::linear, &img, key).is_err());
+
+        icon.write(&mut buf).expect("Failed");
+
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(buf)).expect("Invalid zip");
+        assert_eq!(archive.len(), 2);
+
+        let mut file = archive.by_name("64/icon.png").expect("Missing entry");
+        let mut data = Vec::new();
+        io::Read::read_to_end(&mut file, &mut data).expect("Failed");
+
+        let png = image::load_from_memory(&data).expect("Invalid PNG");
+        assert_eq!(png.dimensions(), (64, 64));
+    }
+}
+
+#[test]
+fn test_replace_entry() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut icon = Ico::new();
+    icon.add_entries(resample::linear, &img, vec![ico::Key(16), ico::Key(32)]).expect("Failed");
+
+    let keys: Vec<ico::Key> = icon.entries().map(|(key, _)| key).collect();
+    assert_eq!(keys, vec![ico::Key(16), ico::Key(32)]);
+
+    let mut replacement = image::RgbaImage::new(32, 32);
+    for pixel in replacement.pixels_mut() {
+        *pixel = image::Rgba([0, 128, 255, 255]);
+    }
+
+    let data = encode::png(&DynamicImage::ImageRgba8(replacement)).expect("Failed");
+    icon.replace_entry(ico::Key(32), &data).expect("Failed");
+
+    let (_, entry) = icon.entries().nth(1).expect("Missing entry");
+    assert_eq!(entry, data.as_slice());
+
+    // The icon should survive a round-trip through its encoded form
+    let buf = icon.write_to_vec().expect("Failed");
+    let mut icon = Ico::from_reader(buf.as_slice()).expect("Failed");
+    let image = icon.get_entry(ico::Key(32)).expect("Missing entry").to_rgba();
+    assert_eq!(image.get_pixel(0, 0), &image::Rgba([0, 128, 255, 255]));
+
+    let err = icon.replace_entry(ico::Key(16), &data).expect_err("Should fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let err = icon.replace_entry(ico::Key(48), &data).expect_err("Should fail");
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    let err = icon.replace_entry(ico::Key(32), b"not a png").expect_err("Should fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_reserve() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+
+    let mut icon = Ico::with_capacity(0);
+    icon.reserve(20);
+    assert!(icon.capacity() >= 20);
+
+    icon.add_entry(resample::linear, &img, ico::Key(16)).expect("Failed");
+    icon.reserve(20);
+    assert!(icon.capacity() >= 21);
+
+    let mut icon = Icns::with_capacity(0);
+    icon.reserve(10);
+    assert!(icon.capacity() >= 10);
+
+    let mut icon = PngSequence::with_capacity(0);
+    icon.reserve(10);
+    assert!(icon.capacity() >= 10);
+
+    let mut icon = Favicon::with_capacity(0);
+    icon.reserve(10);
+    assert!(icon.capacity() >= 10);
+}
+
+#[test]
+fn test_ico_to_icns() {
+    let mut source = image::RgbaImage::new(64, 64);
+    for pixel in source.pixels_mut() {
+        *pixel = image::Rgba([200, 40, 40, 255]);
+    }
+
+    let img = Image::from(DynamicImage::ImageRgba8(source));
+    let mut icon = Ico::new();
+    let entries = vec![ico::Key(16), ico::Key(32), ico::Key(48), ico::Key(0)];
+    icon.add_entries(resample::linear, &img, entries).expect("Failed");
+
+    let buf = icon.write_to_vec().expect("Failed");
+    let mut output = Vec::new();
+    convert::ico_to_icns(buf.as_slice(), &mut output).expect("Failed");
+
+    let family = ::icns::IconFamily::read(output.as_slice()).expect("Invalid ICNS");
+    let sizes: Vec<u32> = family.available_icons().iter().map(|t| t.pixel_width()).collect();
+
+    // 48x48 entries are not supported by ICNS
+    assert_eq!(sizes.len(), 3);
+    assert!(sizes.contains(&16) && sizes.contains(&32) && sizes.contains(&256));
+
+    let image = family.get_icon_with_type(family.available_icons()[0]).expect("Failed");
+    assert_eq!(&image.data()[..4], &[200, 40, 40, 255]);
+
+    // Only the deepest of several entries of the same size is converted
+    let mut output = Vec::new();
+    convert::ico_to_icns(fixture_multi_depth_ico().as_slice(), &mut output).expect("Failed");
+
+    let family = ::icns::IconFamily::read(output.as_slice()).expect("Invalid ICNS");
+    let mut sizes: Vec<u32> = family.available_icons().iter().map(|t| t.pixel_width()).collect();
+    sizes.sort();
+    assert_eq!(sizes, vec![16, 32]);
+
+    let icon_type = family.available_icons().into_iter().find(|t| t.pixel_width() == 32).expect("Missing entry");
+    let image = family.get_icon_with_type(icon_type).expect("Failed").convert_to(::icns::PixelFormat::RGBA);
+
+    let gradient = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, y| {
+        image::Rgba([(x * 4) as u8, (y * 4) as u8, 0, 255])
+    })));
+    let expected = gradient.rasterize(resample::nearest, 32).expect("Failed").to_rgba().into_raw();
+    assert_eq!(image.data(), &expected[..]);
+}
+
+#[cfg(feature = "std-fs")]
+#[test]
+fn test_svg_options() {
+    let data = br#"<svg xmlns="http://www.w3.org/2000/svg" width="1in" height="1in">
+        <rect width="100%" height="100%" fill="red"/>
+    </svg>"#;
+
+    let img = Image::from_bytes(data).expect("Invalid SVG");
+    assert_eq!(img.width(), 96.0);
+
+    let opts = crate::usvg::Options { dpi: 48.0, ..crate::usvg::Options::default() };
+    let img = Image::from_bytes_with_options(data, &opts).expect("Invalid SVG");
+    assert_eq!(img.width(), 48.0);
+
+    let path = std::env::temp_dir().join(format!("iconwriter_test_svg_options_{}.svg", std::process::id()));
+    std::fs::write(&path, &data[..]).expect("Failed");
+
+    let img = Image::open_with_options(&path, &opts).expect("Invalid SVG");
+    assert_eq!(img.height(), 48.0);
+
+    std::fs::remove_file(&path).expect("Failed");
+}
+
+#[cfg(feature = "std-fs")]
+#[test]
+fn test_open_missing() {
+    for path in &["tests/missing.png", "tests/missing.svg"] {
+        match Image::open(path) {
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::NotFound),
+            Ok(_) => panic!("Should fail"),
+        }
+    }
+}
+
+#[test]
+fn test_supersample() {
+    let mut source = image::RgbaImage::new(50, 50);
+    for (x, y, pixel) in source.enumerate_pixels_mut() {
+        let c = if (x / 3 + y / 3) % 2 == 0 { 255 } else { 0 };
+        *pixel = image::Rgba([c, c, c, 255]);
+    }
+
+    let source = DynamicImage::ImageRgba8(source);
+
+    // A factor of 1 is a passthrough
+    let icon = resample::supersample(1, resample::nearest)(&source, 16).expect("Failed");
+    let nearest = resample::nearest(&source, 16).expect("Failed");
+    assert_eq!(icon.to_rgba().into_raw(), nearest.to_rgba().into_raw());
+
+    let icon = resample::supersample(4, resample::nearest)(&source, 16).expect("Failed");
+    assert_eq!(icon.dimensions(), (16, 16));
+
+    // Unlike plain nearest-neighbor, supersampling produces intermediate tones
+    let rgba = icon.to_rgba();
+    assert!(rgba.pixels().any(|p| p[0] != 0 && p[0] != 255));
+
+    let mut ico = Ico::new();
+    let img = Image::from(source);
+    ico.add_entry(resample::supersample(4, resample::linear), &img, ico::Key(16)).expect("Failed");
+
+    // SVG sources are rendered directly, regardless of the filter
+    let svg = Image::from_bytes(
+        b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"32\" height=\"32\">\
+        <circle cx=\"16\" cy=\"16\" r=\"11\" fill=\"red\"/></svg>",
+    )
+    .expect("Failed");
+
+    let icon = svg.rasterize(resample::supersample(4, resample::nearest), 16).expect("Failed");
+    let direct = svg.rasterize(resample::nearest, 16).expect("Failed");
+    assert_eq!(icon.to_rgba().into_raw(), direct.to_rgba().into_raw());
+
+    // Unless they are rasterized at the larger size beforehand
+    let large = Image::from(svg.rasterize(resample::nearest, 4 * 16).expect("Failed"));
+    let icon = large.rasterize(resample::area, 16).expect("Failed");
+    assert_eq!(icon.dimensions(), (16, 16));
+    assert_ne!(icon.to_rgba().into_raw(), direct.to_rgba().into_raw());
+}
+
+#[test]
+fn test_area() {
+    let mut source = image::RgbaImage::new(64, 64);
+    for (x, y, pixel) in source.enumerate_pixels_mut() {
+        let c = if (x + y) % 2 == 0 { 255 } else { 0 };
+        *pixel = image::Rgba([c, c, c, 255]);
     }
-} */
+
+    let source = DynamicImage::ImageRgba8(source);
+
+    // Each 2x2 block of the checkerboard averages to the same gray
+    let icon = resample::area(&source, 32).expect("Failed").to_rgba();
+    assert_eq!(icon.dimensions(), (32, 32));
+    assert!(icon.pixels().all(|p| p == icon.get_pixel(0, 0)));
+    assert_eq!(icon.get_pixel(0, 0)[0], 128);
+
+    // Fractional coverage still produces a uniform average
+    let icon = resample::area(&source, 24).expect("Failed").to_rgba();
+    assert_eq!(icon.dimensions(), (24, 24));
+    assert!(icon.pixels().all(|p| (p[0] as i32 - 128).abs() <= 16 && p[3] == 255));
+}
+
+#[test]
+fn test_catmull_rom() {
+    let mut source = image::RgbaImage::new(48, 24);
+    for pixel in source.pixels_mut() {
+        *pixel = image::Rgba([40, 120, 200, 255]);
+    }
+
+    let source = DynamicImage::ImageRgba8(source);
+
+    for &size in &[16, 32, 96] {
+        let icon = resample::catmull_rom(&source, size).expect("Failed").to_rgba();
+        assert_eq!(icon.dimensions(), (size, size));
+
+        // The kernel is normalized, so flat colors are preserved
+        assert_eq!(icon.get_pixel(size / 2, size / 2), &image::Rgba([40, 120, 200, 255]));
+    }
+}
+
+#[test]
+fn test_add_entries_with() {
+    type Filter = fn(&DynamicImage, u32) -> io::Result<DynamicImage>;
+
+    let mut source = image::RgbaImage::new(64, 64);
+    for (x, y, pixel) in source.enumerate_pixels_mut() {
+        let c = if (x + y) % 2 == 0 { 255 } else { 0 };
+        *pixel = image::Rgba([c, c, c, 255]);
+    }
+
+    let img = Image::from(DynamicImage::ImageRgba8(source));
+    let mut icon = Ico::new();
+    let entries: Vec<(ico::Key, Filter)> = vec![
+        (ico::Key(32), resample::area),
+        (ico::Key(16), resample::nearest),
+    ];
+
+    icon.add_entries_with(&img, entries).expect("Failed");
+    assert_eq!(icon.keys().collect::<Vec<_>>(), vec![ico::Key(32), ico::Key(16)]);
+
+    // Each entry is re-scaled with its own filter
+    let area = icon.get_entry(ico::Key(32)).expect("Missing entry").to_rgba();
+    assert_eq!(area.get_pixel(0, 0)[0], 128);
+
+    let nearest = icon.get_entry(ico::Key(16)).expect("Missing entry").to_rgba();
+    assert!(nearest.pixels().all(|p| p[0] == 0 || p[0] == 255));
+
+    // The `AlreadyIncluded` check runs for each key
+    let entries: Vec<(ico::Key, Filter)> = vec![
+        (ico::Key(48), resample::linear),
+        (ico::Key(16), resample::linear),
+    ];
+
+    match icon.add_entries_with(&img, entries) {
+        Err(crate::IconError::AlreadyIncluded(key)) => assert_eq!(key, ico::Key(16)),
+        _ => panic!("Expected `IconError::AlreadyIncluded`"),
+    }
+
+    assert!(icon.contains(ico::Key(48)));
+}
+
+#[test]
+fn test_dominant_color() -> io::Result<()> {
+    let mut source = image::RgbaImage::new(32, 32);
+    for (x, _, pixel) in source.enumerate_pixels_mut() {
+        *pixel = if x < 8 {
+            image::Rgba([0, 0, 255, 255])
+        } else if x < 12 {
+            image::Rgba([0, 0, 0, 0])
+        } else {
+            image::Rgba([0, 128, 0, 200])
+        };
+    }
+
+    let img = Image::from(DynamicImage::ImageRgba8(source));
+    assert_eq!(img.dominant_color().expect("Failed"), image::Rgba([0, 128, 0, 255]));
+
+    let svg = b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"32\" height=\"32\">\
+        <rect width=\"32\" height=\"32\" fill=\"red\"/></svg>";
+    let img = Image::from_bytes(svg)?;
+    assert_eq!(img.dominant_color().expect("Failed"), image::Rgba([255, 0, 0, 255]));
+
+    // Fully transparent images yield opaque black
+    let img = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::new(16, 16)));
+    assert_eq!(img.dominant_color().expect("Failed"), image::Rgba([0, 0, 0, 255]));
+
+    // Rasterization errors are reported rather than replaced by black
+    let img = Image::generated(|_| DynamicImage::new_rgba8(8, 8));
+    match img.dominant_color() {
+        Err(crate::ResReResampleError::MismatchedDimensions(16, (8, 8))) => (),
+        _ => panic!("Expected `ResReResampleError::MismatchedDimensions`"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_rasterize_fit() {
+    // A filter which stretches the source to fill the whole entry
+    fn stretch(source: &DynamicImage, size: u32) -> io::Result<DynamicImage> {
+        Ok(source.resize_exact(size, size, image::FilterType::Nearest))
+    }
+
+    let source = image::RgbaImage::from_pixel(64, 32, image::Rgba([255, 0, 0, 255]));
+    let img = Image::from(DynamicImage::ImageRgba8(source));
+
+    let icon = img.rasterize_fit(stretch, 32).expect("Failed").to_rgba();
+    assert_eq!(icon.dimensions(), (32, 32));
+
+    for (_, y, pixel) in icon.enumerate_pixels() {
+        if !(8..24).contains(&y) {
+            assert_eq!(pixel[3], 0);
+        } else {
+            assert_eq!(*pixel, image::Rgba([255, 0, 0, 255]));
+        }
+    }
+
+    // `rasterize` leaves the aspect ratio up to the filter
+    let icon = img.rasterize(stretch, 32).expect("Failed").to_rgba();
+    assert!(icon.pixels().all(|pixel| pixel[3] == 255));
+}
+
+#[cfg(feature = "std-fs")]
+#[test]
+fn test_save_inferred() {
+    let img = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+        64,
+        64,
+        image::Rgba([0, 0, 255, 255]),
+    )));
+    let dir = std::env::temp_dir().join(format!("iconwriter_save_inferred_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Failed");
+
+    let path = dir.join("icon.ico");
+    crate::save_inferred(&img, &[16, 32], &path).expect("Failed");
+    let icon = Ico::from_reader(File::open(&path).expect("Missing file")).expect("Invalid ICO");
+    assert_eq!(icon.keys().collect::<Vec<_>>(), vec![ico::Key(16), ico::Key(32)]);
+
+    let path = dir.join("icon.ICNS");
+    crate::save_inferred(&img, &[16, 32], &path).expect("Failed");
+    let data = std::fs::read(&path).expect("Missing file");
+    assert!(data.starts_with(b"icns"));
+
+    // Sizes are validated against the inferred encoder
+    let err = crate::save_inferred(&img, &[48], dir.join("icon.icns")).expect_err("Should fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let err = crate::save_inferred(&img, &[16], dir.join("icon.png")).expect_err("Should fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(!dir.join("icon.png").exists());
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_from_bytes_with_format() -> io::Result<()> {
+    let source = image::RgbaImage::from_pixel(8, 4, image::Rgba([0, 255, 0, 255]));
+    let bmp = encode::bmp(&DynamicImage::ImageRgba8(source))?;
+
+    match Image::from_bytes_with_format(&bmp, image::ImageFormat::BMP)? {
+        Image::Raster(ras) => {
+            assert_eq!(ras.dimensions(), (8, 4));
+            assert_eq!(ras.to_rgba().get_pixel(0, 0), &image::Rgba([0, 255, 0, 255]));
+        }
+        _ => panic!("Expected a raster image"),
+    }
+
+    // The format hint is not second-guessed
+    match Image::from_bytes_with_format(&bmp, image::ImageFormat::PNG) {
+        Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+        Ok(_) => panic!("Expected an error"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_by_name() {
+    let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(24, 24, |x, y| {
+        image::Rgba([(x * 10) as u8, (y * 10) as u8, 0, 255])
+    }));
+
+    type Filter = fn(&DynamicImage, u32) -> io::Result<DynamicImage>;
+
+    let filters: [(&str, Filter); 8] = [
+        ("nearest", resample::nearest),
+        ("linear", resample::linear),
+        ("cubic", resample::lanczos3),
+        ("lanczos3", resample::lanczos3),
+        ("lanczos3_srgb", resample::lanczos3_srgb),
+        ("mitchell", resample::mitchell),
+        ("catmull_rom", resample::catmull_rom),
+        ("area", resample::area),
+    ];
+
+    for &(name, filter) in &filters {
+        let by_name = resample::by_name(name).expect("Missing filter");
+        let expected = filter(&img, 16).expect("Failed").to_rgba().into_raw();
+
+        assert_eq!(by_name(&img, 16).expect("Failed").to_rgba().into_raw(), expected);
+    }
+
+    assert!(resample::by_name("bicubic").is_none());
+    assert!(resample::by_name("Linear").is_none());
+}
+
+#[test]
+fn test_finish() {
+    struct FlushCounter {
+        data: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl Write for FlushCounter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut icon = Ico::new();
+    icon.add_entry(resample::nearest, &img, ico::Key(32)).expect("Failed");
+
+    let mut w = FlushCounter { data: Vec::new(), flushes: 0 };
+    icon.write(&mut w).expect("Failed");
+    assert_eq!(w.flushes, 0);
+
+    let mut w = FlushCounter { data: Vec::new(), flushes: 0 };
+    icon.finish(&mut w).expect("Failed");
+    assert_eq!(w.flushes, 1);
+    assert_eq!(w.data, icon.write_to_vec().expect("Failed"));
+}
+
+#[test]
+fn test_add_entries_from() {
+    let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+    let sources: Vec<Image> = colors
+        .iter()
+        .map(|&c| Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(48, 48, image::Rgba(c)))))
+        .collect();
+
+    let mut icon = Ico::new();
+    icon.add_entries_from(
+        resample::nearest,
+        vec![
+            (&sources[0], ico::Key(16)),
+            (&sources[1], ico::Key(32)),
+            (&sources[2], ico::Key(48)),
+        ],
+    )
+    .expect("Failed");
+
+    for (&key, &color) in [ico::Key(16), ico::Key(32), ico::Key(48)].iter().zip(colors.iter()) {
+        let entry = icon.get_entry(key).expect("Missing entry").to_rgba();
+        assert_eq!(entry.get_pixel(0, 0), &image::Rgba(color));
+    }
+
+    // Owned sources are accepted as well
+    let source = Image::from(DynamicImage::new_rgba8(64, 64));
+    match icon.add_entries_from(resample::nearest, vec![(source, ico::Key(32))]) {
+        Err(crate::IconError::AlreadyIncluded(key)) => assert_eq!(key, ico::Key(32)),
+        _ => panic!("Expected `IconError::AlreadyIncluded`"),
+    }
+}
+
+#[test]
+fn test_grayscale() {
+    let source = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(32, 32, |x, y| {
+        image::Rgba([(x * 8) as u8, (y * 8) as u8, 200, (x * 8) as u8])
+    }));
+
+    let mut filter = resample::grayscale(resample::nearest);
+    let output = filter(&source, 16).expect("Failed");
+    let expected = resample::nearest(&source, 16).expect("Failed").to_rgba();
+
+    assert_eq!(output.dimensions(), (16, 16));
+
+    for (pixel, expected) in output.to_rgba().pixels().zip(expected.pixels()) {
+        assert_eq!(pixel[0], pixel[1]);
+        assert_eq!(pixel[1], pixel[2]);
+        assert_eq!(pixel[3], expected[3]);
+    }
+
+    let mut filter = resample::monochrome(resample::nearest, 128);
+    let output = filter(&source, 16).expect("Failed").to_rgba();
+
+    assert!(output.pixels().all(|p| (p[0] == 0 || p[0] == 255) && p[0] == p[1] && p[1] == p[2]));
+    assert!(output.pixels().any(|p| p[0] == 0));
+    assert!(output.pixels().any(|p| p[0] == 255));
+
+    // The filters compose with `add_entry`
+    let img = Image::from(source);
+    let mut icon = Icns::new();
+    icon.add_entry(resample::grayscale(resample::linear), &img, icns::Key::Rgba32)
+        .expect("Failed");
+}
+
+#[test]
+fn test_sharpen() {
+    // A vertical edge between two shades of gray
+    let source = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(32, 32, |x, _| {
+        let l = if x < 16 { 64 } else { 192 };
+        image::Rgba([l, l, l, 200])
+    }));
+
+    let plain = resample::nearest(&source, 32).expect("Failed").to_rgba();
+    let mut filter = resample::sharpen(resample::nearest, 0.5);
+    let sharp = filter(&source, 32).expect("Failed").to_rgba();
+
+    assert_eq!(sharp.dimensions(), (32, 32));
+    assert!(sharp.pixels().all(|p| p[3] == 200));
+
+    let contrast = |image: &image::RgbaImage| {
+        i32::from(image.get_pixel(16, 8)[0]) - i32::from(image.get_pixel(15, 8)[0])
+    };
+    assert!(contrast(&sharp) > contrast(&plain));
+
+    // Flat regions are left untouched
+    assert_eq!(sharp.get_pixel(4, 4), plain.get_pixel(4, 4));
+    assert_eq!(sharp.get_pixel(28, 4), plain.get_pixel(28, 4));
+
+    // A zero amount is a no-op
+    let mut filter = resample::sharpen(resample::nearest, 0.0);
+    assert_eq!(filter(&source, 32).expect("Failed").to_rgba().into_raw(), plain.into_raw());
+}
+
+#[test]
+fn test_encoded_len() {
+    let img = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, y| {
+        image::Rgba([(x * 4) as u8, (y * 4) as u8, 0, (x * 4) as u8])
+    })));
+
+    let mut icon = Ico::new();
+    assert_eq!(icon.encoded_len(), icon.write_to_vec().expect("Failed").len());
+
+    icon.add_entries(resample::linear, &img, vec![ico::Key(16), ico::Key(32), ico::Key(0)])
+        .expect("Failed");
+    assert_eq!(icon.encoded_len(), icon.write_to_vec().expect("Failed").len());
+
+    let mut icon = Icns::new();
+    assert_eq!(icon.encoded_len(), icon.write_to_vec().expect("Failed").len());
+
+    icon.add_entries(
+        resample::linear,
+        &img,
+        vec![icns::Key::Rgba16, icns::Key::Rgba64, icns::Key::Rgba32At2x],
+    )
+    .expect("Failed");
+    assert_eq!(icon.encoded_len(), icon.write_to_vec().expect("Failed").len());
+}
+
+#[test]
+fn test_error_context() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut icon = Ico::new();
+
+    let filter = |src: &DynamicImage, size: u32| {
+        if size == 48 {
+            Err(io::Error::from(io::ErrorKind::Other))
+        } else {
+            resample::linear(src, size)
+        }
+    };
+
+    let keys = vec![ico::Key(16), ico::Key(48), ico::Key(64)];
+    let err = icon.add_entries(filter, &img, keys).expect_err("Should fail");
+
+    if let crate::IconError::WhileAdding(size, _) = err {
+        assert_eq!(size, 48);
+    } else {
+        panic!("Expected `IconError::WhileAdding`");
+    }
+
+    assert!(err.to_string().starts_with("failed adding 48x48 entry: "));
+
+    // Entries that precede the failing one are kept
+    assert!(icon.contains(ico::Key(16)));
+    assert!(!icon.contains(ico::Key(64)));
+
+    // Errors that already identify their key are left unchanged
+    let err = icon.add_entries(resample::linear, &img, vec![ico::Key(16)]).expect_err("Should fail");
+    if let crate::IconError::AlreadyIncluded(key) = err {
+        assert_eq!(key, ico::Key(16));
+    } else {
+        panic!("Expected `IconError::AlreadyIncluded`");
+    }
+}
+
+#[test]
+fn test_content_eq() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut other_source = image::RgbaImage::new(64, 64);
+    for pixel in other_source.pixels_mut() {
+        *pixel = image::Rgba([255, 0, 0, 255]);
+    }
+
+    let other_img = Image::from(DynamicImage::ImageRgba8(other_source));
+
+    let mut a = Ico::new();
+    a.add_entries(resample::linear, &img, vec![ico::Key(16), ico::Key(32)]).expect("Failed");
+
+    let mut b = Ico::new();
+    b.add_entries(resample::linear, &img, vec![ico::Key(32), ico::Key(16)]).expect("Failed");
+
+    assert_eq!(a, b);
+    assert_eq!(a.content_hash(), b.content_hash());
+
+    let mut c = Ico::new();
+    c.add_entries(resample::linear, &other_img, vec![ico::Key(16), ico::Key(32)]).expect("Failed");
+
+    assert_ne!(a, c);
+    assert_ne!(a.content_hash(), c.content_hash());
+
+    let mut a = Icns::new();
+    a.add_entries(resample::linear, &img, vec![icns::Key::Rgba16, icns::Key::Rgba64]).expect("Failed");
+
+    let mut b = Icns::new();
+    b.add_entries(resample::linear, &img, vec![icns::Key::Rgba64, icns::Key::Rgba16]).expect("Failed");
+
+    assert_eq!(a, b);
+    assert_eq!(a.content_hash(), b.content_hash());
+
+    let mut c = Icns::new();
+    c.add_entries(resample::linear, &other_img, vec![icns::Key::Rgba16, icns::Key::Rgba64]).expect("Failed");
+
+    assert_ne!(a, c);
+    assert_ne!(a.content_hash(), c.content_hash());
+}
+
+#[test]
+fn test_merge() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+
+    let mut icon = Ico::new();
+    icon.add_entries(resample::linear, &img, vec![ico::Key(16), ico::Key(32)]).expect("Failed");
+
+    let mut other = Ico::new();
+    other.add_entries(resample::linear, &img, vec![ico::Key(48), ico::Key(0)]).expect("Failed");
+
+    icon.merge(&other).expect("Failed");
+    assert_eq!(icon.len(), 4);
+    assert_eq!(icon.get_entry(ico::Key(0)).expect("Missing entry").dimensions(), (256, 256));
+
+    let mut colliding = Ico::new();
+    colliding.add_entries(resample::linear, &img, vec![ico::Key(24), ico::Key(32)]).expect("Failed");
+
+    match icon.merge(&colliding) {
+        Err(crate::IconError::AlreadyIncluded(key)) => assert_eq!(key, ico::Key(32)),
+        _ => panic!("Expected `IconError::AlreadyIncluded`"),
+    }
+
+    // A failed merge should leave the icon unchanged
+    assert_eq!(icon.len(), 4);
+    assert!(!icon.contains(ico::Key(24)));
+
+    let mut icns = Icns::new();
+    icns.add_entry(resample::linear, &img, icns::Key::Rgba32).expect("Failed");
+
+    let mut other = Icns::new();
+    other.add_entry(resample::linear, &img, icns::Key::Rgba16).expect("Failed");
+
+    icns.merge(&other).expect("Failed");
+    assert!(icns.get_entry(icns::Key::Rgba16).is_some());
+    assert!(icns.merge(&other).is_err());
+}
+
+#[cfg(feature = "std-fs")]
+#[test]
+fn test_write_to_vec() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut icon = Ico::new();
+    icon.add_entries(resample::linear, &img, vec![ico::Key(16), ico::Key(32)]).expect("Failed");
+
+    let path = std::env::temp_dir().join(format!("iconwriter_test_write_to_vec_{}.ico", std::process::id()));
+    icon.save(&path).expect("Failed");
+
+    let buf = icon.write_to_vec().expect("Failed");
+    assert_eq!(buf, std::fs::read(&path).expect("Failed"));
+
+    std::fs::remove_file(&path).expect("Failed");
+}
+
+#[test]
+fn test_icns_streaming() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let keys = vec![icns::Key::Rgba16, icns::Key::Rgba32At2x, icns::Key::Rgba256];
+
+    let mut icon = Icns::new();
+    icon.add_entries(resample::linear, &img, keys.clone()).expect("Failed");
+    let expected = icon.write_to_vec().expect("Failed");
+
+    // The icon does not have to start at the beginning of the writer
+    let mut cursor = io::Cursor::new(Vec::new());
+    cursor.write_all(b"prefix").expect("Failed");
+
+    let mut stream = Icns::streaming(cursor).expect("Failed");
+    for &key in &keys {
+        stream.add_entry(resample::linear, &img, key).expect("Failed");
+    }
+
+    assert!(stream.add_entry(resample::linear, &img, icns::Key::Rgba16).is_err());
+    assert_eq!(stream.keys().collect::<Vec<_>>(), keys);
+
+    let buf = stream.finish().expect("Failed").into_inner();
+    assert_eq!(&buf[..6], b"prefix");
+
+    // The stream writes the same elements, but no `TOC ` element
+    let toc_len = u32::from_be_bytes([expected[12], expected[13], expected[14], expected[15]]) as usize;
+    let stream_len = u32::from_be_bytes([buf[10], buf[11], buf[12], buf[13]]) as usize;
+    assert_eq!(&expected[8..12], b"TOC ");
+    assert_eq!(stream_len, expected.len() - toc_len);
+    assert_eq!(&buf[14..], &expected[8 + toc_len..]);
+}
+
+#[test]
+fn test_icns_legacy_masks() {
+    let mut source = image::RgbaImage::new(64, 64);
+    for (x, _, pixel) in source.enumerate_pixels_mut() {
+        *pixel = image::Rgba([255, 0, 0, if x < 32 { 255 } else { 0 }]);
+    }
+
+    let img = Image::from(DynamicImage::ImageRgba8(source));
+    let mut icon = Icns::new();
+    icon.add_entries(resample::nearest, &img, vec![icns::Key::Rgba16, icns::Key::Rgba32]).expect("Failed");
+
+    let buf = icon.write_to_vec().expect("Failed");
+    let family = ::icns::IconFamily::read(buf.as_slice()).expect("Invalid ICNS");
+    let ostypes: Vec<String> = family.elements.iter().map(|e| e.ostype.to_string()).collect();
+
+    // Each legacy key produces an RGB element and its mask
+    assert_eq!(ostypes, vec!["TOC ", "is32", "s8mk", "il32", "l8mk"]);
+
+    // The alpha channel is preserved through the mask
+    let image = family.get_icon_with_type(::icns::IconType::RGB24_32x32).expect("Failed");
+    let image = image.convert_to(::icns::PixelFormat::RGBA);
+    assert_eq!(&image.data()[..4], &[255, 0, 0, 255]);
+    assert_eq!(image.data()[31 * 4 + 3], 0);
+}
+
+#[test]
+fn test_deterministic_write() {
+    let img = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, y| {
+        image::Rgba([(x * 4) as u8, (y * 4) as u8, 0, 255])
+    })));
+
+    let mut a = Ico::new();
+    let mut b = Ico::new();
+    a.add_entries(resample::nearest, &img, vec![ico::Key(48), ico::Key(0), ico::Key(16), ico::Key(32)])
+        .expect("Failed");
+    b.add_entries(resample::nearest, &img, vec![ico::Key(0), ico::Key(32), ico::Key(16), ico::Key(48)])
+        .expect("Failed");
+
+    assert_eq!(a.write_to_vec().expect("Failed"), b.write_to_vec().expect("Failed"));
+
+    // Insertion order is preserved by `keys`
+    assert_eq!(a.keys().next(), Some(ico::Key(48)));
+
+    let icon_dir = ::ico::IconDir::read(io::Cursor::new(a.write_to_vec().expect("Failed"))).expect("Failed");
+    let sizes: Vec<u32> = icon_dir.entries().iter().map(|entry| entry.width()).collect();
+    assert_eq!(sizes, vec![16, 32, 48, 256]);
+
+    let mut a = Icns::new();
+    let mut b = Icns::new();
+    a.add_entries(
+        resample::nearest,
+        &img,
+        vec![icns::Key::Rgba64, icns::Key::Rgba16At2x, icns::Key::Rgba32, icns::Key::Rgba16],
+    )
+    .expect("Failed");
+    b.add_entries(
+        resample::nearest,
+        &img,
+        vec![icns::Key::Rgba16, icns::Key::Rgba32, icns::Key::Rgba16At2x, icns::Key::Rgba64],
+    )
+    .expect("Failed");
+
+    let buf = a.write_to_vec().expect("Failed");
+    assert_eq!(buf, b.write_to_vec().expect("Failed"));
+
+    let family = ::icns::IconFamily::read(buf.as_slice()).expect("Invalid ICNS");
+    let ostypes: Vec<String> = family.elements.iter().map(|e| e.ostype.to_string()).collect();
+    assert_eq!(ostypes, vec!["TOC ", "is32", "s8mk", "il32", "l8mk", "ic11", "icp6"]);
+}
+
+#[test]
+fn test_validate() {
+    use crate::Warning;
+
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+
+    let mut icon = Icns::new();
+    icon.add_entry(resample::nearest, &img, icns::Key::Rgba64).expect("Failed");
+
+    let missing: Vec<Warning<icns::Key>> = vec![
+        icns::Key::Rgba16,
+        icns::Key::Rgba16At2x,
+        icns::Key::Rgba32,
+        icns::Key::Rgba32At2x,
+        icns::Key::Rgba128,
+        icns::Key::Rgba128At2x,
+        icns::Key::Rgba256,
+        icns::Key::Rgba256At2x,
+        icns::Key::Rgba512,
+        icns::Key::Rgba1024,
+    ]
+    .into_iter()
+    .map(Warning::MissingRecommendedSize)
+    .collect();
+
+    assert_eq!(icon.validate(), missing);
+
+    // Entries of the same physical size with identical contents
+    icon.add_entries(resample::nearest, &img, vec![icns::Key::Rgba32, icns::Key::Rgba16At2x])
+        .expect("Failed");
+    assert!(icon.validate().contains(&Warning::DuplicateContent(icns::Key::Rgba32, icns::Key::Rgba16At2x)));
+
+    // Validation is advisory only
+    assert!(icon.write_to_vec().is_ok());
+
+    // Entries downscaled from a detailed source are distinct
+    let detailed = image::RgbaImage::from_fn(64, 64, |x, y| image::Rgba([(x * 4) as u8, (y * 4) as u8, 0, 255]));
+    let detailed = Image::from(DynamicImage::ImageRgba8(detailed));
+
+    let mut icon = Ico::new();
+    icon.add_entries(resample::nearest, &detailed, vec![ico::Key(16), ico::Key(64)]).expect("Failed");
+
+    assert_eq!(
+        icon.validate(),
+        vec![
+            Warning::MissingRecommendedSize(ico::Key(32)),
+            Warning::MissingRecommendedSize(ico::Key(48)),
+            Warning::MissingRecommendedSize(ico::Key(0)),
+        ]
+    );
+    assert_eq!(
+        icon.validate()[2].to_string(),
+        "the icon is missing a recommended entry of size 256"
+    );
+}
+
+#[test]
+fn test_icns_unsupported_size() {
+    let mut icon = Icns::new();
+    let data = vec![0; 16 * 16 * 4];
+    let image = ::icns::Image::from_data(::icns::PixelFormat::RGBA, 16, 16, data).expect("Failed");
+
+    // A 16x16 image does not fit a 32x32 entry
+    match icon.add_image(&image, icns::Key::Rgba32) {
+        Err(crate::IconError::UnsupportedSize(key)) => assert_eq!(key, icns::Key::Rgba32),
+        _ => panic!("Expected `IconError::UnsupportedSize`"),
+    }
+
+    assert!(!icon.contains(icns::Key::Rgba32));
+    assert!(icon.add_image(&image, icns::Key::Rgba16).is_ok());
+    assert!(icon.contains(icns::Key::Rgba16));
+}
+
+#[test]
+fn test_svg_fit_to() {
+    // The aspect ratio of the document differs from the one of its view box
+    let data = br#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50"
+        viewBox="0 0 50 100" preserveAspectRatio="none">
+        <rect width="50" height="100" fill="red"/>
+    </svg>"#;
+    let img = Image::from_bytes(data).expect("Invalid SVG");
+
+    for &size in &[16, 256] {
+        let icon = img.rasterize(resample::linear, size).expect("Failed").to_rgba();
+        assert_eq!(icon.dimensions(), (size, size));
+
+        // The document is rendered at size x size/2 and padded vertically
+        assert_eq!(icon.get_pixel(0, 0)[3], 0);
+        assert_eq!(icon.get_pixel(0, size / 2)[3], 255);
+        assert_eq!(icon.get_pixel(size - 1, size / 2)[3], 255);
+    }
+}
+
+#[test]
+fn test_gaussian() {
+    let mut source = image::RgbaImage::new(64, 64);
+    for (x, y, pixel) in source.enumerate_pixels_mut() {
+        // A fine checkerboard, prone to moiré
+        let c = if (x + y) % 2 == 0 { 255 } else { 0 };
+        *pixel = image::Rgba([c, c, c, 255]);
+    }
+
+    let source = DynamicImage::ImageRgba8(source);
+
+    // No blur is applied when the image is not downscaled
+    let same = resample::gaussian(2.0)(&source, 64).expect("Failed");
+    let linear = resample::linear(&source, 64).expect("Failed");
+    assert_eq!(same.to_rgba().into_raw(), linear.to_rgba().into_raw());
+
+    let small = resample::gaussian(0.5)(&source, 16).expect("Failed");
+    assert_eq!(small.dimensions(), (16, 16));
+
+    let mut icon = Ico::new();
+    let img = Image::from(source);
+    icon.add_entry(resample::gaussian(0.5), &img, ico::Key(16)).expect("Failed");
+    assert!(icon.contains(ico::Key(16)));
+}
+
+#[test]
+fn test_encode() {
+    let img = DynamicImage::new_rgba8(16, 8);
+
+    let png = encode::to_format(&img, encode::ImageOutputFormat::PNG).expect("Failed");
+    assert_eq!(image::guess_format(&png).expect("Unknown format"), image::ImageFormat::PNG);
+
+    let bmp = encode::bmp(&img).expect("Failed");
+    assert_eq!(image::guess_format(&bmp).expect("Unknown format"), image::ImageFormat::BMP);
+    assert_eq!(image::load_from_memory(&bmp).expect("Invalid BMP").dimensions(), (16, 8));
+
+    let jpeg = encode::jpeg(&img, 90).expect("Failed");
+    assert_eq!(image::guess_format(&jpeg).expect("Unknown format"), image::ImageFormat::JPEG);
+    assert_eq!(image::load_from_memory(&jpeg).expect("Invalid JPEG").dimensions(), (16, 8));
+
+    let unsupported = encode::ImageOutputFormat::Unsupported(String::from("tga"));
+    let err = encode::to_format(&img, unsupported).expect_err("Should fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "avif")]
+#[test]
+fn test_avif_sequence() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut buf = Vec::new();
+
+    let mut icon = png_sequence::AvifSequence::new();
+    let entries = vec![
+        png_sequence::Key::from(32, "32/icon.avif").expect("Invalid key"),
+        png_sequence::Key::from(64, "64/icon.avif").expect("Invalid key"),
+    ];
+
+    icon.quality(60);
+    icon.add_entries(resample::linear, &img, entries).expect("Failed");
+    icon.write(&mut buf).expect("Failed");
+
+    let mut archive = tar::Archive::new(buf.as_slice());
+    let mut paths = Vec::new();
+
+    for entry in archive.entries().expect("Invalid tar") {
+        let mut entry = entry.expect("Invalid entry");
+        let mut data = Vec::new();
+        io::Read::read_to_end(&mut entry, &mut data).expect("Failed");
+
+        // ISO-BMFF `ftyp` box with the `avif` brand
+        assert_eq!(&data[4..12], b"ftypavif");
+        paths.push(entry.path().expect("Invalid path").into_owned());
+    }
+
+    assert_eq!(paths, vec![Path::new("32/icon.avif"), Path::new("64/icon.avif")]);
+    assert!(encode::avif(&DynamicImage::new_rgba8(8, 8), 0).is_err());
+}
+
+#[cfg(feature = "webp")]
+#[test]
+fn test_webp_sequence() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut buf = Vec::new();
+
+    let mut icon = png_sequence::WebpSequence::new();
+    let entries = vec![
+        png_sequence::Key::from(32, "32/icon.webp").expect("Invalid key"),
+        png_sequence::Key::from(64, "64/icon.webp").expect("Invalid key"),
+    ];
+
+    icon.quality(60);
+    icon.add_entries(resample::linear, &img, entries).expect("Failed");
+    icon.write(&mut buf).expect("Failed");
+
+    let mut archive = tar::Archive::new(buf.as_slice());
+    let mut paths = Vec::new();
+
+    for entry in archive.entries().expect("Invalid tar") {
+        let mut entry = entry.expect("Invalid entry");
+        let mut data = Vec::new();
+        io::Read::read_to_end(&mut entry, &mut data).expect("Failed");
+
+        // RIFF container with the `WEBP` form type
+        assert_eq!(&data[..4], b"RIFF");
+        assert_eq!(&data[8..12], b"WEBP");
+        paths.push(entry.path().expect("Invalid path").into_owned());
+    }
+
+    assert_eq!(paths, vec![Path::new("32/icon.webp"), Path::new("64/icon.webp")]);
+    assert!(encode::webp(&DynamicImage::new_rgba8(8, 8), 101).is_err());
+}
+
+#[test]
+fn test_html_links() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut icon = Favicon::new();
+
+    let entries = vec![favicon::Key(180), favicon::Key(16), favicon::Key(32)];
+    icon.add_entries(resample::linear, &img, entries).expect("Failed");
+
+    assert_eq!(
+        icon.html_links(),
+        "<link rel=\"icon\" type=\"image/png\" sizes=\"16x16\" href=\"icons/favicon-0.png\">\n\
+         <link rel=\"icon\" type=\"image/png\" sizes=\"32x32\" href=\"icons/favicon-1.png\">\n\
+         <link rel=\"icon\" type=\"image/png\" sizes=\"180x180\" href=\"icons/favicon-2.png\">\n\
+         <link rel=\"apple-touch-icon\" sizes=\"180x180\" href=\"icons/favicon-2.png\">\n"
+    );
+}
+
+/// Returns the data of the `pHYs` chunks of a _PNG_ buffer.
+fn png_phys_chunks(buf: &[u8]) -> Vec<&[u8]> {
+    let mut chunks = Vec::new();
+    let mut offset = 8;
+
+    while offset + 12 <= buf.len() {
+        let len = u32::from_be_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]]) as usize;
+
+        if &buf[offset + 4..offset + 8] == b"pHYs" {
+            chunks.push(&buf[offset + 8..offset + 8 + len]);
+        }
+        offset += 12 + len;
+    }
+
+    chunks
+}
+
+#[test]
+fn test_favicon_density() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut icon = Favicon::new();
+
+    icon.add_entries(resample::linear, &img, vec![favicon::Key(32), favicon::Key(64)])
+        .expect("Failed");
+    icon.set_density(favicon::Key(64), 3).expect("Failed");
+    icon.set_density(favicon::Key(64), 2).expect("Failed");
+
+    match icon.set_density(favicon::Key(48), 2) {
+        Err(err) => assert_eq!(err.kind(), io::ErrorKind::NotFound),
+        Ok(_) => panic!("Expected an error for a missing entry"),
+    }
+
+    match icon.set_density(favicon::Key(64), 0) {
+        Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput),
+        Ok(_) => panic!("Expected an error for a null density"),
+    }
+
+    let mut buf = Vec::new();
+    icon.write(&mut buf).expect("Failed");
+
+    let mut archive = tar::Archive::new(buf.as_slice());
+    let mut pngs = Vec::new();
+
+    for entry in archive.entries().expect("Invalid tar") {
+        let mut entry = entry.expect("Invalid entry");
+        let path = entry.path().expect("Invalid path").into_owned();
+
+        if path.extension() == Some(std::ffi::OsStr::new("png")) {
+            let mut data = Vec::new();
+            io::Read::read_to_end(&mut entry, &mut data).expect("Failed");
+            pngs.push((path, data));
+        }
+    }
+
+    pngs.sort();
+    assert_eq!(pngs.len(), 2);
+
+    // 144 DPI, in pixels per meter
+    let mut phys = Vec::new();
+    phys.extend_from_slice(&5669u32.to_be_bytes());
+    phys.extend_from_slice(&5669u32.to_be_bytes());
+    phys.push(1);
+
+    assert!(png_phys_chunks(&pngs[0].1).is_empty());
+    assert_eq!(png_phys_chunks(&pngs[1].1), vec![phys.as_slice()]);
+
+    let image = image::load_from_memory(&pngs[1].1).expect("Invalid PNG");
+    assert_eq!(image.dimensions(), (64, 64));
+
+    let buf = encode::png_with_density(&DynamicImage::new_rgba8(8, 8), 1).expect("Failed");
+    assert_eq!(&png_phys_chunks(&buf)[0][..4], &2835u32.to_be_bytes());
+}
+
+#[test]
+fn test_supported_sizes() {
+    use std::convert::TryFrom;
+
+    assert_eq!(icns::Key::supported_sizes(), &[16, 32, 64, 128, 256, 512, 1024]);
+    for &size in icns::Key::supported_sizes() {
+        assert!(icns::Key::try_from(size).is_ok());
+    }
+
+    assert_eq!(icns::Key::nearest_supported(0), icns::Key::Rgba16);
+    assert_eq!(icns::Key::nearest_supported(100), icns::Key::Rgba128);
+    assert_eq!(icns::Key::nearest_supported(48), icns::Key::Rgba64);
+    assert_eq!(icns::Key::nearest_supported(4096), icns::Key::Rgba1024);
+
+    assert_eq!(ico::Key::supported_sizes().len(), 256);
+    for (&size, expected) in ico::Key::supported_sizes().iter().zip(1..) {
+        assert_eq!(size, expected);
+        assert!(ico::Key::try_from(size).is_ok());
+    }
+
+    assert_eq!(ico::Key::nearest_supported(0), ico::Key(1));
+    assert_eq!(ico::Key::nearest_supported(48), ico::Key(48));
+    assert_eq!(ico::Key::nearest_supported(512), ico::Key(0));
+}
+
+#[cfg(feature = "std-fs")]
+#[test]
+fn test_favicon_ico() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut icon = Favicon::new();
+
+    let entries = vec![favicon::Key(16), favicon::Key(32), favicon::Key(180)];
+    icon.add_entries(resample::linear, &img, entries).expect("Failed");
+
+    let dir = std::env::temp_dir().join(format!("iconwriter_test_favicon_ico_{}", std::process::id()));
+    icon.save(&dir).expect("Failed");
+
+    let file = File::open(dir.join("favicon.ico")).expect("Missing favicon.ico");
+    let ico = Ico::from_reader(file).expect("Invalid ICO");
+    let keys: Vec<ico::Key> = ico.keys().collect();
+    assert_eq!(keys, vec![ico::Key(16), ico::Key(32)]);
+
+    std::fs::remove_dir_all(&dir).expect("Failed");
+
+    // No sizes are available, so no favicon.ico is written
+    icon.ico_sizes(&[48, 64]);
+    icon.save(&dir).expect("Failed");
+    assert!(!dir.join("favicon.ico").exists());
+
+    std::fs::remove_dir_all(&dir).expect("Failed");
+}
+
+#[cfg(feature = "std-fs")]
+#[test]
+fn test_favicon_save_many() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut icon = Favicon::new();
+
+    let sizes = [16, 24, 32, 48, 57, 64, 72, 96, 128, 144, 180, 192];
+    icon.add_entries(resample::nearest, &img, sizes.iter().map(|&s| favicon::Key(s)))
+        .expect("Failed");
+
+    let dir = std::env::temp_dir().join(format!("iconwriter_test_favicon_save_many_{}", std::process::id()));
+    icon.save(&dir).expect("Failed");
+
+    for (i, &size) in sizes.iter().enumerate() {
+        let path = dir.join(format!("icons/favicon-{}.png", i));
+        let png = image::open(&path).expect("Missing entry");
+        assert_eq!(png.width(), u32::from(size));
+    }
+
+    assert!(dir.join("favicon.ico").exists());
+    assert!(dir.join("helper.html").exists());
+
+    std::fs::remove_dir_all(&dir).expect("Failed");
+}
+
+#[cfg(feature = "std-fs")]
+#[test]
+fn test_svg_passthrough() {
+    let svg = b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"32\" height=\"32\">\
+        <style>@media (prefers-color-scheme: dark) { rect { fill: white; } }</style>\
+        <rect width=\"32\" height=\"32\" fill=\"red\"/></svg>";
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let png = encode::png(&img.rasterize(resample::linear, 64).expect("Failed")).expect("Failed");
+
+    let mut icon = Favicon::new();
+    icon.add_entry(resample::linear, &img, favicon::Key(32)).expect("Failed");
+
+    match icon.add_svg_passthrough(&png) {
+        Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+        Ok(()) => panic!("Expected an error"),
+    }
+
+    icon.add_svg_passthrough(svg).expect("Failed");
+    assert!(icon
+        .html_links()
+        .contains("<link rel=\"icon\" type=\"image/svg+xml\" href=\"favicon.svg\">"));
+
+    let dir = std::env::temp_dir().join(format!("iconwriter_test_svg_passthrough_{}", std::process::id()));
+    icon.save(&dir).expect("Failed");
+
+    let data = std::fs::read(dir.join("favicon.svg")).expect("Missing favicon.svg");
+    assert_eq!(&data[..], &svg[..]);
+
+    let helper = std::fs::read_to_string(dir.join("helper.html")).expect("Missing helper.html");
+    assert!(helper.contains("type=\"image/svg+xml\" href=\"favicon.svg\""));
+
+    std::fs::remove_dir_all(&dir).expect("Failed");
+}
+
+#[test]
+fn test_manifest() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut icon = Favicon::new();
+
+    icon.add_entries(resample::linear, &img, vec![favicon::Key(16), favicon::Key(32)])
+        .expect("Failed");
+    icon.manifest_config(favicon::ManifestConfig {
+        name: Some(String::from("The \"App\"")),
+        theme_color: Some(String::from("#ffffff")),
+    });
+
+    let mut buf = Vec::new();
+    icon.write_manifest(&mut buf).expect("Failed");
+
+    let manifest: serde_json::Value = serde_json::from_slice(&buf).expect("Invalid JSON");
+    let icons = manifest["icons"].as_array().expect("Missing icons");
+
+    assert_eq!(icons.len(), 2);
+    assert_eq!(icons[1]["src"], "icons/favicon-1.png");
+    assert_eq!(icons[1]["sizes"], "32x32");
+    assert_eq!(manifest["name"], "The \"App\"");
+    assert_eq!(manifest["theme_color"], "#ffffff");
+}
+
+#[test]
+fn test_get_entry() {
+    let mut source = image::RgbaImage::new(32, 32);
+
+    for (x, y, pixel) in source.enumerate_pixels_mut() {
+        *pixel = image::Rgba([(x * 8) as u8, (y * 8) as u8, 0, 255]);
+    }
+
+    let img = Image::from(DynamicImage::ImageRgba8(source.clone()));
+
+    let mut ico = Ico::new();
+    ico.add_entry(resample::nearest, &img, ico::Key(32)).expect("Failed");
+
+    let entry = ico.get_entry(ico::Key(32)).expect("Missing entry");
+    assert_eq!(entry.to_rgba().into_raw(), source.as_ref());
+    assert!(ico.get_entry(ico::Key(16)).is_none());
+
+    let mut icns = Icns::new();
+    icns.add_entry(resample::nearest, &img, icns::Key::Rgba16At2x).expect("Failed");
+
+    let entry = icns.get_entry(icns::Key::Rgba16At2x).expect("Missing entry");
+    assert_eq!(entry.to_rgba().into_raw(), source.as_ref());
+    assert!(icns.get_entry(icns::Key::Rgba32).is_none());
+
+    let mut fav = Favicon::new();
+    fav.add_entry(resample::nearest, &img, favicon::Key(32)).expect("Failed");
+
+    let entry = fav.get_entry(favicon::Key(32)).expect("Missing entry");
+    assert_eq!(entry.to_rgba().into_raw(), source.as_ref());
+    assert!(fav.get_entry(favicon::Key(16)).is_none());
+}
+
+#[test]
+fn test_lanczos3_srgb() {
+    // A white glyph made of thin vertical strokes on a transparent background
+    let mut glyph = image::RgbaImage::new(64, 64);
+
+    for (x, _, pixel) in glyph.enumerate_pixels_mut() {
+        if x % 4 == 0 {
+            *pixel = image::Rgba([255, 255, 255, 255]);
+        }
+    }
+
+    let glyph = DynamicImage::ImageRgba8(glyph);
+
+    let brightness = |icon: DynamicImage| {
+        let icon = icon.to_rgba();
+        let visible: Vec<_> = icon.pixels().filter(|p| p[3] > 0).collect();
+
+        visible.iter().map(|p| p[0] as f32).sum::<f32>() / visible.len() as f32
+    };
+
+    let plain = brightness(resample::lanczos3(&glyph, 16).expect("Failed"));
+    let gamma = brightness(resample::lanczos3_srgb(&glyph, 16).expect("Failed"));
+
+    // Transparent pixels should not darken the edges of the glyph
+    assert!(gamma > plain, "{} <= {}", gamma, plain);
+    assert!(gamma > 250.0);
+
+    let icon = resample::lanczos3_srgb(&glyph, 16).expect("Failed");
+    assert_eq!(icon.dimensions(), (16, 16));
+}
+
+#[test]
+fn test_premultiplied() {
+    let mut source = image::RgbaImage::new(2, 2);
+
+    for y in 0..2 {
+        source.put_pixel(0, y, image::Rgba([255, 0, 0, 0]));
+        source.put_pixel(1, y, image::Rgba([0, 0, 255, 255]));
+    }
+
+    let source = DynamicImage::ImageRgba8(source);
+
+    let plain = resample::linear(&source, 1).expect("Failed").to_rgba();
+    let fringe = plain.get_pixel(0, 0);
+    assert!(fringe[0] > 0);
+
+    let mut filter = resample::premultiplied(resample::linear);
+    let premultiplied = filter(&source, 1).expect("Failed").to_rgba();
+    let fringe = premultiplied.get_pixel(0, 0);
+
+    // The transparent red pixel should not pull red into the fringe
+    assert_eq!(fringe[0], 0);
+    assert_eq!(fringe[2], 255);
+    assert!(fringe[3] > 0 && fringe[3] < 255);
+}
+
+#[test]
+fn test_standard_sets() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+
+    let ico = Ico::standard_windows(resample::nearest, &img).expect("Failed");
+    assert_eq!(ico.len(), 7);
+    assert!(ico.contains(ico::Key(24)));
+    assert!(ico.contains(ico::Key(0)));
+
+    let icns = Icns::standard_macos(resample::nearest, &img).expect("Failed");
+    assert_eq!(icns.len(), 10);
+    assert!(icns.contains(icns::Key::Rgba1024));
+
+    // Errors should be surfaced rather than panicking
+    let filter = |_: &DynamicImage, _: u32| Ok(DynamicImage::new_rgba8(1, 1));
+    assert!(Ico::standard_windows(filter, &img).is_err());
+}
+
+#[test]
+fn test_keys() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+
+    let mut ico = Ico::new();
+    ico.add_entries(resample::nearest, &img, vec![ico::Key(16), ico::Key(0), ico::Key(32)])
+        .expect("Failed");
+
+    let keys: Vec<ico::Key> = ico.keys().collect();
+    assert_eq!(keys, vec![ico::Key(16), ico::Key(0), ico::Key(32)]);
+
+    let mut icns = Icns::new();
+    icns.add_entries(resample::nearest, &img, vec![icns::Key::Rgba32, icns::Key::Rgba16At2x])
+        .expect("Failed");
+
+    let keys: Vec<icns::Key> = (&icns).into_iter().collect();
+    assert_eq!(keys, vec![icns::Key::Rgba32, icns::Key::Rgba16At2x]);
+
+    let mut fav = Favicon::new();
+    fav.add_entries(resample::nearest, &img, vec![favicon::Key(32), favicon::Key(16)])
+        .expect("Failed");
+
+    let mut sizes = Vec::new();
+
+    for key in &fav {
+        sizes.push(key.0);
+    }
+
+    assert_eq!(sizes, vec![16, 32]);
+}
+
+#[test]
+fn test_bmp_entries() {
+    // Images with semi-transparent pixels would be encoded as PNG by default
+    let source = image::RgbaImage::from_pixel(64, 64, image::Rgba([255, 0, 0, 128]));
+    let img = Image::from(DynamicImage::ImageRgba8(source));
+    let mut buf = Vec::new();
+
+    let mut icon = Ico::with_encoding(ico::IcoEncoding::Auto);
+    icon.add_entries(resample::nearest, &img, vec![ico::Key(32), ico::Key(0)])
+        .expect("Failed");
+    icon.write(&mut buf).expect("Failed");
+
+    let icon_dir = ::ico::IconDir::read(io::Cursor::new(buf)).expect("Failed");
+    let entries = icon_dir.entries();
+
+    assert_eq!(entries[0].width(), 32);
+    assert!(!entries[0].data().starts_with(b"\x89PNG"));
+
+    assert_eq!(entries[1].width(), 256);
+    assert!(entries[1].data().starts_with(b"\x89PNG"));
+
+    let entry = entries[0].decode().expect("Failed");
+    assert_eq!(entry.rgba_data()[..4], [255, 0, 0, 128]);
+}
+
+#[test]
+fn test_mask_threshold() {
+    // A soft horizontal alpha gradient, from 0 to 255
+    let mut source = image::RgbaImage::new(16, 16);
+    for (x, _, pixel) in source.enumerate_pixels_mut() {
+        *pixel = image::Rgba([255, 0, 0, x as u8 * 17]);
+    }
+
+    let img = Image::from(DynamicImage::ImageRgba8(source));
+    let mask = |icon: &mut Ico| {
+        let mut buf = Vec::new();
+        icon.write(&mut buf).expect("Failed");
+
+        let icon_dir = ::ico::IconDir::read(io::Cursor::new(buf)).expect("Failed");
+        let data = icon_dir.entries()[0].data().to_vec();
+
+        // Each row of the mask is padded to 4 bytes
+        data[data.len() - 4 * 16..].to_vec()
+    };
+
+    let mut icon = Ico::new();
+    assert_eq!(icon.mask_threshold(), 128);
+    icon.add_entry(resample::nearest, &img, ico::Key(16)).expect("Failed");
+
+    for row in mask(&mut icon).chunks(4) {
+        assert_eq!(row, [0xff, 0x00, 0x00, 0x00]);
+    }
+
+    // The alpha channel itself is left untouched
+    let entry = icon.get_entry(ico::Key(16)).expect("Missing entry").to_rgba();
+    assert_eq!(entry.get_pixel(1, 0)[3], 17);
+
+    let mut icon = Ico::new();
+    icon.set_mask_threshold(1);
+    icon.add_entry(resample::nearest, &img, ico::Key(16)).expect("Failed");
+
+    for row in mask(&mut icon).chunks(4) {
+        assert_eq!(row, [0x80, 0x00, 0x00, 0x00]);
+    }
+}
+
+#[test]
+fn test_bit_depth() {
+    use std::collections::HashSet;
+
+    let mut source = image::RgbaImage::from_fn(32, 32, |x, y| {
+        image::Rgba([(x * 8) as u8, (y * 8) as u8, ((x + y) * 4) as u8, 255])
+    });
+    source.put_pixel(0, 0, image::Rgba([0, 0, 0, 0]));
+
+    let img = Image::from(DynamicImage::ImageRgba8(source));
+
+    for &(depth, bits, max_colors) in &[(ico::BitDepth::Eight, 8, 256), (ico::BitDepth::Four, 4, 16)] {
+        let mut icon = Ico::new();
+        icon.set_bit_depth(depth);
+        icon.add_entry(resample::nearest, &img, ico::Key(32)).expect("Failed");
+
+        let buf = icon.write_to_vec().expect("Failed");
+        let icon_dir = ::ico::IconDir::read(io::Cursor::new(buf)).expect("Failed");
+        let entry = &icon_dir.entries()[0];
+        assert_eq!(entry.bits_per_pixel(), bits);
+
+        let image = entry.decode().expect("Failed");
+        let colors: HashSet<&[u8]> = image.rgba_data().chunks(4).filter(|p| p[3] != 0).collect();
+        assert!(colors.len() <= max_colors);
+        assert!(colors.len() > max_colors / 2);
+
+        // Transparency is preserved through the mask
+        assert_eq!(image.rgba_data()[3], 0);
+    }
+
+    assert_eq!(Ico::new().bit_depth(), ico::BitDepth::ThirtyTwo);
+}
+
+#[test]
+fn test_ico_encoding() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let cases = [
+        (ico::IcoEncoding::Auto, false, true),
+        (ico::IcoEncoding::AllPng, true, true),
+        (ico::IcoEncoding::AllBmp, false, false),
+    ];
+
+    assert_eq!(Ico::new().encoding(), ico::IcoEncoding::Auto);
+
+    for &(encoding, png_32, png_256) in &cases {
+        let mut buf = Vec::new();
+        let mut icon = Ico::with_encoding(encoding);
+
+        icon.add_entries(resample::nearest, &img, vec![ico::Key(32), ico::Key(0)])
+            .expect("Failed");
+        icon.write(&mut buf).expect("Failed");
+
+        let icon_dir = ::ico::IconDir::read(io::Cursor::new(buf)).expect("Failed");
+        let entries = icon_dir.entries();
+
+        assert_eq!(entries[0].data().starts_with(b"\x89PNG"), png_32);
+        assert_eq!(entries[1].data().starts_with(b"\x89PNG"), png_256);
+    }
+}
+
+#[test]
+fn test_ani() {
+    let red = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+        32,
+        32,
+        image::Rgba([255, 0, 0, 255]),
+    )));
+    let blue = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+        32,
+        32,
+        image::Rgba([0, 0, 255, 255]),
+    )));
+
+    let mut ani = Ani::new();
+    ani.add_frame(resample::nearest, &red, ico::Key(32), (4, 8)).expect("Failed");
+    ani.add_frame(resample::nearest, &blue, ico::Key(32), (4, 8)).expect("Failed");
+    ani.set_rate(6);
+    assert_eq!(ani.len(), 2);
+
+    match ani.add_frame(resample::nearest, &red, ico::Key(16), (16, 0)) {
+        Err(crate::IconError::HotspotOutOfBounds((16, 0))) => {}
+        _ => panic!("Expected `IconError::HotspotOutOfBounds`"),
+    }
+
+    let mut data = Vec::new();
+    ani.write(&mut data).expect("Failed");
+
+    let u32_at = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+
+    assert_eq!(&data[0..4], b"RIFF");
+    assert_eq!(u32_at(4) as usize, data.len() - 8);
+    assert_eq!(&data[8..12], b"ACON");
+    assert_eq!(&data[12..16], b"anih");
+    assert_eq!(u32_at(16), 36);
+    assert_eq!(u32_at(24), 2); // nFrames
+    assert_eq!(u32_at(48), 6); // jifRate
+    assert_eq!(&data[56..60], b"LIST");
+    assert_eq!(&data[64..68], b"fram");
+
+    let mut offset = 68;
+    let mut colors = Vec::new();
+
+    while offset < data.len() {
+        assert_eq!(&data[offset..offset + 4], b"icon");
+        let len = u32_at(offset + 4) as usize;
+        let frame = &data[offset + 8..offset + 8 + len];
+
+        let cursor = ::ico::IconDir::read(io::Cursor::new(frame)).expect("Invalid cursor");
+        assert_eq!(cursor.resource_type(), ::ico::ResourceType::Cursor);
+
+        let image = cursor.entries()[0].decode().expect("Invalid frame");
+        assert_eq!(image.cursor_hotspot(), Some((4, 8)));
+        colors.push(image.rgba_data()[..4].to_vec());
+
+        offset += 8 + len + (len & 1);
+    }
+
+    assert_eq!(colors, vec![vec![255, 0, 0, 255], vec![0, 0, 255, 255]]);
+}
+
+#[test]
+fn test_debug() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+
+    let mut ico = Ico::new();
+    ico.add_entries(resample::nearest, &img, vec![ico::Key(32), ico::Key(16), ico::Key(0)])
+        .expect("Failed");
+    assert_eq!(format!("{:?}", ico), "iconwriter::Ico { entries: [32x32, 16x16, 256x256] }");
+
+    let mut icns = Icns::new();
+    icns.add_entries(resample::nearest, &img, vec![icns::Key::Rgba256, icns::Key::Rgba128At2x])
+        .expect("Failed");
+    assert_eq!(
+        format!("{:?}", icns),
+        "iconwriter::Icns { elements: [ic08 (256x256), ic13 (128x128@2x)] }"
+    );
+
+    // Large icons are truncated
+    let mut ico = Ico::new();
+    ico.add_entries(resample::nearest, &img, (1..=40).map(|size| ico::Key(size as u8)))
+        .expect("Failed");
+    assert!(format!("{:?}", ico).ends_with("32x32, ... 8 more] }"));
+}
+
+/// Builds a minimal _PE_ image with a single `.text` section and,
+/// optionally, a `.rsrc` section containing a single manifest.
+#[cfg(feature = "pe")]
+fn fixture_pe(plus: bool, manifest: Option<&[u8]>) -> Vec<u8> {
+    fn put(buf: &mut [u8], offset: usize, bytes: &[u8]) {
+        buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    let optional_header_len: u16 = if plus { 240 } else { 224 };
+    let n_sections: u16 = if manifest.is_some() { 2 } else { 1 };
+    let mut exe = vec![0u8; 0x400];
+
+    put(&mut exe, 0, b"MZ");
+    put(&mut exe, 0x3c, &0x40u32.to_le_bytes());
+    put(&mut exe, 0x40, b"PE\0\0");
+    put(&mut exe, 0x44, &(if plus { 0x8664u16 } else { 0x14cu16 }).to_le_bytes());
+    put(&mut exe, 0x46, &n_sections.to_le_bytes());
+    put(&mut exe, 0x54, &optional_header_len.to_le_bytes());
+
+    let opt = 0x58;
+    put(&mut exe, opt, &(if plus { 0x20bu16 } else { 0x10bu16 }).to_le_bytes());
+    put(&mut exe, opt + 32, &0x1000u32.to_le_bytes());
+    put(&mut exe, opt + 36, &0x200u32.to_le_bytes());
+    put(&mut exe, opt + 56, &(0x1000u32 * (1 + u32::from(n_sections))).to_le_bytes());
+    put(&mut exe, opt + 60, &0x200u32.to_le_bytes());
+    put(&mut exe, opt + if plus { 108 } else { 92 }, &16u32.to_le_bytes());
+
+    let sections = opt + usize::from(optional_header_len);
+    put(&mut exe, sections, b".text\0\0\0");
+    put(&mut exe, sections + 8, &0x200u32.to_le_bytes());
+    put(&mut exe, sections + 12, &0x1000u32.to_le_bytes());
+    put(&mut exe, sections + 16, &0x200u32.to_le_bytes());
+    put(&mut exe, sections + 20, &0x200u32.to_le_bytes());
+
+    if let Some(manifest) = manifest {
+        // Type 24 (RT_MANIFEST) -> name 1 -> language 0x409 -> data
+        let mut rsrc = vec![0u8; 0x200];
+        put(&mut rsrc, 14, &1u16.to_le_bytes());
+        put(&mut rsrc, 16, &24u32.to_le_bytes());
+        put(&mut rsrc, 20, &(0x8000_0000u32 | 24).to_le_bytes());
+        put(&mut rsrc, 24 + 14, &1u16.to_le_bytes());
+        put(&mut rsrc, 24 + 16, &1u32.to_le_bytes());
+        put(&mut rsrc, 24 + 20, &(0x8000_0000u32 | 48).to_le_bytes());
+        put(&mut rsrc, 48 + 14, &1u16.to_le_bytes());
+        put(&mut rsrc, 48 + 16, &0x409u32.to_le_bytes());
+        put(&mut rsrc, 48 + 20, &72u32.to_le_bytes());
+        put(&mut rsrc, 72, &(0x2000u32 + 88).to_le_bytes());
+        put(&mut rsrc, 76, &(manifest.len() as u32).to_le_bytes());
+        put(&mut rsrc, 88, manifest);
+
+        let directories = opt + if plus { 112 } else { 96 };
+        put(&mut exe, directories + 16, &0x2000u32.to_le_bytes());
+        put(&mut exe, directories + 20, &(88 + manifest.len() as u32).to_le_bytes());
+
+        put(&mut exe, sections + 40, b".rsrc\0\0\0");
+        put(&mut exe, sections + 48, &(88 + manifest.len() as u32).to_le_bytes());
+        put(&mut exe, sections + 52, &0x2000u32.to_le_bytes());
+        put(&mut exe, sections + 56, &0x200u32.to_le_bytes());
+        put(&mut exe, sections + 60, &0x400u32.to_le_bytes());
+        exe.extend_from_slice(&rsrc);
+    }
+
+    exe
+}
+
+#[cfg(feature = "pe")]
+#[test]
+fn test_embed() {
+    use crate::embed;
+
+    const RT_ICON: u16 = 3;
+    const RT_GROUP_ICON: u16 = 14;
+    const RT_MANIFEST: u16 = 24;
+
+    let img = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, y| {
+        image::Rgba([(x * 4) as u8, (y * 4) as u8, 0, 255])
+    })));
+
+    let mut small = Ico::new();
+    small.add_entries(resample::nearest, &img, vec![ico::Key(32), ico::Key(16)]).expect("Failed");
+
+    let mut large = Ico::new();
+    large.add_entries(resample::nearest, &img, vec![ico::Key(48), ico::Key(0), ico::Key(16)])
+        .expect("Failed");
+
+    let manifest: &[u8] = b"<assembly/>";
+
+    for &plus in &[false, true] {
+        for &has_manifest in &[false, true] {
+            let exe = fixture_pe(plus, if has_manifest { Some(manifest) } else { None });
+
+            let exe = embed::set_icon(&exe, &small).expect("Failed to embed the icon");
+            let exe = embed::set_icon(&exe, &large).expect("Failed to replace the icon");
+
+            // The headers must remain consistent
+            let opt = 0x58;
+            let checksum = u32::from_le_bytes([exe[opt + 64], exe[opt + 65], exe[opt + 66], exe[opt + 67]]);
+            assert_eq!(checksum, embed::checksum(&exe, opt + 64));
+
+            let resources = embed::read_resources(&exe).expect("Invalid resources");
+
+            // The previous group and its images are replaced
+            assert_eq!(resources.ids(RT_GROUP_ICON), vec![1]);
+            assert_eq!(resources.ids(RT_ICON).len(), 3);
+
+            let group = resources.data(RT_GROUP_ICON, 1).expect("Missing group");
+            assert_eq!(&group[..6], &[0, 0, 1, 0, 3, 0]);
+
+            let widths: Vec<u8> = group[6..].chunks(14).map(|entry| entry[0]).collect();
+            assert_eq!(widths, vec![16, 48, 0]);
+
+            for (entry, icon) in group[6..].chunks(14).zip(large.dir_entries()) {
+                let id = u16::from_le_bytes([entry[12], entry[13]]);
+                assert_eq!(resources.data(RT_ICON, id), Some(icon.data()));
+            }
+
+            if has_manifest {
+                assert_eq!(resources.data(RT_MANIFEST, 1), Some(manifest));
+            }
+        }
+    }
+
+    match embed::set_icon(b"not an executable", &small) {
+        Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+        Ok(_) => panic!("Expected an error for an invalid executable"),
+    }
+
+    match embed::set_icon(&fixture_pe(true, None), &Ico::new()) {
+        Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput),
+        Ok(_) => panic!("Expected an error for an empty icon"),
+    }
+}
+
+#[cfg(feature = "pe")]
+#[test]
+fn test_embed_overflow() {
+    use crate::embed;
+
+    fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+        buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    let img = Image::from(DynamicImage::new_rgba8(32, 32));
+    let mut icon = Ico::new();
+    icon.add_entry(resample::nearest, &img, ico::Key(32)).expect("Failed");
+
+    let (opt, rsrc) = (0x58, 0x400);
+    let sections = opt + 224;
+    let mut exes = Vec::new();
+
+    // The mapping of the resource data overflows the file offsets
+    let mut exe = fixture_pe(false, Some(b"<assembly/>"));
+    put_u32(&mut exe, opt + 96 + 16, 0x200);
+    put_u32(&mut exe, sections + 52, 0x200);
+    put_u32(&mut exe, sections + 56, 0xffff_ffff);
+    put_u32(&mut exe, rsrc + 72, 0xffff_fff0);
+    exes.push(exe);
+
+    // The new section does not fit the address space of the image
+    let mut exe = fixture_pe(false, None);
+    put_u32(&mut exe, sections + 8, 0x2000);
+    put_u32(&mut exe, sections + 12, 0xffff_f000);
+    exes.push(exe);
+
+    let mut exe = fixture_pe(false, None);
+    put_u32(&mut exe, sections + 12, 0xffff_e000);
+    exes.push(exe);
+
+    // The new images can not be numbered after an existing image 65535
+    let mut exe = fixture_pe(false, Some(b"<assembly/>"));
+    put_u32(&mut exe, rsrc + 16, 3);
+    put_u32(&mut exe, rsrc + 24 + 16, 0xffff);
+    exes.push(exe);
+
+    for exe in exes {
+        match embed::set_icon(&exe, &icon) {
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+            Ok(_) => panic!("Expected an error for an overflowing header field"),
+        }
+    }
+}
+
+#[cfg(feature = "pe")]
+#[test]
+fn test_embed_no_sections() {
+    use crate::embed;
+
+    const RT_GROUP_ICON: u16 = 14;
+
+    let img = Image::from(DynamicImage::new_rgba8(32, 32));
+    let mut icon = Ico::new();
+    icon.add_entry(resample::nearest, &img, ico::Key(32)).expect("Failed");
+
+    let sections = 0x58 + 224;
+    let mut exes = Vec::new();
+
+    // An image without any section
+    let mut exe = fixture_pe(false, None);
+    exe[0x46..0x48].copy_from_slice(&0u16.to_le_bytes());
+    exes.push(exe);
+
+    // An image whose sections have no raw data
+    let mut exe = fixture_pe(false, None);
+    exe[sections + 16..sections + 20].copy_from_slice(&0u32.to_le_bytes());
+    exes.push(exe);
+
+    for exe in exes {
+        let exe = embed::set_icon(&exe, &icon).expect("Failed to embed the icon");
+
+        let resources = embed::read_resources(&exe).expect("Invalid resources");
+        assert_eq!(resources.ids(RT_GROUP_ICON), vec![1]);
+    }
+}
+
+#[test]
+fn test_in_memory() {
+    let png = {
+        let mut buf = Vec::new();
+        let img = image::RgbaImage::from_pixel(64, 64, image::Rgba([0, 128, 255, 255]));
+
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut buf, image::ImageOutputFormat::PNG)
+            .expect("Failed to encode the source");
+        buf
+    };
+
+    let img = Image::from_bytes(&png).expect("Failed to decode the source");
+    let mut icon = Ico::new();
+
+    icon.add_entries(resample::linear, &img, vec![ico::Key(16), ico::Key(32)]).expect("Failed");
+
+    let data = icon.write_to_vec().expect("Failed");
+    let icon = Ico::from_reader(io::Cursor::new(data)).expect("Invalid ICO");
+    assert_eq!(icon.len(), 2);
+}
+
+#[test]
+fn test_with_viewbox() {
+    let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"64\" height=\"64\">\
+        <rect width=\"32\" height=\"64\" fill=\"#ff0000\"/>\
+        <rect x=\"32\" width=\"32\" height=\"64\" fill=\"#0000ff\"/></svg>";
+    let img = Image::from_bytes(svg.as_bytes()).expect("Invalid SVG");
+
+    let left = img.with_viewbox(0.0, 0.0, 32.0, 32.0).expect("Failed");
+    let right = img.with_viewbox(32.0, 0.0, 32.0, 32.0).expect("Failed");
+    assert_eq!(left.dimensions(), (32.0, 32.0));
+
+    let left = left.rasterize(resample::linear, 16).expect("Failed").to_rgba();
+    let right = right.rasterize(resample::linear, 16).expect("Failed").to_rgba();
+    assert_eq!(left.get_pixel(8, 8), &image::Rgba([255, 0, 0, 255]));
+    assert_eq!(right.get_pixel(8, 8), &image::Rgba([0, 0, 255, 255]));
+
+    // The original image is left untouched
+    assert_eq!(img.dimensions(), (64.0, 64.0));
+
+    for &(w, h) in &[(0.0, 32.0), (32.0, -1.0), (std::f64::NAN, 32.0)] {
+        match img.with_viewbox(0.0, 0.0, w, h) {
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput),
+            Ok(_) => panic!("Expected an error for an invalid view box"),
+        }
+    }
+
+    let raster = Image::from(DynamicImage::new_rgba8(8, 8));
+    assert!(raster.with_viewbox(0.0, 0.0, 4.0, 4.0).is_err());
+}
+
+#[test]
+fn test_generated() {
+    let img = Image::generated(|size| {
+        DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(size, size, image::Rgba([0, 128, 255, 255])))
+    });
+
+    for &size in &[16, 32, 48] {
+        let icon = img.rasterize(resample::nearest, size).expect("Failed").to_rgba();
+        assert_eq!(icon.dimensions(), (size, size));
+        assert!(icon.pixels().all(|p| p == &image::Rgba([0, 128, 255, 255])));
+    }
+
+    let mut ico = Ico::new();
+    ico.add_entry(resample::linear, &img, ico::Key(32)).expect("Failed");
+
+    let bad = Image::generated(|size| DynamicImage::new_rgba8(size, size / 2));
+    match bad.rasterize(resample::linear, 32) {
+        Err(crate::ResReResampleError::MismatchedDimensions(32, (32, 16))) => (),
+        _ => panic!("Expected a dimension mismatch"),
+    }
+}
+
+#[cfg(feature = "std-fs")]
+#[test]
+fn test_bundle() {
+    let mut calls = Vec::new();
+    let mut bundle = Bundle {
+        source: Image::from(DynamicImage::new_rgba8(64, 64)),
+        sizes: vec![16, 32, 48, 300],
+        filter: |src: &DynamicImage, size: u32| {
+            calls.push(size);
+            resample::nearest(src, size)
+        },
+    };
+
+    let dir = std::env::temp_dir().join(format!("iconwriter_test_bundle_{}", std::process::id()));
+    bundle.write_all(&dir).expect("Failed");
+    drop(bundle);
+
+    // Each size is rendered once, even if shared by several formats
+    calls.sort();
+    assert_eq!(calls, vec![16, 32, 48, 300]);
+
+    let ico = Ico::from_reader(File::open(dir.join("icon.ico")).expect("Missing icon.ico")).expect("Invalid ICO");
+    assert_eq!(ico.keys().collect::<Vec<_>>(), vec![ico::Key(16), ico::Key(32), ico::Key(48)]);
+
+    let icns = ::icns::IconFamily::read(File::open(dir.join("icon.icns")).expect("Missing icon.icns")).expect("Invalid ICNS");
+    assert_eq!(icns.available_icons().len(), 2);
+
+    let pngs = std::fs::read_dir(dir.join("favicon/icons")).expect("Missing favicon directory");
+    assert_eq!(pngs.count(), 4);
+    assert!(dir.join("favicon/favicon.ico").is_file());
+
+    std::fs::remove_dir_all(&dir).expect("Failed");
+}
+
+#[test]
+fn test_nearest_integer() {
+    let colors = [
+        image::Rgba([255, 0, 0, 255]),
+        image::Rgba([0, 255, 0, 128]),
+        image::Rgba([0, 0, 255, 255]),
+        image::Rgba([0, 0, 0, 0]),
+    ];
+    let source = image::RgbaImage::from_fn(2, 2, |x, y| colors[(y * 2 + x) as usize]);
+    let source = DynamicImage::ImageRgba8(source);
+
+    let output = resample::nearest_integer(&source, 4).expect("Failed").to_rgba();
+    assert_eq!(output.dimensions(), (4, 4));
+
+    for (x, y, px) in output.enumerate_pixels() {
+        assert_eq!(px, &colors[((y / 2) * 2 + x / 2) as usize]);
+    }
+
+    // Non-integer factors fall back to `nearest`
+    let output = resample::nearest_integer(&source, 5).expect("Failed");
+    assert_eq!(output.dimensions(), (5, 5));
+    assert!(resample::by_name("nearest_integer").is_some());
+}
+
+#[test]
+fn test_add_raw_entry() {
+    let red = image::RgbaImage::from_pixel(32, 32, image::Rgba([255, 0, 0, 255]));
+    let mut icon = Ico::new();
+
+    icon.add_raw_entry(DynamicImage::ImageRgba8(red), ico::Key(32)).expect("Failed");
+    assert_eq!(icon.keys().collect::<Vec<_>>(), vec![ico::Key(32)]);
+
+    let mut buf = Vec::new();
+    icon.write(&mut buf).expect("Failed");
+    let dir = ::ico::IconDir::read(io::Cursor::new(buf)).expect("Invalid ICO");
+    let entry = dir.entries()[0].decode().expect("Failed");
+    assert_eq!(&entry.rgba_data()[..4], &[255, 0, 0, 255]);
+
+    match icon.add_raw_entry(DynamicImage::new_rgba8(32, 16), ico::Key(16)) {
+        Err(crate::IconError::Resample(crate::ResReResampleError::MismatchedDimensions(16, (32, 16)))) => (),
+        _ => panic!("Expected a dimension mismatch"),
+    }
+    assert_eq!(icon.len(), 1);
+}
+
+#[test]
+fn test_add_entries_with_progress() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut icon = Ico::new();
+    let mut calls = Vec::new();
+
+    let keys = vec![ico::Key(16), ico::Key(32), ico::Key(48)];
+    icon.add_entries_with_progress(resample::nearest, &img, keys, |key, i, n| calls.push((key, i, n)))
+        .expect("Failed");
+
+    assert_eq!(calls, vec![(ico::Key(16), 0, 3), (ico::Key(32), 1, 3), (ico::Key(48), 2, 3)]);
+
+    // No progress is reported for the entry that failed
+    calls.clear();
+    let keys = vec![ico::Key(64), ico::Key(16), ico::Key(128)];
+    assert!(icon.add_entries_with_progress(resample::nearest, &img, keys, |key, i, n| calls.push((key, i, n))).is_err());
+    assert_eq!(calls, vec![(ico::Key(64), 0, 3)]);
+}
+
+#[test]
+fn test_preset() {
+    let source = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(64, 32, image::Rgba([0, 0, 255, 255])));
+
+    for &quality in &[resample::Quality::Fast, resample::Quality::Balanced, resample::Quality::Best] {
+        let output = resample::preset(quality)(&source, 16).expect("Failed");
+        assert_eq!(output.dimensions(), (16, 16));
+        let output = output.to_rgba();
+
+        // The source is letterboxed, with some ringing tolerated for `Best`
+        assert_eq!(output.get_pixel(8, 0)[3], 0);
+        assert!(output.get_pixel(8, 8)[2] > 240);
+    }
+}
+
+#[test]
+fn test_icns_toc() {
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let mut icon = Icns::new();
+    icon.add_entries(resample::nearest, &img, vec![icns::Key::Rgba16, icns::Key::Rgba256]).expect("Failed");
+
+    let buf = icon.write_to_vec().expect("Failed");
+    assert_eq!(buf.len(), icon.encoded_len());
+    assert_eq!(&buf[..4], b"icns");
+    assert_eq!(u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize, buf.len());
+
+    // The TOC lists every following element along with its length
+    let family = ::icns::IconFamily::read(buf.as_slice()).expect("Invalid ICNS");
+    let (toc, elements) = family.elements.split_first().expect("Empty ICNS");
+    assert_eq!(toc.ostype.to_string(), "TOC ");
+    assert_eq!(toc.data.len(), 8 * elements.len());
+
+    for (entry, element) in toc.data.chunks(8).zip(elements) {
+        assert_eq!(&entry[..4], &element.ostype.0);
+        assert_eq!(u32::from_be_bytes([entry[4], entry[5], entry[6], entry[7]]), element.total_length());
+    }
+
+    // The entries can still be decoded
+    assert!(family.get_icon_with_type(::icns::IconType::RGBA32_256x256).is_ok());
+}
+
+#[test]
+fn test_retina_sizes() {
+    assert_eq!(crate::retina_sizes(&[16, 32, 128, 256, 512]), vec![16, 32, 64, 128, 256, 512, 1024]);
+    assert_eq!(crate::retina_sizes(&[48, 16]), vec![16, 32, 48, 96]);
+
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+
+    let mut icon = Icns::new();
+    icon.add_retina_set(resample::nearest, &img, &[16, 32, 512, 16]).expect("Failed");
+    assert_eq!(icon.len(), 6);
+    assert!(icon.contains(icns::Key::Rgba16At2x));
+    assert!(icon.contains(icns::Key::Rgba1024));
+
+    // There are no @2x ICNS entries for 64x64 and 1024x1024 points
+    for &size in &[64, 1024, 48] {
+        match Icns::new().add_retina_set(resample::nearest, &img, &[16, size]) {
+            Err(crate::IconError::UnsupportedRetinaSize(s)) if s == size => (),
+            _ => panic!("Expected `IconError::UnsupportedRetinaSize`"),
+        }
+    }
+
+    let mut fav = Favicon::new();
+    fav.add_retina_set(resample::nearest, &img, &[16, 32]).expect("Failed");
+    assert_eq!(fav.keys().collect::<Vec<_>>(), vec![favicon::Key(16), favicon::Key(32), favicon::Key(64)]);
+
+    match Favicon::new().add_retina_set(resample::nearest, &img, &[16, 40000]) {
+        Err(crate::IconError::UnsupportedRetinaSize(40000)) => (),
+        _ => panic!("Expected `IconError::UnsupportedRetinaSize`"),
+    }
+}
+
+#[test]
+fn test_ico_strict() {
+    let img = Image::from(DynamicImage::new_rgba8(16, 16));
+    let mut icon = Ico::new();
+    assert!(!icon.strict());
+
+    // Sources are upscaled by default
+    icon.add_entry(resample::nearest, &img, ico::Key(32)).expect("Failed");
+
+    icon.set_strict(true);
+    icon.add_entry(resample::nearest, &img, ico::Key(16)).expect("Failed");
+
+    let err = icon.add_entry(resample::nearest, &img, ico::Key(64)).expect_err("Expected an error");
+    assert_eq!(err.to_string(), "the 16x16 source image is smaller than the requested size of 64");
+
+    match err {
+        crate::IconError::SourceTooSmall { source: (16, 16), requested: 64 } => (),
+        _ => panic!("Expected `IconError::SourceTooSmall`"),
+    }
+    assert!(!icon.contains(ico::Key(64)));
+
+    // The reason is kept when converting to an I/O error
+    let err: io::Error = icon.add_entry(resample::nearest, &img, ico::Key(64)).expect_err("Expected an error").into();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.to_string(), "the 16x16 source image is smaller than the requested size of 64");
+
+    // Vector graphics are resolution-independent
+    let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"16\" height=\"16\"><rect width=\"16\" height=\"16\"/></svg>";
+    let svg = Image::from_bytes(svg.as_bytes()).expect("Invalid SVG");
+    icon.add_entry(resample::nearest, &svg, ico::Key(64)).expect("Failed");
+}
+
+#[test]
+fn test_progressive() {
+    // One-pixel lines every four pixels, which `nearest` either
+    // always hits or always misses when downscaling by 16
+    let source = image::RgbaImage::from_fn(256, 256, |x, _| {
+        if x % 4 == 1 { image::Rgba([0, 0, 0, 255]) } else { image::Rgba([255, 255, 255, 255]) }
+    });
+    let source = DynamicImage::ImageRgba8(source);
+
+    let reference = resample::area(&source, 16).expect("Failed").to_rgba();
+    let error = |output: DynamicImage| -> u32 {
+        let output = output.to_rgba();
+        assert_eq!(output.dimensions(), (16, 16));
+
+        output.pixels().zip(reference.pixels())
+            .map(|(a, b)| (i32::from(a[0]) - i32::from(b[0])).abs() as u32)
+            .sum::<u32>() / 256
+    };
+
+    let single = error(resample::nearest(&source, 16).expect("Failed"));
+    let progressive = error(resample::progressive(resample::nearest)(&source, 16).expect("Failed"));
+
+    assert!(progressive <= 2, "progressive error: {}", progressive);
+    assert!(single > 32, "single-step error: {}", single);
+
+    // The output has the exact requested dimensions, even for odd sources
+    let odd = DynamicImage::new_rgba8(1001, 333);
+    let output = resample::progressive(resample::linear)(&odd, 16).expect("Failed");
+    assert_eq!(output.dimensions(), (16, 16));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    let config: serde_json::Value = serde_json::from_str(
+        r#"{ "format": "icns", "sizes": [16, 32, 256], "manifest": { "name": "App" } }"#,
+    )
+    .expect("Invalid JSON");
+
+    let format: crate::FormatKind = serde_json::from_value(config["format"].clone()).expect("Failed");
+    let keys: Vec<icns::Key> = serde_json::from_value(config["sizes"].clone()).expect("Failed");
+    let manifest: favicon::ManifestConfig = serde_json::from_value(config["manifest"].clone()).expect("Failed");
+
+    assert_eq!(format, crate::FormatKind::Icns);
+    assert_eq!(keys, vec![icns::Key::Rgba16, icns::Key::Rgba32, icns::Key::Rgba256]);
+    assert_eq!(manifest.name.as_deref(), Some("App"));
+    assert_eq!(manifest.theme_color, None);
+
+    // Keys are serialized as their size
+    assert_eq!(serde_json::to_value(&keys).expect("Failed"), config["sizes"]);
+    assert_eq!(serde_json::to_value(format).expect("Failed"), config["format"]);
+
+    let keys: Vec<ico::Key> = serde_json::from_value(config["sizes"].clone()).expect("Failed");
+    assert_eq!(keys, vec![ico::Key(16), ico::Key(32), ico::Key(0)]);
+    assert_eq!(serde_json::to_string(&keys).expect("Failed"), "[16,32,256]");
+
+    let manifest_json = serde_json::to_value(&manifest).expect("Failed");
+    assert_eq!(serde_json::from_value::<favicon::ManifestConfig>(manifest_json).expect("Failed"), manifest);
+
+    // The fields may also be given in order, as by non-self-describing formats
+    let manifest: favicon::ManifestConfig = serde_json::from_str(r##"["App", "#ff0000"]"##).expect("Failed");
+    assert_eq!(manifest.name.as_deref(), Some("App"));
+    assert_eq!(manifest.theme_color.as_deref(), Some("#ff0000"));
+    assert!(serde_json::from_str::<favicon::ManifestConfig>(r#"["App"]"#).is_err());
+
+    // Keys at 2x density are distinguished from the standard ones
+    let all = vec![
+        icns::Key::Rgba16,
+        icns::Key::Rgba32,
+        icns::Key::Rgba64,
+        icns::Key::Rgba128,
+        icns::Key::Rgba256,
+        icns::Key::Rgba512,
+        icns::Key::Rgba1024,
+        icns::Key::Rgba16At2x,
+        icns::Key::Rgba32At2x,
+        icns::Key::Rgba128At2x,
+        icns::Key::Rgba256At2x,
+    ];
+
+    let json = serde_json::to_string(&all).expect("Failed");
+    assert_eq!(json, r#"[16,32,64,128,256,512,1024,"16@2x","32@2x","128@2x","256@2x"]"#);
+    assert_eq!(serde_json::from_str::<Vec<icns::Key>>(&json).expect("Failed"), all);
+
+    // Sizes unsupported by the format are rejected
+    assert!(serde_json::from_str::<icns::Key>("48").is_err());
+    assert!(serde_json::from_str::<icns::Key>("\"64@2x\"").is_err());
+    assert!(serde_json::from_str::<icns::Key>("-16").is_err());
+    assert!(serde_json::from_str::<ico::Key>("512").is_err());
+    assert!(serde_json::from_str::<crate::FormatKind>("\"bmp\"").is_err());
+}
+
+#[test]
+fn test_duplicate_content() {
+    // An 8x8 checkerboard, smaller than every entry
+    let small = image::RgbaImage::from_fn(8, 8, |x, y| {
+        if (x + y) % 2 == 0 { image::Rgba([255, 0, 0, 255]) } else { image::Rgba([0, 0, 255, 255]) }
+    });
+    let small = Image::from(DynamicImage::ImageRgba8(small));
+
+    let detailed = image::RgbaImage::from_fn(64, 64, |x, y| image::Rgba([(x * 4) as u8, (y * 4) as u8, 0, 255]));
+    let detailed = Image::from(DynamicImage::ImageRgba8(detailed));
+
+    let mut icon = Ico::new();
+    icon.add_entries(resample::nearest_integer, &small, vec![ico::Key(16), ico::Key(32)]).expect("Failed");
+    icon.add_entry(resample::nearest_integer, &detailed, ico::Key(48)).expect("Failed");
+
+    assert_eq!(icon.duplicate_content(), vec![(ico::Key(16), ico::Key(32))]);
+    assert!(icon.validate().contains(&crate::Warning::DuplicateContent(ico::Key(16), ico::Key(32))));
+
+    // Entries downscaled from a large enough source are distinct
+    let mut icon = Ico::new();
+    icon.add_entries(resample::linear, &detailed, vec![ico::Key(16), ico::Key(32)]).expect("Failed");
+    assert!(icon.duplicate_content().is_empty());
+
+    // Interpolated upscales are never exact
+    let mut icon = Ico::new();
+    icon.add_entries(resample::linear, &small, vec![ico::Key(16), ico::Key(32)]).expect("Failed");
+    assert!(icon.duplicate_content().is_empty());
+}
+
+#[cfg(feature = "std-fs")]
+#[test]
+fn test_ico_extract_all() {
+    let img = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(64, 64, image::Rgba([0, 255, 0, 255]))));
+    let mut icon = Ico::new();
+    icon.add_entries(resample::nearest, &img, vec![ico::Key(16), ico::Key(32), ico::Key(0)]).expect("Failed");
+
+    // Round-trip through the ICO format, so that BMP entries are decoded
+    let icon = Ico::from_reader(io::Cursor::new(icon.write_to_vec().expect("Failed"))).expect("Invalid ICO");
+
+    let dir = std::env::temp_dir().join(format!("iconwriter_test_extract_{}", std::process::id()));
+    icon.extract_all(&dir).expect("Failed");
+
+    for &size in &[16, 32, 256] {
+        let path = dir.join(format!("entry_{}x{}.png", size, size));
+        let png = image::open(&path).expect("Invalid PNG");
+
+        assert_eq!(png.dimensions(), (size, size));
+        assert_eq!(png.to_rgba().get_pixel(0, 0), &image::Rgba([0, 255, 0, 255]));
+    }
+
+    assert_eq!(std::fs::read_dir(&dir).expect("Failed").count(), 3);
+    std::fs::remove_dir_all(&dir).expect("Failed");
+}
+
+#[test]
+fn test_icns_legacy_mask_alignment() {
+    // The edge falls between two pixels of the 32x32 entry,
+    // so that the filter produces a soft edge
+    let source = image::RgbaImage::from_fn(64, 64, |x, _| image::Rgba([0, 0, 255, if x < 27 { 255 } else { 0 }]));
+    let source = DynamicImage::ImageRgba8(source);
+
+    let mut icon = Icns::new();
+    icon.add_entry(resample::lanczos3, &Image::from(source.clone()), icns::Key::Rgba32).expect("Failed");
+
+    let expected = resample::lanczos3(&source, 32).expect("Failed").to_rgba();
+    let alpha: Vec<u8> = expected.pixels().map(|p| p[3]).collect();
+    assert!(alpha.iter().any(|&a| a != 0 && a != 255));
+
+    let buf = icon.write_to_vec().expect("Failed");
+    let family = ::icns::IconFamily::read(buf.as_slice()).expect("Invalid ICNS");
+
+    let mask = family.elements.iter().find(|e| e.ostype.to_string() == "l8mk").expect("Missing mask");
+    assert_eq!(mask.data, alpha);
+
+    // The color element is aligned with the mask
+    let image = family.get_icon_with_type(::icns::IconType::RGB24_32x32).expect("Failed");
+    let image = image.convert_to(::icns::PixelFormat::RGBA);
+    assert_eq!((image.width(), image.height()), (32, 32));
+
+    for (px, expected) in image.data().chunks(4).zip(expected.pixels()) {
+        assert_eq!(px[3], expected[3]);
+        if expected[3] > 0 {
+            assert_eq!(&px[..3], &expected.0[..3]);
+        }
+    }
+}
+
+#[test]
+fn test_image_accessors() {
+    let raster = Image::from(DynamicImage::new_rgba8(8, 4));
+    assert_eq!(raster.as_raster().map(|ras| ras.dimensions()), Some((8, 4)));
+    assert!(raster.as_svg().is_none());
+
+    let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"16\" height=\"16\"/>";
+    let svg = Image::from_bytes(svg.as_bytes()).expect("Invalid SVG");
+    assert_eq!(svg.as_svg().map(|tree| tree.svg_node().size.width()), Some(16.0));
+    assert!(svg.as_raster().is_none());
+
+    let generated = Image::generated(|size| DynamicImage::new_rgba8(size, size));
+    assert!(generated.as_raster().is_none());
+    assert!(generated.as_svg().is_none());
+}
+
+#[test]
+fn test_diff() {
+    use crate::diff::{self, SizeDiff, Status};
+
+    let red = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(64, 64, image::Rgba([255, 0, 0, 255]))));
+    let mut a = Ico::new();
+    a.add_entries(resample::nearest, &red, vec![ico::Key(16), ico::Key(32), ico::Key(48)]).expect("Failed");
+
+    let same = |dimensions| SizeDiff { dimensions, status: Status::Same };
+    assert_eq!(diff::compare(&a, &a), vec![same((16, 16)), same((32, 32)), same((48, 48))]);
+
+    // A modified copy, with a single pixel changed in the 32x32 entry
+    let mut b = a.clone();
+    let mut entry = b.get_entry(ico::Key(32)).expect("Missing entry").to_rgba();
+    entry.put_pixel(0, 0, image::Rgba([0, 0, 255, 255]));
+
+    assert!(b.remove_entry(ico::Key(32)));
+    assert!(b.remove_entry(ico::Key(48)));
+    b.add_raw_entry(DynamicImage::ImageRgba8(entry), ico::Key(32)).expect("Failed");
+    b.add_entry(resample::nearest, &red, ico::Key(64)).expect("Failed");
+
+    assert_eq!(
+        diff::compare(&a, &b),
+        vec![
+            same((16, 16)),
+            SizeDiff { dimensions: (32, 32), status: Status::Changed(1) },
+            SizeDiff { dimensions: (48, 48), status: Status::OnlyInA },
+            SizeDiff { dimensions: (64, 64), status: Status::OnlyInB },
+        ]
+    );
+
+    // Icons of different formats can be compared
+    let mut icns = Icns::new();
+    icns.add_entry(resample::nearest, &red, icns::Key::Rgba16).expect("Failed");
+    assert_eq!(diff::compare(&icns, &a)[0], same((16, 16)));
+}
+
+#[test]
+fn test_rasterize_with_background() {
+    let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"32\" height=\"16\">\
+        <circle cx=\"16\" cy=\"8\" r=\"4\" fill=\"#ff0000\"/></svg>";
+    let img = Image::from_bytes(svg.as_bytes()).expect("Invalid SVG");
+    let white = crate::usvg::Color::new(255, 255, 255);
+
+    let transparent = img.rasterize(resample::linear, 32).expect("Failed").to_rgba();
+    let page = img.rasterize_with_background(resample::linear, 32, white).expect("Failed").to_rgba();
+
+    // The corners of the document differ, while the contents are unchanged
+    assert_eq!(transparent.get_pixel(0, 8), &image::Rgba([0, 0, 0, 0]));
+    assert_eq!(page.get_pixel(0, 8), &image::Rgba([255, 255, 255, 255]));
+    assert_eq!(page.get_pixel(16, 16), &image::Rgba([255, 0, 0, 255]));
+
+    // The borders fitting the document to a square are left transparent
+    assert_eq!(page.get_pixel(0, 0)[3], 0);
+
+    // Raster images are not affected
+    let raster = Image::from(DynamicImage::new_rgba8(32, 32));
+    let output = raster.rasterize_with_background(resample::nearest, 32, white).expect("Failed");
+    assert_eq!(output.to_rgba().get_pixel(0, 0)[3], 0);
+}
+
+#[test]
+fn test_key_from_str() {
+    assert_eq!("32".parse::<ico::Key>().unwrap(), ico::Key(32));
+    assert_eq!("256x256".parse::<ico::Key>().unwrap(), ico::Key(0));
+    assert_eq!("16x16".parse::<icns::Key>().unwrap(), icns::Key::Rgba16);
+    assert_eq!("512".parse::<icns::Key>().unwrap(), icns::Key::Rgba512);
+
+    for s in &["16x32", "", "x16", "sixteen", "16x", "-16"] {
+        let err = s.parse::<ico::Key>().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains(s), "{}", err);
+    }
+
+    assert!("16x32".parse::<icns::Key>().unwrap_err().to_string().contains("square"));
+    assert!("512".parse::<ico::Key>().unwrap_err().to_string().contains("does not support"));
+    assert!("48".parse::<icns::Key>().unwrap_err().to_string().contains("does not support"));
+}
+
+#[test]
+fn test_max_size() {
+    // Never rasterized, since the requested sizes are rejected beforehand
+    let src = Image::generated(|_| panic!("the entry should not be rasterized"));
+    let mut fav = Favicon::new();
+
+    match fav.add_entry(resample::nearest, &src, favicon::Key(65535)) {
+        Err(crate::IconError::InvalidSize { requested: 65535, max: crate::DEFAULT_MAX_SIZE }) => (),
+        _ => panic!("Expected `IconError::InvalidSize`"),
+    }
+
+    let mut png = PngSequence::new();
+    png.max_size(16);
+
+    let key = png_sequence::Key::from(32, "32/icon.png").unwrap();
+    match png.add_entry(resample::nearest, &src, key.clone()) {
+        Err(crate::IconError::InvalidSize { requested: 32, max: 16 }) => (),
+        _ => panic!("Expected `IconError::InvalidSize`"),
+    }
+
+    let src = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::new(8, 8)));
+    png.max_size(32);
+    png.add_entry(resample::nearest, &src, key).expect("Failed to add entry below the limit");
+    assert_eq!(png.len(), 1);
+}
+
+#[test]
+fn test_icns_srgb_profile() {
+    let src = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+        64,
+        64,
+        image::Rgba([200, 100, 50, 255]),
+    )));
+
+    let mut icon = Icns::with_srgb_profile();
+    icon.add_entry(resample::nearest, &src, icns::Key::Rgba16).expect("Failed");
+    icon.add_entry(resample::nearest, &src, icns::Key::Rgba64).expect("Failed");
+
+    let mut buf = Vec::new();
+    icon.write(&mut buf).expect("Failed to write icon");
+
+    let family = ::icns::IconFamily::read(buf.as_slice()).expect("Invalid ICNS");
+    let has_srgb = |ostype: &[u8; 4]| {
+        let element = family.elements.iter().find(|e| &e.ostype.0 == ostype).expect("Missing element");
+        element.data.windows(4).any(|w| w == b"sRGB")
+    };
+
+    // `icp6` is encoded as PNG, while `is32` is a legacy RGB element
+    assert!(has_srgb(b"icp6"));
+    assert!(!has_srgb(b"is32"));
+    assert_eq!(icon.encoded_len(), buf.len());
+
+    let decoded = icon.get_entry(icns::Key::Rgba64).expect("Failed to decode entry");
+    assert_eq!(decoded.get_pixel(10, 10), image::Rgba([200, 100, 50, 255]));
+
+    // The `sRGB` chunk is 13 bytes long
+    let mut plain = Icns::new();
+    plain.add_entry(resample::nearest, &src, icns::Key::Rgba16).expect("Failed");
+    plain.add_entry(resample::nearest, &src, icns::Key::Rgba64).expect("Failed");
+
+    assert_eq!(plain.encoded_len() + 13, icon.encoded_len());
+}
+
+#[test]
+fn test_icns_jpeg2000() {
+    let src = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_fn(256, 256, |x, y| {
+        image::Rgba([x as u8, y as u8, (x ^ y) as u8, 255])
+    })));
+
+    let mut icon = Icns::with_jpeg2000();
+    let mut plain = Icns::new();
+
+    for &key in &[icns::Key::Rgba32, icns::Key::Rgba256] {
+        icon.add_entry(resample::nearest, &src, key).expect("Failed");
+        plain.add_entry(resample::nearest, &src, key).expect("Failed");
+    }
+
+    let mut buf = Vec::new();
+    icon.write(&mut buf).expect("Failed to write icon");
+
+    let mut plain_buf = Vec::new();
+    plain.write(&mut plain_buf).expect("Failed to write icon");
+
+    // No JPEG 2000 encoder is available, so large entries fall back to RGBA
+    assert!(buf.len() <= plain_buf.len());
+
+    let family = ::icns::IconFamily::read(buf.as_slice()).expect("Invalid ICNS");
+    let image = family
+        .get_icon_with_type(::icns::IconType::RGBA32_256x256)
+        .expect("Failed to decode entry");
+    assert_eq!((image.width(), image.height()), (256, 256));
+
+    let decoded = icon.get_entry(icns::Key::Rgba256).expect("Failed to decode entry");
+    assert_eq!(decoded.get_pixel(10, 20), image::Rgba([10, 20, 10 ^ 20, 255]));
+    assert!(icon.get_entry(icns::Key::Rgba32).is_some());
+}
+
+#[test]
+fn test_rasterize_into() -> io::Result<()> {
+    let circle = b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"32\" height=\"32\">\
+        <circle cx=\"16\" cy=\"16\" r=\"11\" fill=\"red\" fill-opacity=\"0.5\"/></svg>";
+    let banner = b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"32\" height=\"16\">\
+        <rect width=\"32\" height=\"16\" fill=\"blue\"/></svg>";
+    let raster = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_fn(40, 40, |x, y| {
+        image::Rgba([x as u8 * 6, y as u8 * 6, 128, 255])
+    })));
+
+    let mut buf = Vec::new();
+
+    for img in &[Image::from_bytes(circle)?, Image::from_bytes(banner)?, raster] {
+        for &size in &[64, 16, 32] {
+            img.rasterize_into(resample::linear, size, &mut buf).expect("Failed to rasterize");
+
+            let expected = img.rasterize(resample::linear, size).expect("Failed").to_rgba().into_raw();
+            assert_eq!(buf.len(), (4 * size * size) as usize);
+            assert_eq!(buf, expected);
+        }
+    }
+
+    // The allocation of the largest size is reused by the smaller ones
+    assert!(buf.capacity() >= 4 * 64 * 64);
+
+    Ok(())
+}
+
+#[test]
+fn test_too_many_entries() {
+    let img = Image::from(DynamicImage::new_rgba8(32, 32));
+
+    // Uses a lowered limit, since `Ico` only supports 256 distinct sizes
+    let mut icon = Ico::new();
+    assert_eq!(icon.max_entries(), 65535);
+    icon.set_max_entries(3);
+
+    icon.add_entries(resample::nearest, &img, vec![ico::Key(16), ico::Key(24), ico::Key(32)])
+        .expect("Failed");
+
+    match icon.add_entry(resample::nearest, &img, ico::Key(48)) {
+        Err(crate::IconError::TooManyEntries(3)) => (),
+        _ => panic!("Expected `IconError::TooManyEntries`"),
+    }
+    assert_eq!(icon.len(), 3);
+
+    let mut other = Ico::new();
+    other.add_entries(resample::nearest, &img, vec![ico::Key(48), ico::Key(64)]).expect("Failed");
+
+    let mut merged = Ico::new();
+    merged.set_max_entries(4);
+    merged.add_entries(resample::nearest, &img, vec![ico::Key(16), ico::Key(24)]).expect("Failed");
+    merged.merge(&other).expect("Failed");
+    assert_eq!(merged.len(), 4);
+
+    match icon.merge(&other) {
+        Err(crate::IconError::TooManyEntries(3)) => (),
+        _ => panic!("Expected `IconError::TooManyEntries`"),
+    }
+    assert_eq!(icon.len(), 3);
+
+    icon.set_max_entries(100_000);
+    assert_eq!(icon.max_entries(), 65535);
+}
+
+#[test]
+fn test_ico_dither() {
+    let gradient = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, y| {
+        let alpha = if x < 4 && y < 4 { 0 } else { 255 };
+        image::Rgba([x as u8 * 4, x as u8 * 4, 255 - x as u8 * 2, alpha])
+    })));
+
+    // Counts the horizontally and vertically adjacent pixels of different colors
+    let variation = |dither: bool| {
+        let mut icon = Ico::with_encoding(ico::IcoEncoding::AllBmp);
+        icon.set_bit_depth(ico::BitDepth::Four).set_dither(dither);
+        icon.add_entry(resample::nearest, &gradient, ico::Key(64)).expect("Failed");
+
+        let entry = icon.get_entry(ico::Key(64)).expect("Failed to decode entry").to_rgba();
+        let mut count = 0;
+
+        for (x, y, pixel) in entry.enumerate_pixels() {
+            // The alpha channel is still reduced to a 1-bit mask
+            assert_eq!(pixel[3], if x < 4 && y < 4 { 0 } else { 255 });
+
+            if x + 1 < 64 && entry.get_pixel(x + 1, y) != pixel {
+                count += 1;
+            }
+
+            if y + 1 < 64 && entry.get_pixel(x, y + 1) != pixel {
+                count += 1;
+            }
+        }
+
+        count
+    };
+
+    let (banded, dithered) = (variation(false), variation(true));
+    assert!(dithered > banded, "{} <= {}", dithered, banded);
+}
+
+#[test]
+fn test_clear() {
+    let src = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+        32,
+        32,
+        image::Rgba([0, 128, 255, 255]),
+    )));
+
+    let mut ico = Ico::with_capacity(10);
+    ico.add_entries(resample::nearest, &src, vec![ico::Key(16), ico::Key(32)]).expect("Failed");
+    ico.clear();
+
+    assert_eq!(ico.len(), 0);
+    assert!(ico.capacity() >= 10);
+    ico.add_entries(resample::nearest, &src, vec![ico::Key(16), ico::Key(32)]).expect("Failed to re-add entries");
+    assert_eq!(ico.len(), 2);
+
+    let mut icns = Icns::new();
+    icns.add_entry(resample::nearest, &src, icns::Key::Rgba16).expect("Failed");
+    icns.clear();
+
+    assert_eq!(icns.len(), 0);
+    assert_eq!(icns.encoded_len(), Icns::new().encoded_len());
+    icns.add_entry(resample::nearest, &src, icns::Key::Rgba16).expect("Failed to re-add entry");
+
+    let mut fav = Favicon::new();
+    fav.add_entry(resample::nearest, &src, favicon::Key(16)).expect("Failed");
+    fav.clear();
+
+    assert_eq!(fav.len(), 0);
+    fav.add_entry(resample::nearest, &src, favicon::Key(16)).expect("Failed to re-add entry");
+
+    let key = png_sequence::Key::from(16, "16/icon.png").unwrap();
+    let mut png = PngSequence::new();
+    png.add_entry(resample::nearest, &src, key.clone()).expect("Failed");
+    png.clear();
+
+    assert_eq!(png.len(), 0);
+    png.add_entry(resample::nearest, &src, key).expect("Failed to re-add entry");
+}
+
+#[test]
+fn test_icns_info() {
+    let src = Image::from(DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+        32,
+        32,
+        image::Rgba([0, 128, 255, 255]),
+    )));
+
+    let mut icon = Icns::new();
+    icon.add_entry(resample::nearest, &src, icns::Key::Rgba32).expect("Failed");
+    icon.set_info("Version", "1.0");
+    icon.set_info("Version", "1.1");
+    icon.set_info("Build", "<nightly> & co");
+
+    let mut buf = Vec::new();
+    icon.write(&mut buf).expect("Failed to write icon");
+    assert_eq!(icon.encoded_len(), buf.len());
+
+    let family = ::icns::IconFamily::read(buf.as_slice()).expect("Invalid ICNS");
+    let infos: Vec<_> = family.elements.iter().filter(|e| &e.ostype.0 == b"info").collect();
+    assert_eq!(infos.len(), 1);
+
+    let plist = String::from_utf8(infos[0].data.clone()).expect("Invalid UTF-8");
+    assert!(plist.starts_with("<?xml"));
+    assert!(plist.contains("<key>Version</key>\n\t<string>1.1</string>"));
+    assert!(plist.contains("<key>Build</key>\n\t<string>&lt;nightly&gt; &amp; co</string>"));
+    assert!(!plist.contains("<string>1.0</string>"));
+
+    // The entries are still readable
+    let image = family.get_icon_with_type(::icns::IconType::RGB24_32x32).expect("Missing entry");
+    assert_eq!(image.width(), 32);
+
+    // The metadata survives `Icon::clear`, but is not merged
+    icon.clear();
+    assert_eq!(icon.len(), 0);
+
+    let mut other = Icns::new();
+    other.merge(&icon).expect("Failed to merge");
+    assert_eq!(other, Icns::new());
+    assert_ne!(icon, Icns::new());
+}
+
+#[test]
+fn test_error_source() {
+    use std::error::Error;
+
+    let img = Image::from(DynamicImage::new_rgba8(64, 64));
+    let failing = |_: &DynamicImage, _: u32| -> io::Result<DynamicImage> {
+        Err(io::Error::new(io::ErrorKind::Other, "filter failed"))
+    };
+    let mismatched = |_: &DynamicImage, _: u32| Ok(DynamicImage::new_rgba8(3, 3));
+
+    let mut icon = Ico::new();
+
+    // Wrapped I/O errors are exposed as the source of the error
+    let err = icon.add_entry(failing, &img, ico::Key(32)).expect_err("Should fail");
+    let source = err.source().expect("Missing source");
+    assert_eq!(source.downcast_ref::<io::Error>().map(io::Error::kind), Some(io::ErrorKind::Other));
+
+    let err = icon.add_entries(failing, &img, vec![ico::Key(32)]).expect_err("Should fail");
+    let io_err = err.source().and_then(Error::source).expect("Missing source");
+    assert_eq!(io_err.to_string(), "filter failed");
+
+    // Errors raised by iconwriter itself have no source
+    let err = icon.add_entry(mismatched, &img, ico::Key(32)).expect_err("Should fail");
+    assert!(err.source().is_none());
+
+    icon.add_entry(resample::nearest, &img, ico::Key(32)).expect("Failed");
+    let err = icon.add_entry(resample::nearest, &img, ico::Key(32)).expect_err("Should fail");
+    assert!(err.source().is_none());
+
+    // `IconError` can be propagated as a boxed error
+    fn boxed(icon: &mut Ico, img: &Image) -> Result<(), Box<dyn Error + Send + Sync>> {
+        icon.add_entry(resample::nearest, img, ico::Key(32))?;
+        Ok(())
+    }
+
+    assert!(boxed(&mut icon, &img).is_err());
+}
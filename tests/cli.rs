@@ -0,0 +1,91 @@
+// The command line interface requires the `std-fs` feature
+#![cfg(feature = "std-fs")]
+
+extern crate ico;
+extern crate image;
+
+use std::{
+    env,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+fn iconwriter(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_iconwriter"))
+        .args(args)
+        .output()
+        .expect("Failed to run the binary")
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("iconwriter_cli_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).expect("Failed to create the temporary directory");
+
+    dir
+}
+
+fn source_svg(dir: &Path) -> PathBuf {
+    let path = dir.join("input.svg");
+    let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"64\" height=\"64\">\
+        <rect width=\"64\" height=\"64\" fill=\"red\"/></svg>";
+
+    fs::write(&path, svg).expect("Failed to write the source image");
+    path
+}
+
+#[test]
+fn test_cli_ico() {
+    let dir = temp_dir("ico");
+    let input = source_svg(&dir);
+    let output = dir.join("out.ico");
+
+    let result = iconwriter(&[
+        output.to_str().unwrap(),
+        "--size", "16,32,48",
+        "--filter", "lanczos3",
+        input.to_str().unwrap(),
+    ]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+
+    let icon_dir = ico::IconDir::read(File::open(&output).expect("Missing output"))
+        .expect("Invalid ICO file");
+    let sizes: Vec<u32> = icon_dir.entries().iter().map(|entry| entry.width()).collect();
+    assert_eq!(sizes, vec![16, 32, 48]);
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_cli_favicon_dir() {
+    let dir = temp_dir("favicon");
+    let input = source_svg(&dir);
+    let output = dir.join("favicon");
+
+    let result = iconwriter(&[output.to_str().unwrap(), "-s", "16,32", input.to_str().unwrap()]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    assert!(output.join("helper.html").exists());
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_cli_errors() {
+    let dir = temp_dir("errors");
+    let input = source_svg(&dir);
+    let input = input.to_str().unwrap();
+    let ico = dir.join("out.ico");
+    let icns = dir.join("out.icns");
+
+    // Usage errors
+    assert_eq!(iconwriter(&[]).status.code(), Some(2));
+    assert_eq!(iconwriter(&[ico.to_str().unwrap(), "--filter", "foo", input]).status.code(), Some(2));
+    assert_eq!(iconwriter(&[ico.to_str().unwrap(), "--size", "16,x", input]).status.code(), Some(2));
+
+    // Runtime errors
+    let missing = dir.join("missing.png");
+    assert_eq!(iconwriter(&[ico.to_str().unwrap(), missing.to_str().unwrap()]).status.code(), Some(1));
+    assert_eq!(iconwriter(&[icns.to_str().unwrap(), "--size", "48", input]).status.code(), Some(1));
+
+    fs::remove_dir_all(&dir).ok();
+}